@@ -0,0 +1,99 @@
+/*
+ *  Copyright 2019 Gregory Meyer
+ *
+ *  Permission is hereby granted, free of charge, to any person
+ *  obtaining a copy of this software and associated documentation
+ *  files (the "Software"), to deal in the Software without
+ *  restriction, including without limitation the rights to use, copy,
+ *  modify, merge, publish, distribute, sublicense, and/or sell copies
+ *  of the Software, and to permit persons to whom the Software is
+ *  furnished to do so, subject to the following conditions:
+ *
+ *  The above copyright notice and this permission notice shall be
+ *  included in all copies or substantial portions of the Software.
+ *
+ *  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+ *  EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+ *  MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ *  NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+ *  BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+ *  ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ *  CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  SOFTWARE.
+ */
+
+#ifndef SRM_CAPNP_HPP
+#define SRM_CAPNP_HPP
+
+/* typed helpers for publishing and subscribing to capnp structs compiled by capnpc-c++; T is the
+ * struct type, e.g. Message for `struct Message` */
+
+#include <srm/node.hpp>
+
+#include <cassert>
+#include <cstddef>
+#include <string>
+#include <vector>
+
+#include <capnp/message.h>
+
+namespace srm {
+
+/* allocates segments from the core through an SrmMsgBuilder */
+class RemoteMessageBuilder : public capnp::MessageBuilder {
+public:
+    explicit RemoteMessageBuilder(SrmMsgBuilder builder) noexcept : builder_(builder) { }
+
+    kj::ArrayPtr<capnp::word> allocateSegment(capnp::uint minimum_size) override {
+        SrmMsgSegment segment{nullptr, static_cast<SrmIndex>(minimum_size)};
+
+        const int err = builder_.vptr->alloc_segment(builder_.impl_ptr, &segment);
+
+        if (err != 0) {
+            throw Error(err, builder_.vptr->get_err_msg(builder_.impl_ptr, err));
+        }
+
+        return {reinterpret_cast<capnp::word*>(segment.data),
+                static_cast<std::size_t>(segment.len)};
+    }
+
+private:
+    SrmMsgBuilder builder_;
+};
+
+template <typename T>
+Publisher advertise(const Core &core, const std::string &topic) {
+    return core.advertise(capnp::typeId<T>(), topic);
+}
+
+/* build is called with a T::Builder for the message, which is published once build returns */
+template <typename T, typename F>
+void publish(Publisher &publisher, F &&build) {
+    assert(publisher.msg_type() == capnp::typeId<T>());
+
+    publisher.publish([&build](SrmMsgBuilder raw_builder) {
+        RemoteMessageBuilder builder(raw_builder);
+        build(builder.initRoot<T>());
+    });
+}
+
+/* callback is called with a T::Reader for each message published on topic */
+template <typename T, typename F>
+Subscriber subscribe(const Core &core, const std::string &topic, F callback) {
+    return core.subscribe(capnp::typeId<T>(), topic, [callback](SrmMsgView msg) {
+        std::vector<kj::ArrayPtr<const capnp::word>> segments;
+        segments.reserve(static_cast<std::size_t>(msg.num_segments));
+
+        for (SrmIndex i = 0; i < msg.num_segments; ++i) {
+            segments.emplace_back(reinterpret_cast<const capnp::word*>(msg.segments[i].data),
+                                  static_cast<std::size_t>(msg.segments[i].len));
+        }
+
+        capnp::SegmentArrayMessageReader reader({segments.data(), segments.size()});
+        callback(reader.getRoot<T>());
+    });
+}
+
+} // namespace srm
+
+#endif
@@ -50,6 +50,26 @@ struct SrmSubscriber {
     const SrmSubscriberVtbl *vptr;
 };
 
+struct SrmSynchronizer {
+    void *impl_ptr;
+    const SrmSynchronizerVtbl *vptr;
+};
+
+struct SrmTimer {
+    void *impl_ptr;
+    const SrmTimerVtbl *vptr;
+};
+
+struct SrmParamWatcher {
+    void *impl_ptr;
+    const SrmParamWatcherVtbl *vptr;
+};
+
+/* callbacks in one callback group never run concurrently; a message for a callback whose group is
+ * busy is queued until the group is free, or dropped and counted as a failure if the group has
+ * SRM_MAX_DEFERRED_CALLBACKS queued already */
+#define SRM_MAX_DEFERRED_CALLBACKS 256
+
 struct SrmSubscribeParams {
     SrmMsgType msg_type;
     SrmStrView topic;
@@ -60,6 +80,83 @@ struct SrmSubscribeParams {
 struct SrmAdvertiseParams {
     SrmMsgType msg_type;
     SrmStrView topic;
+    SrmIndex size_hint; /* expected message size in bytes; 0 for the default */
+    /* if nonzero, publish returns before subscribers are invoked; messages from one publisher
+     * are still delivered in order. if zero, publish returns once each subscriber has been invoked
+     * or queued behind its busy callback group, and queued subscribers are not waited for */
+    int nonblocking;
+    /* nonblocking only; 0 for the default. a message is in flight until on_complete would be
+     * called for it */
+    SrmIndex max_in_flight;
+    /* nonblocking only; may be NULL. called once every subscriber has returned, including those
+     * queued behind a busy callback group, with the number that failed or were dropped */
+    SrmPublishCompleteCallback on_complete;
+    void *on_complete_arg;
+};
+
+typedef enum SrmSyncPolicy {
+    SRM_SYNC_EXACT, /* header stamps must be equal */
+    SRM_SYNC_APPROXIMATE /* header stamps must be within slop of each other */
+} SrmSyncPolicy;
+
+struct SrmSyncTopic {
+    SrmMsgType msg_type;
+    SrmStrView topic;
+};
+
+struct SrmSyncParams {
+    const SrmSyncTopic *topics;
+    SrmIndex num_topics;
+    int policy; /* an SrmSyncPolicy */
+    SrmDuration slop; /* ignored for SRM_SYNC_EXACT */
+    SrmIndex queue_size; /* messages buffered per topic */
+    SrmSyncCallback callback;
+    void *arg;
+};
+
+struct SrmTimerParams {
+    SrmDuration period; /* must be positive */
+    int oneshot;
+    SrmTimerCallback callback;
+    void *arg;
+};
+
+typedef enum SrmLogLevel {
+    SRM_LOG_ERROR = 1,
+    SRM_LOG_WARN,
+    SRM_LOG_INFO,
+    SRM_LOG_DEBUG,
+    SRM_LOG_TRACE
+} SrmLogLevel;
+
+struct SrmLogField {
+    SrmStrView key;
+    SrmStrView value;
+};
+
+/* the core publishes Event messages from capnp/events.capnp here, asynchronously and in order;
+ * nodes may not advertise on it */
+#define SRM_EVENTS_TOPIC "/srm/events"
+
+/* if the node graph sets publish_logs, the core publishes LogRecord messages from capnp/log.capnp
+ * here, asynchronously and in order; records are dropped if too many are in flight, and records
+ * logged by its subscribers' callbacks aren't published; nodes may not advertise on it */
+#define SRM_LOG_TOPIC "/srm/log"
+
+/* upper bounds of the callback duration histogram, in ns: 1us, 10us, 100us, 1ms, 10ms, 100ms,
+ * 1s and unbounded */
+#define SRM_NUM_LATENCY_BUCKETS 8
+
+struct SrmTopicStats {
+    SrmMsgType msg_type;
+    uint64_t num_messages;
+    uint64_t num_bytes; /* message contents, excluding unused segment space */
+    uint64_t num_failures; /* failed callback invocations */
+    double message_rate; /* messages per second since the channel was created */
+    SrmIndex num_subscribers;
+    uint64_t num_callbacks; /* summed over all subscribers */
+    uint64_t total_callback_duration; /* nanoseconds, summed over all subscribers */
+    uint64_t latency_buckets[SRM_NUM_LATENCY_BUCKETS]; /* not cumulative */
 };
 
 typedef enum SrmParamType {
@@ -69,37 +166,94 @@ typedef enum SrmParamType {
     SRM_STRING
 } SrmParamType;
 
+union SrmParamValue {
+    ptrdiff_t integer;
+    int boolean;
+    double real;
+    SrmStrView string;
+};
+
+struct SrmParamView {
+    int type; /* one of SrmParamType */
+    SrmParamValue value;
+};
+
+struct SrmParamAssignment {
+    SrmStrView key;
+    SrmParamView value;
+};
+
+struct SrmParamDecl {
+    SrmStrView key;
+    SrmParamView default_value; /* also determines the type of the parameter */
+    SrmStrView description;
+    const double *min; /* NULL if unbounded */
+    const double *max; /* NULL if unbounded */
+    const SrmParamView *allowed_values; /* NULL if any value is allowed */
+    SrmIndex num_allowed_values;
+    int read_only;
+};
+
 struct SrmCoreVtbl {
     SrmStrView (*get_type)(const void*);
 
     int (*subscribe)(const void*, SrmSubscribeParams, SrmSubscriber*);
     int (*advertise)(const void*, SrmAdvertiseParams, SrmPublisher*);
+    int (*synchronize)(const void*, SrmSyncParams, SrmSynchronizer*);
+    int (*get_topic_stats)(const void*, SrmStrView, SrmTopicStats*);
 
     SrmStrView (*get_err_msg)(const void*, int);
 
+    /* sim time published on /clock if the .use_sim_time param is true, else wall clock time */
+    SrmTime (*now)(const void*);
+    int (*create_timer)(const void*, SrmTimerParams, SrmTimer*);
+
     int (*log_error)(const void*, SrmStrView);
     int (*log_warn)(const void*, SrmStrView);
     int (*log_info)(const void*, SrmStrView);
     int (*log_debug)(const void*, SrmStrView);
     int (*log_trace)(const void*, SrmStrView);
+    /* level is an SrmLogLevel; fields are attached to the record as key-value pairs */
+    int (*log)(const void*, int, SrmStrView, const SrmLogField*, SrmIndex);
+    /* logs msg unless a record with the same key was logged by this node less than period ago;
+     * the next record logged carries the number suppressed in between as a field */
+    int (*log_throttled)(const void*, int, SrmStrView, SrmDuration, SrmStrView);
+    /* logs msg only the first time this node logs with key */
+    int (*log_once)(const void*, int, SrmStrView, SrmStrView);
 
     int (*param_type)(const void*, SrmStrView, int*);
 
     int (*param_seti)(const void*, SrmStrView, ptrdiff_t);
     int (*param_geti)(const void*, SrmStrView, ptrdiff_t*);
     int (*param_swapi)(const void*, SrmStrView, ptrdiff_t, ptrdiff_t*);
+    int (*param_compare_swapi)(const void*, SrmStrView, ptrdiff_t, ptrdiff_t, int*);
 
     int (*param_setb)(const void*, SrmStrView, int);
     int (*param_getb)(const void*, SrmStrView, int*);
     int (*param_swapb)(const void*, SrmStrView, int, int*);
+    int (*param_compare_swapb)(const void*, SrmStrView, int, int, int*);
 
     int (*param_setr)(const void*, SrmStrView, double);
     int (*param_getr)(const void*, SrmStrView, double*);
     int (*param_swapr)(const void*, SrmStrView, double, double*);
+    int (*param_compare_swapr)(const void*, SrmStrView, double, double, int*);
 
     int (*param_sets)(const void*, SrmStrView, SrmStrView);
     int (*param_gets)(const void*, SrmStrView, SrmString*);
     int (*param_swaps)(const void*, SrmStrView, SrmStrView, SrmString*);
+    int (*param_compare_swaps)(const void*, SrmStrView, SrmStrView, SrmStrView, int*);
+
+    int (*param_transaction)(const void*, const SrmParamAssignment*, SrmIndex);
+
+    /* an empty prefix lists all parameters */
+    int (*param_list)(const void*, SrmStrView, SrmParamListCallback, void*);
+    int (*param_delete)(const void*, SrmStrView);
+
+    /* an empty prefix dumps all parameters */
+    int (*param_dump)(const void*, SrmStrView, SrmStrView);
+
+    int (*param_declare)(const void*, SrmParamDecl);
+    int (*param_watch)(const void*, SrmStrView, SrmParamWatchCallback, void*, SrmParamWatcher*);
 };
 
 struct SrmSubscriberVtbl {
@@ -109,6 +263,25 @@ struct SrmSubscriberVtbl {
     SrmStrView (*get_err_msg)(const void*, int);
 };
 
+struct SrmSynchronizerVtbl {
+    SrmIndex (*get_num_topics)(const void*);
+    int (*disconnect)(void*);
+    SrmStrView (*get_err_msg)(const void*, int);
+};
+
+struct SrmTimerVtbl {
+    SrmDuration (*get_period)(const void*);
+    /* frees the timer; blocks until a running callback returns */
+    int (*cancel)(void*);
+    SrmStrView (*get_err_msg)(const void*, int);
+};
+
+struct SrmParamWatcherVtbl {
+    SrmStrView (*get_key)(const void*);
+    int (*disconnect)(void*);
+    SrmStrView (*get_err_msg)(const void*, int);
+};
+
 struct SrmPublisherVtbl {
     SrmStrView (*get_channel_name)(const void*);
     SrmMsgType (*get_channel_type)(const void*);
@@ -45,10 +45,19 @@ struct SrmMsgSegmentView {
     SrmIndex len;
 };
 
+/* attached to each message by the core when it is published */
+struct SrmMsgHeader {
+    SrmTime stamp; /* when the message was published */
+    uint64_t sequence; /* counts up from zero for each publisher */
+    uint64_t publisher_id; /* unique among all publishers of a core */
+    SrmStrView publisher_name; /* name of the node that advertised the publisher */
+};
+
 struct SrmMsgView {
     const SrmMsgSegmentView *segments;
     SrmIndex num_segments;
     SrmMsgType msg_type;
+    SrmMsgHeader header;
 };
 
 struct SrmMsgBuilder {
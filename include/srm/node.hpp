@@ -0,0 +1,505 @@
+/*
+ *  Copyright 2019 Gregory Meyer
+ *
+ *  Permission is hereby granted, free of charge, to any person
+ *  obtaining a copy of this software and associated documentation
+ *  files (the "Software"), to deal in the Software without
+ *  restriction, including without limitation the rights to use, copy,
+ *  modify, merge, publish, distribute, sublicense, and/or sell copies
+ *  of the Software, and to permit persons to whom the Software is
+ *  furnished to do so, subject to the following conditions:
+ *
+ *  The above copyright notice and this permission notice shall be
+ *  included in all copies or substantial portions of the Software.
+ *
+ *  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+ *  EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+ *  MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+ *  NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+ *  BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+ *  ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+ *  CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ *  SOFTWARE.
+ */
+
+#ifndef SRM_NODE_HPP
+#define SRM_NODE_HPP
+
+/* RAII wrappers for writing nodes in C++14 without touching vtables; see srm/capnp.hpp for
+ * typed publish and subscribe helpers */
+
+#include <srm/core.h>
+#include <srm/msg.h>
+#include <srm/node.h>
+#include <srm/types.h>
+#include <srm/util.h>
+
+#include <chrono>
+#include <cstddef>
+#include <cstring>
+#include <deque>
+#include <exception>
+#include <functional>
+#include <memory>
+#include <mutex>
+#include <new>
+#include <stdexcept>
+#include <string>
+#include <utility>
+
+namespace srm {
+
+inline SrmStrView as_view(const std::string &s) noexcept {
+    return SrmStrView{s.data(), static_cast<SrmIndex>(s.size())};
+}
+
+inline SrmStrView as_view(const char *s) noexcept {
+    return SrmStrView{s, static_cast<SrmIndex>(std::strlen(s))};
+}
+
+inline std::string to_string(SrmStrView view) {
+    if (!view.data) {
+        return std::string();
+    }
+
+    return std::string(view.data, static_cast<std::string::size_type>(view.len));
+}
+
+/* thrown when the core or one of its objects returns nonzero */
+class Error : public std::runtime_error {
+public:
+    Error(int code, SrmStrView what) : std::runtime_error(to_string(what)), code_(code) { }
+
+    int code() const noexcept {
+        return code_;
+    }
+
+private:
+    int code_;
+};
+
+enum class LogLevel : int {
+    Error = SRM_LOG_ERROR,
+    Warn = SRM_LOG_WARN,
+    Info = SRM_LOG_INFO,
+    Debug = SRM_LOG_DEBUG,
+    Trace = SRM_LOG_TRACE
+};
+
+class Publisher;
+class Subscriber;
+
+/* copyable view of the core, valid for the lifetime of the node it was passed to */
+class Core {
+public:
+    explicit Core(SrmCore core) noexcept : core_(core) { }
+
+    SrmCore as_ffi() const noexcept {
+        return core_;
+    }
+
+    Publisher advertise(SrmMsgType msg_type, const std::string &topic) const;
+
+    /* callback may be invoked from any thread until the Subscriber is destroyed; exceptions it
+     * throws are logged and count the message as failed */
+    Subscriber subscribe(SrmMsgType msg_type, const std::string &topic,
+                         std::function<void(SrmMsgView)> callback) const;
+
+    SrmTopicStats topic_stats(const std::string &topic) const {
+        SrmTopicStats stats;
+        check(core_.vptr->get_topic_stats(core_.impl_ptr, as_view(topic), &stats));
+
+        return stats;
+    }
+
+    /* sim time published on /clock if the .use_sim_time param is true, else wall clock time */
+    SrmTime now() const noexcept {
+        return core_.vptr->now(core_.impl_ptr);
+    }
+
+    void log(LogLevel level, const std::string &msg) const {
+        check(core_.vptr->log(core_.impl_ptr, static_cast<int>(level), as_view(msg), nullptr, 0));
+    }
+
+    void log_throttled(LogLevel level, const std::string &key, std::chrono::nanoseconds period,
+                       const std::string &msg) const {
+        check(core_.vptr->log_throttled(core_.impl_ptr, static_cast<int>(level), as_view(key),
+                                        static_cast<SrmDuration>(period.count()), as_view(msg)));
+    }
+
+    void log_once(LogLevel level, const std::string &key, const std::string &msg) const {
+        check(core_.vptr->log_once(core_.impl_ptr, static_cast<int>(level), as_view(key),
+                                   as_view(msg)));
+    }
+
+    std::ptrdiff_t param_geti(const std::string &key) const {
+        std::ptrdiff_t value;
+        check(core_.vptr->param_geti(core_.impl_ptr, as_view(key), &value));
+
+        return value;
+    }
+
+    bool param_getb(const std::string &key) const {
+        int value;
+        check(core_.vptr->param_getb(core_.impl_ptr, as_view(key), &value));
+
+        return value != 0;
+    }
+
+    double param_getr(const std::string &key) const {
+        double value;
+        check(core_.vptr->param_getr(core_.impl_ptr, as_view(key), &value));
+
+        return value;
+    }
+
+    std::string param_gets(const std::string &key) const {
+        SrmString value;
+        check(core_.vptr->param_gets(core_.impl_ptr, as_view(key), &value));
+
+        struct Dropper {
+            SrmString &s;
+
+            ~Dropper() {
+                if (s.drop) {
+                    s.drop(s.data, s.capacity, s.drop_arg);
+                }
+            }
+        } dropper{value};
+
+        return value.data ? std::string(value.data, static_cast<std::size_t>(value.len))
+                          : std::string();
+    }
+
+    void param_seti(const std::string &key, std::ptrdiff_t value) const {
+        check(core_.vptr->param_seti(core_.impl_ptr, as_view(key), value));
+    }
+
+    void param_setb(const std::string &key, bool value) const {
+        check(core_.vptr->param_setb(core_.impl_ptr, as_view(key), value ? 1 : 0));
+    }
+
+    void param_setr(const std::string &key, double value) const {
+        check(core_.vptr->param_setr(core_.impl_ptr, as_view(key), value));
+    }
+
+    void param_sets(const std::string &key, const std::string &value) const {
+        check(core_.vptr->param_sets(core_.impl_ptr, as_view(key), as_view(value)));
+    }
+
+    void check(int err) const {
+        if (err != 0) {
+            throw Error(err, core_.vptr->get_err_msg(core_.impl_ptr, err));
+        }
+    }
+
+private:
+    SrmCore core_;
+};
+
+/* owns an SrmPublisher; disconnects when destroyed */
+class Publisher {
+public:
+    explicit Publisher(SrmPublisher publisher) noexcept : publisher_(publisher) { }
+
+    Publisher(const Publisher&) = delete;
+
+    Publisher(Publisher &&other) noexcept : publisher_(other.publisher_) {
+        other.publisher_.impl_ptr = nullptr;
+    }
+
+    Publisher& operator=(Publisher other) noexcept {
+        std::swap(publisher_, other.publisher_);
+
+        return *this;
+    }
+
+    ~Publisher() {
+        if (publisher_.impl_ptr) {
+            publisher_.vptr->disconnect(publisher_.impl_ptr);
+        }
+    }
+
+    std::string topic() const {
+        return to_string(publisher_.vptr->get_channel_name(publisher_.impl_ptr));
+    }
+
+    SrmMsgType msg_type() const noexcept {
+        return publisher_.vptr->get_channel_type(publisher_.impl_ptr);
+    }
+
+    /* build is called with an SrmMsgBuilder that allocates from the core before the message is
+     * published; exceptions it throws are rethrown and nothing is published */
+    template <typename F>
+    void publish(F &&build) {
+        PublishState<F> state{build, nullptr};
+
+        const int err =
+            publisher_.vptr->publish(publisher_.impl_ptr, publish_entry<F>, &state);
+
+        if (state.exception) {
+            std::rethrow_exception(state.exception);
+        } else if (err != 0) {
+            throw Error(err, publisher_.vptr->get_err_msg(publisher_.impl_ptr, err));
+        }
+    }
+
+private:
+    template <typename F>
+    struct PublishState {
+        F &build;
+        std::exception_ptr exception;
+    };
+
+    template <typename F>
+    static int publish_entry(SrmMsgBuilder builder, void *arg) noexcept {
+        PublishState<F> &state = *static_cast<PublishState<F>*>(arg);
+
+        try {
+            state.build(builder);
+        } catch (...) {
+            state.exception = std::current_exception();
+
+            return 1;
+        }
+
+        return 0;
+    }
+
+    SrmPublisher publisher_;
+};
+
+/* owns an SrmSubscriber and its callback; disconnects when destroyed */
+class Subscriber {
+public:
+    Subscriber(Core core, SrmMsgType msg_type, const std::string &topic,
+               std::function<void(SrmMsgView)> callback)
+    : state_(new State{core, topic, std::move(callback)}) {
+        SrmSubscribeParams params;
+        params.msg_type = msg_type;
+        params.topic = as_view(state_->topic);
+        params.callback = callback_entry;
+        params.arg = state_.get();
+
+        SrmCore raw = core.as_ffi();
+        core.check(raw.vptr->subscribe(raw.impl_ptr, params, &subscriber_));
+    }
+
+    Subscriber(const Subscriber&) = delete;
+
+    Subscriber(Subscriber &&other) noexcept
+    : subscriber_(other.subscriber_), state_(std::move(other.state_)) {
+        other.subscriber_.impl_ptr = nullptr;
+    }
+
+    Subscriber& operator=(Subscriber other) noexcept {
+        std::swap(subscriber_, other.subscriber_);
+        std::swap(state_, other.state_);
+
+        return *this;
+    }
+
+    /* disconnects before the callback is destroyed, so that it can't be invoked afterwards */
+    ~Subscriber() {
+        if (subscriber_.impl_ptr) {
+            subscriber_.vptr->disconnect(subscriber_.impl_ptr);
+        }
+    }
+
+    const std::string& topic() const noexcept {
+        return state_->topic;
+    }
+
+private:
+    struct State {
+        Core core;
+        std::string topic;
+        std::function<void(SrmMsgView)> callback;
+    };
+
+    static int callback_entry(SrmMsgView msg, void *arg) noexcept {
+        State &state = *static_cast<State*>(arg);
+
+        try {
+            state.callback(msg);
+
+            return 0;
+        } catch (const std::exception &e) {
+            log_failure(state, e.what());
+        } catch (...) {
+            log_failure(state, "unknown exception");
+        }
+
+        return 1;
+    }
+
+    static void log_failure(const State &state, const char *what) noexcept {
+        try {
+            state.core.log(LogLevel::Warn,
+                           "couldn't handle message on '" + state.topic + "': " + what);
+        } catch (...) { }
+    }
+
+    SrmSubscriber subscriber_ = SrmSubscriber{nullptr, nullptr};
+    std::unique_ptr<State> state_;
+};
+
+inline Publisher Core::advertise(SrmMsgType msg_type, const std::string &topic) const {
+    SrmAdvertiseParams params;
+    params.msg_type = msg_type;
+    params.topic = as_view(topic);
+    params.size_hint = 0;
+    params.nonblocking = 0;
+    params.max_in_flight = 0;
+    params.on_complete = nullptr;
+    params.on_complete_arg = nullptr;
+
+    SrmPublisher publisher;
+    check(core_.vptr->advertise(core_.impl_ptr, params, &publisher));
+
+    return Publisher(publisher);
+}
+
+inline Subscriber Core::subscribe(SrmMsgType msg_type, const std::string &topic,
+                                  std::function<void(SrmMsgView)> callback) const {
+    return Subscriber(*this, msg_type, topic, std::move(callback));
+}
+
+/* base class of nodes exported with SRM_REGISTER_NODE, which must be constructible from
+ * (srm::Core, std::string name); run and stop are called concurrently from different threads */
+class Node {
+public:
+    Node(Core core, std::string name) : core_(core), name_(std::move(name)) { }
+
+    virtual ~Node() = default;
+
+    /* should not return until stop is called */
+    virtual void run() = 0;
+
+    /* should not block */
+    virtual void stop() = 0;
+
+    const Core& core() const noexcept {
+        return core_;
+    }
+
+    const std::string& name() const noexcept {
+        return name_;
+    }
+
+private:
+    Core core_;
+    std::string name_;
+};
+
+namespace detail {
+
+/* what impl points to; allocated even if the node's constructor throws, so that the error can be
+ * read back through get_err_msg until the core destroys it */
+template <typename T>
+struct NodeBox {
+    std::unique_ptr<T> node;
+    std::mutex mutex;
+    std::deque<std::string> errors; /* the message for code x is at x - 1 */
+
+    template <typename F>
+    int invoke(F &&f) noexcept {
+        try {
+            f();
+
+            return 0;
+        } catch (const std::exception &e) {
+            return push_error(e.what());
+        } catch (...) {
+            return push_error("unknown exception");
+        }
+    }
+
+    int push_error(const char *what) noexcept {
+        try {
+            const std::lock_guard<std::mutex> guard(mutex);
+
+            /* the core doesn't accept empty strings */
+            errors.emplace_back(*what ? what : "unknown error");
+
+            return static_cast<int>(errors.size());
+        } catch (...) {
+            return -1;
+        }
+    }
+};
+
+template <typename T>
+struct NodeEntry {
+    static int create(SrmCore core, SrmStrView name, void **impl) noexcept {
+        NodeBox<T> *const node_box = new (std::nothrow) NodeBox<T>();
+        *impl = node_box;
+
+        if (!node_box) {
+            return -1;
+        }
+
+        return node_box->invoke([node_box, core, name] {
+            node_box->node.reset(new T(Core(core), to_string(name)));
+        });
+    }
+
+    static int destroy(void *impl) noexcept {
+        delete static_cast<NodeBox<T>*>(impl);
+
+        return 0;
+    }
+
+    static int run(void *impl) noexcept {
+        NodeBox<T> &node_box = *static_cast<NodeBox<T>*>(impl);
+
+        return node_box.invoke([&node_box] { node_box.node->run(); });
+    }
+
+    static int stop(void *impl) noexcept {
+        NodeBox<T> &node_box = *static_cast<NodeBox<T>*>(impl);
+
+        return node_box.invoke([&node_box] { node_box.node->stop(); });
+    }
+
+    static SrmStrView get_err_msg(const void *impl, int err) noexcept {
+        if (!impl || err < 0) {
+            return as_view("out of memory");
+        }
+
+        NodeBox<T> &node_box = *static_cast<NodeBox<T>*>(const_cast<void*>(impl));
+        const std::lock_guard<std::mutex> guard(node_box.mutex);
+
+        /* messages are never removed, so the view outlives the lock */
+        if (err == 0 || static_cast<std::size_t>(err) > node_box.errors.size()) {
+            return as_view("unknown error");
+        }
+
+        return as_view(node_box.errors[static_cast<std::size_t>(err) - 1]);
+    }
+};
+
+} // namespace detail
+} // namespace srm
+
+/* exports T, a class derived from srm::Node, as the node in this shared object; TYPE is a string
+ * literal such as "c++/publisher" */
+#define SRM_REGISTER_NODE(T, TYPE) \
+    namespace { \
+    SrmStrView srm_node_get_type(const void*) noexcept { \
+        return srm::as_view(TYPE); \
+    } \
+    } \
+    SRM_SHARED_OBJECT_EXPORT const SrmNodeVtbl* srm_Node_get_vtbl(void) { \
+        static const SrmNodeVtbl vtbl = { \
+            srm::detail::NodeEntry<T>::create, \
+            srm::detail::NodeEntry<T>::destroy, \
+            srm::detail::NodeEntry<T>::run, \
+            srm::detail::NodeEntry<T>::stop, \
+            srm_node_get_type, \
+            srm::detail::NodeEntry<T>::get_err_msg \
+        }; \
+        return &vtbl; \
+    }
+
+#endif
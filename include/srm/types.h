@@ -35,6 +35,8 @@ extern "C" {
 typedef uint64_t SrmMsgType;
 typedef uint64_t SrmWord;
 typedef ptrdiff_t SrmIndex;
+typedef int64_t SrmTime; /* nanoseconds since the UNIX epoch */
+typedef int64_t SrmDuration; /* nanoseconds */
 
 typedef struct SrmCore SrmCore;
 
@@ -45,6 +47,7 @@ typedef struct SrmMsgSegment SrmMsgSegment;
 typedef struct SrmMsgSegmentView SrmMsgSegmentView;
 
 typedef struct SrmMsgView SrmMsgView;
+typedef struct SrmMsgHeader SrmMsgHeader;
 
 typedef struct SrmMsgBuilder SrmMsgBuilder;
 
@@ -52,15 +55,43 @@ typedef struct SrmMsgBuilderVtbl SrmMsgBuilderVtbl;
 
 typedef int (*SrmSubscribeCallback)(SrmMsgView, void*);
 typedef int (*SrmPublishFn)(SrmMsgBuilder, void*);
+/* called with the sequence number of a message and how many subscribers failed to receive it */
+typedef int (*SrmPublishCompleteCallback)(uint64_t, SrmIndex, void*);
+typedef int (*SrmSyncCallback)(const SrmMsgView*, SrmIndex, void*); /* one message per topic */
+typedef int (*SrmTimerCallback)(SrmTime, void*); /* called with the time the timer expired */
+
+typedef union SrmParamValue SrmParamValue;
+typedef struct SrmParamView SrmParamView;
+typedef struct SrmParamAssignment SrmParamAssignment;
+typedef struct SrmParamDecl SrmParamDecl;
+
+/* key, type (one of SrmParamType), arg; returning nonzero stops enumeration */
+typedef int (*SrmParamListCallback)(SrmStrView, int, void*);
+/* key, old value (NULL if just created), new value (NULL if just deleted), arg */
+typedef int (*SrmParamWatchCallback)(SrmStrView, const SrmParamView*, const SrmParamView*, void*);
 
 typedef struct SrmSubscribeParams SrmSubscribeParams;
 typedef struct SrmAdvertiseParams SrmAdvertiseParams;
+typedef struct SrmSyncTopic SrmSyncTopic;
+typedef struct SrmSyncParams SrmSyncParams;
+typedef struct SrmTimerParams SrmTimerParams;
+typedef struct SrmTopicStats SrmTopicStats;
+typedef struct SrmLogField SrmLogField;
 
 typedef struct SrmCoreVtbl SrmCoreVtbl;
 
 typedef struct SrmPublisherVtbl SrmPublisherVtbl;
 typedef struct SrmSubscriberVtbl SrmSubscriberVtbl;
 
+typedef struct SrmSynchronizer SrmSynchronizer;
+typedef struct SrmSynchronizerVtbl SrmSynchronizerVtbl;
+
+typedef struct SrmTimer SrmTimer;
+typedef struct SrmTimerVtbl SrmTimerVtbl;
+
+typedef struct SrmParamWatcher SrmParamWatcher;
+typedef struct SrmParamWatcherVtbl SrmParamWatcherVtbl;
+
 typedef struct SrmNodeVtbl SrmNodeVtbl;
 
 typedef struct SrmString SrmString;
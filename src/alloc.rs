@@ -24,25 +24,80 @@ use super::*;
 
 use std::{
     alloc::{self, Layout},
+    cmp,
     error::Error,
     fmt::{self, Display, Formatter},
-    mem,
+    mem, ptr, slice,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
-use capnp::message::Allocator;
+use capnp::{
+    any_pointer,
+    message::{self, Allocator, ReaderOptions, SegmentArray},
+};
 use libc::c_int;
+use parking_lot::Mutex;
 
-/// Allocates cache-aligned message segments.
+/// Allocates cache-aligned message segments from a pool.
+///
+/// Like capnp's default allocator, the first segment is at least first_segment_words long and
+/// each following segment is at least as long as all previous segments combined, so the number
+/// of segments grows logarithmically with message size. Segments are returned to the pool when
+/// the allocator is dropped.
 pub struct CacheAlignedAllocator {
     segments: Vec<(*mut capnp::Word, usize)>,
+    pool: Arc<SegmentPool>,
+    next_num_words: usize,
 }
 
+/// The first segment size used by capnp's default allocator.
+pub const DEFAULT_FIRST_SEGMENT_WORDS: usize = 1024;
+
 impl CacheAlignedAllocator {
-    pub fn new() -> CacheAlignedAllocator {
+    pub fn new(pool: Arc<SegmentPool>, first_segment_words: usize) -> CacheAlignedAllocator {
         CacheAlignedAllocator {
             segments: Vec::new(),
+            pool,
+            next_num_words: first_segment_words,
+        }
+    }
+
+    /// Returns the total length of all allocated segments.
+    pub fn num_words(&self) -> usize {
+        self.segments.iter().map(|(_, sz)| sz).sum()
+    }
+
+    /// Returns the length of the message built in the allocated segments, which is usually far
+    /// less than num_words. Falls back to num_words if the message can't be read.
+    pub fn message_words(&self) -> usize {
+        let segments: Vec<&[capnp::Word]> = self
+            .segments
+            .iter()
+            .map(|&(p, sz)| unsafe { slice::from_raw_parts(p as *const capnp::Word, sz) })
+            .collect();
+
+        let options = ReaderOptions {
+            traversal_limit_in_words: self.num_words() as u64,
+            ..ReaderOptions::new()
+        };
+        let message = message::Reader::new(SegmentArray::new(&segments), options);
+
+        // the root pointer isn't counted as part of its target
+        match message
+            .get_root::<any_pointer::Reader>()
+            .and_then(|r| r.target_size())
+        {
+            Ok(size) => size.word_count as usize + 1,
+            Err(_) => self.num_words(),
         }
     }
+
+    pub fn num_segments(&self) -> usize {
+        self.segments.len()
+    }
 }
 
 unsafe impl Send for CacheAlignedAllocator {}
@@ -52,15 +107,25 @@ unsafe impl Sync for CacheAlignedAllocator {}
 unsafe impl Allocator for CacheAlignedAllocator {
     /// Allocates segments that are multiples of 16 words (128 bytes) long.
     ///
-    /// Messages are allocated using `std::alloc::alloc_zeroed`.
+    /// Segments are taken from the pool if possible, else allocated using
+    /// `std::alloc::alloc_zeroed`.
     fn allocate_segment(&mut self, min_num_words: u32) -> (*mut capnp::Word, u32) {
-        let (buf, sz) = alloc_at_least(min_num_words as usize);
+        let num_words = cmp::max(min_num_words as usize, self.next_num_words);
+        let (buf, sz) = self.pool.take(num_words);
         self.segments.push((buf, sz));
+        self.next_num_words = self.num_words();
 
         (buf, sz as u32)
     }
 }
 
+// lets the core build messages with capnp::message::Builder and keep ownership of the segments
+unsafe impl Allocator for &mut CacheAlignedAllocator {
+    fn allocate_segment(&mut self, min_num_words: u32) -> (*mut capnp::Word, u32) {
+        (**self).allocate_segment(min_num_words)
+    }
+}
+
 impl core::MessageBuilder for CacheAlignedAllocator {
     type Error = NullError;
 
@@ -78,15 +143,140 @@ impl core::MessageBuilder for CacheAlignedAllocator {
 }
 
 impl Drop for CacheAlignedAllocator {
-    /// Deallocates all allocated message segments.
+    /// Returns all allocated message segments to the pool.
     fn drop(&mut self) {
-        for (buf, sz) in self.segments.iter() {
-            let num_bytes = sz * mem::size_of::<capnp::Word>();
-            let layout = unsafe { Layout::from_size_align_unchecked(num_bytes, CACHE_SIZE) };
+        for &(buf, sz) in self.segments.iter() {
+            self.pool.put(buf, sz);
+        }
+    }
+}
+
+/// Recycles message segments, sorted into size classes of 128 bytes times a power of two.
+///
+/// Segments larger than the largest size class are allocated and deallocated as usual, as are
+/// segments returned while the pool already caches MAX_CACHED_BYTES.
+pub struct SegmentPool {
+    classes: Vec<Mutex<Vec<*mut capnp::Word>>>,
+    cached_bytes: AtomicUsize, // may briefly overcount while a segment is being cached
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SegmentPoolStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub cached_segments: usize,
+}
 
-            unsafe { alloc::dealloc(*buf as *mut u8, layout) };
+// the largest size class is 1 MiB
+const NUM_SIZE_CLASSES: usize = 14;
+
+/// The length of the largest segment that the pool recycles.
+pub const MAX_POOLED_SEGMENT_WORDS: usize =
+    (CACHE_SIZE << (NUM_SIZE_CLASSES - 1)) / mem::size_of::<capnp::Word>();
+
+const MAX_CACHED_PER_CLASS: usize = 64;
+
+/// Bounds the memory that each pool, i.e. each channel, keeps cached. Without it, a channel that
+/// once published large messages would pin up to 64 segments of each of the large classes.
+const MAX_CACHED_BYTES: usize = 4 << 20;
+
+impl SegmentPool {
+    pub fn new() -> SegmentPool {
+        SegmentPool {
+            classes: (0..NUM_SIZE_CLASSES)
+                .map(|_| Mutex::new(Vec::new()))
+                .collect(),
+            cached_bytes: AtomicUsize::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
         }
     }
+
+    /// Returns a zeroed segment of at least min_num_words words and its actual length.
+    pub fn take(&self, min_num_words: usize) -> (*mut capnp::Word, usize) {
+        let num_bytes = mem::size_of::<capnp::Word>() * min_num_words;
+
+        let class = match (0..NUM_SIZE_CLASSES).find(|&c| class_size(c) >= num_bytes) {
+            Some(c) => c,
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+
+                return alloc_at_least(min_num_words);
+            }
+        };
+
+        let num_words = class_size(class) / mem::size_of::<capnp::Word>();
+        let cached = self.classes[class].lock().pop();
+
+        match cached {
+            Some(buf) => {
+                self.cached_bytes
+                    .fetch_sub(class_size(class), Ordering::Relaxed);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                unsafe { ptr::write_bytes(buf, 0, num_words) };
+
+                (buf, num_words)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+
+                alloc_at_least(num_words)
+            }
+        }
+    }
+
+    /// Caches a segment returned by take, or deallocates it if its size class or the pool is
+    /// full.
+    pub fn put(&self, buf: *mut capnp::Word, num_words: usize) {
+        let num_bytes = mem::size_of::<capnp::Word>() * num_words;
+
+        if let Some(c) = (0..NUM_SIZE_CLASSES).find(|&c| class_size(c) == num_bytes) {
+            let mut cached = self.classes[c].lock();
+
+            // reserved before pushing so that concurrent puts can't exceed the budget together
+            let reserved = self.cached_bytes.fetch_add(num_bytes, Ordering::Relaxed);
+
+            if cached.len() < MAX_CACHED_PER_CLASS && reserved + num_bytes <= MAX_CACHED_BYTES {
+                cached.push(buf);
+
+                return;
+            }
+
+            self.cached_bytes.fetch_sub(num_bytes, Ordering::Relaxed);
+        }
+
+        unsafe { dealloc(buf, num_words) };
+    }
+
+    pub fn stats(&self) -> SegmentPoolStats {
+        SegmentPoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            cached_segments: self.classes.iter().map(|c| c.lock().len()).sum(),
+        }
+    }
+}
+
+unsafe impl Send for SegmentPool {}
+
+unsafe impl Sync for SegmentPool {}
+
+impl Drop for SegmentPool {
+    fn drop(&mut self) {
+        for (c, cached) in self.classes.iter().enumerate() {
+            let num_words = class_size(c) / mem::size_of::<capnp::Word>();
+
+            for &buf in cached.lock().iter() {
+                unsafe { dealloc(buf, num_words) };
+            }
+        }
+    }
+}
+
+fn class_size(class: usize) -> usize {
+    CACHE_SIZE << class
 }
 
 #[derive(Debug)]
@@ -132,6 +322,14 @@ fn alloc_at_least(min_num_words: usize) -> (*mut capnp::Word, usize) {
     (buf, num_words)
 }
 
+/// buf must have been allocated by alloc_at_least and be num_words long.
+unsafe fn dealloc(buf: *mut capnp::Word, num_words: usize) {
+    let num_bytes = num_words * mem::size_of::<capnp::Word>();
+    let layout = Layout::from_size_align_unchecked(num_bytes, CACHE_SIZE);
+
+    alloc::dealloc(buf as *mut u8, layout);
+}
+
 fn round_up_to_nearest_multiple_of_cache_size(x: usize) -> usize {
     if x % CACHE_SIZE != 0 {
         x + CACHE_SIZE - x % 128
@@ -139,3 +337,96 @@ fn round_up_to_nearest_multiple_of_cache_size(x: usize) -> usize {
         x
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_words_counts_what_was_built() {
+        let pool = Arc::new(SegmentPool::new());
+
+        // a one word first segment spreads the message over several segments
+        for &first_segment_words in &[DEFAULT_FIRST_SEGMENT_WORDS, 1] {
+            let mut allocator = CacheAlignedAllocator::new(pool.clone(), first_segment_words);
+
+            let num_used = {
+                let mut message = message::Builder::new(&mut allocator);
+                message.set_root(&[7u8; 100][..]).unwrap();
+
+                let segments = message.get_segments_for_output();
+                segments.iter().map(|s| s.len()).sum::<usize>()
+            };
+
+            assert_eq!(allocator.message_words(), num_used);
+            assert!(allocator.num_words() >= num_used);
+        }
+    }
+
+    #[test]
+    fn message_words_of_empty_message() {
+        let allocator = CacheAlignedAllocator::new(Arc::new(SegmentPool::new()), 1);
+
+        assert_eq!(allocator.message_words(), 0);
+    }
+
+    #[test]
+    fn largest_pooled_segment_is_recycled() {
+        let pool = SegmentPool::new();
+
+        let (buf, num_words) = pool.take(MAX_POOLED_SEGMENT_WORDS);
+        assert_eq!(num_words, MAX_POOLED_SEGMENT_WORDS);
+        pool.put(buf, num_words);
+
+        let (buf, num_words) = pool.take(MAX_POOLED_SEGMENT_WORDS);
+        pool.put(buf, num_words);
+
+        let stats = pool.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.cached_segments, 1);
+    }
+
+    #[test]
+    fn oversized_segment_bypasses_pool() {
+        let pool = SegmentPool::new();
+
+        let (buf, num_words) = pool.take(MAX_POOLED_SEGMENT_WORDS + 1);
+        assert!(num_words > MAX_POOLED_SEGMENT_WORDS);
+        pool.put(buf, num_words);
+
+        let stats = pool.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.cached_segments, 0);
+    }
+
+    #[test]
+    fn cache_is_bounded_by_total_bytes() {
+        let pool = SegmentPool::new();
+        let max_segment_bytes = MAX_POOLED_SEGMENT_WORDS * mem::size_of::<capnp::Word>();
+        let max_cached = MAX_CACHED_BYTES / max_segment_bytes;
+
+        let segments: Vec<_> = (0..max_cached + 2)
+            .map(|_| pool.take(MAX_POOLED_SEGMENT_WORDS))
+            .collect();
+
+        for (buf, num_words) in segments {
+            pool.put(buf, num_words);
+        }
+
+        assert_eq!(pool.stats().cached_segments, max_cached);
+
+        // small segments aren't cached either once the budget is spent...
+        let (buf, num_words) = pool.take(1);
+        pool.put(buf, num_words);
+        assert_eq!(pool.stats().cached_segments, max_cached);
+
+        // ...but are once taking a large one frees some of it
+        let (large, large_words) = pool.take(MAX_POOLED_SEGMENT_WORDS);
+        let (buf, num_words) = pool.take(1);
+        pool.put(buf, num_words);
+        assert_eq!(pool.stats().cached_segments, max_cached);
+
+        pool.put(large, large_words); // no room left for it
+        assert_eq!(pool.stats().cached_segments, max_cached);
+    }
+}
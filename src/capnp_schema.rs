@@ -0,0 +1,584 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Checks the hand-written `*_capnp` modules against the schemas in capnp/.
+//!
+//! This parses the subset of the schema language those schemas use (structs and enums with
+//! explicit ids, primitive, enum, `Text` and `List` fields) and lays structs out the way capnpc
+//! does, so tests can read what a module built at the offsets the schema assigns.
+
+use capnp::{
+    private::layout::{self, ElementSize},
+    traits::{FromPointerBuilder, FromPointerReader},
+    Result,
+};
+
+pub struct Schema {
+    structs: Vec<Struct>,
+    enums: Vec<Enum>,
+}
+
+impl Schema {
+    pub fn parse(src: &str) -> Schema {
+        let tokens = tokenize(src);
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+
+        parser.id();
+        parser.expect(";");
+
+        let mut decls = Vec::new();
+
+        while parser.pos < tokens.len() {
+            parser.decl(&mut decls);
+        }
+
+        let enums: Vec<Enum> = decls
+            .iter()
+            .filter_map(|d| match d {
+                Decl::Enum(e) => Some(e.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let structs = decls
+            .iter()
+            .filter_map(|d| match d {
+                Decl::Struct(s) => Some(s.lay_out(&enums)),
+                _ => None,
+            })
+            .collect();
+
+        Schema { structs, enums }
+    }
+
+    pub fn structure(&self, name: &str) -> &Struct {
+        self.structs
+            .iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("no struct {} in schema", name))
+    }
+
+    pub fn enumeration(&self, name: &str) -> &Enum {
+        self.enums
+            .iter()
+            .find(|e| e.name == name)
+            .unwrap_or_else(|| panic!("no enum {} in schema", name))
+    }
+}
+
+pub struct Struct {
+    pub name: String,
+    pub id: u64,
+    pub data_words: u16,
+    pub pointers: u16,
+    pub fields: Vec<Field>,
+}
+
+impl Struct {
+    pub fn field(&self, name: &str) -> &Field {
+        self.fields
+            .iter()
+            .find(|f| f.name == name)
+            .unwrap_or_else(|| panic!("no field {} in struct {}", name, self.name))
+    }
+}
+
+pub struct Field {
+    pub name: String,
+    pub slot: Slot,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Slot {
+    /// `offset` is in multiples of the field's own size, as the layout accessors take it.
+    Data {
+        lg_bits: u32,
+        offset: u32,
+    },
+    Pointer(u16),
+}
+
+#[derive(Clone)]
+pub struct Enum {
+    pub name: String,
+    /// Indexed by ordinal.
+    pub enumerants: Vec<String>,
+}
+
+impl Enum {
+    pub fn ordinal(&self, name: &str) -> u16 {
+        self.enumerants
+            .iter()
+            .position(|e| e == name)
+            .unwrap_or_else(|| panic!("no enumerant {} in enum {}", name, self.name)) as u16
+    }
+}
+
+/// A struct of any type, for reading back what a builder wrote.
+pub struct RawReader<'a>(layout::StructReader<'a>);
+
+impl<'a> FromPointerReader<'a> for RawReader<'a> {
+    fn get_from_pointer(reader: &layout::PointerReader<'a>) -> Result<RawReader<'a>> {
+        Ok(RawReader(reader.get_struct(::std::ptr::null())?))
+    }
+}
+
+impl<'a> RawReader<'a> {
+    pub fn assert_size(&self, structure: &Struct) {
+        assert_eq!(
+            self.0.get_data_section_size(),
+            u32::from(structure.data_words) * 64,
+            "data section size of {}",
+            structure.name
+        );
+        assert_eq!(
+            self.0.get_pointer_section_size(),
+            structure.pointers,
+            "pointer section size of {}",
+            structure.name
+        );
+    }
+
+    /// Reads a primitive or enum field, zero-extended.
+    pub fn data(&self, field: &Field) -> u64 {
+        match field.slot {
+            Slot::Data { lg_bits: 0, offset } => self.0.get_bool_field(offset as usize) as u64,
+            Slot::Data { lg_bits: 3, offset } => {
+                self.0.get_data_field::<u8>(offset as usize).into()
+            }
+            Slot::Data { lg_bits: 4, offset } => {
+                self.0.get_data_field::<u16>(offset as usize).into()
+            }
+            Slot::Data { lg_bits: 5, offset } => {
+                self.0.get_data_field::<u32>(offset as usize).into()
+            }
+            Slot::Data { lg_bits: 6, offset } => self.0.get_data_field::<u64>(offset as usize),
+            slot => panic!("{} is not a data field: {:?}", field.name, slot),
+        }
+    }
+
+    pub fn text(&self, field: &Field) -> &'a str {
+        self.0
+            .get_pointer_field(pointer_index(field))
+            .get_text(::std::ptr::null(), 0)
+            .unwrap()
+    }
+
+    pub fn struct_list(&self, field: &Field) -> Vec<RawReader<'a>> {
+        let list = self
+            .0
+            .get_pointer_field(pointer_index(field))
+            .get_list(ElementSize::InlineComposite, ::std::ptr::null())
+            .unwrap();
+
+        (0..list.len())
+            .map(|i| RawReader(list.get_struct_element(i)))
+            .collect()
+    }
+}
+
+/// A struct of any type, for writing what a reader is expected to read.
+pub struct RawBuilder<'a>(layout::StructBuilder<'a>);
+
+impl<'a> FromPointerBuilder<'a> for RawBuilder<'a> {
+    /// `size` packs the data section size in words above the pointer count; see `init_root`.
+    fn init_pointer(builder: layout::PointerBuilder<'a>, size: u32) -> RawBuilder<'a> {
+        RawBuilder(builder.init_struct(layout::StructSize {
+            data: (size >> 16) as u16,
+            pointers: size as u16,
+        }))
+    }
+
+    fn get_from_pointer(_: layout::PointerBuilder<'a>) -> Result<RawBuilder<'a>> {
+        Err(capnp::Error::unimplemented(
+            "RawBuilder can only be initialized".to_string(),
+        ))
+    }
+}
+
+impl<'a> RawBuilder<'a> {
+    pub fn init_root<A: capnp::message::Allocator>(
+        message: &'a mut capnp::message::Builder<A>,
+        structure: &Struct,
+    ) -> RawBuilder<'a> {
+        message
+            .init_root::<capnp::any_pointer::Builder>()
+            .initn_as((u32::from(structure.data_words) << 16) | u32::from(structure.pointers))
+    }
+
+    pub fn set_data(&mut self, field: &Field, value: u64) {
+        match field.slot {
+            Slot::Data { lg_bits: 6, offset } => {
+                self.0.set_data_field::<u64>(offset as usize, value)
+            }
+            slot => panic!("{} is not a 64-bit data field: {:?}", field.name, slot),
+        }
+    }
+}
+
+fn pointer_index(field: &Field) -> usize {
+    match field.slot {
+        Slot::Pointer(index) => index as usize,
+        slot => panic!("{} is not a pointer field: {:?}", field.name, slot),
+    }
+}
+
+fn tokenize(src: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+
+    for line in src.lines() {
+        let line = line.split('#').next().unwrap();
+        let mut token = String::new();
+
+        for c in line.chars() {
+            if c.is_whitespace() || "{}();:".contains(c) {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+
+                if !c.is_whitespace() {
+                    tokens.push(c.to_string());
+                }
+            } else {
+                token.push(c);
+            }
+        }
+
+        if !token.is_empty() {
+            tokens.push(token);
+        }
+    }
+
+    tokens
+}
+
+enum Decl {
+    Struct(ParsedStruct),
+    Enum(Enum),
+}
+
+struct ParsedStruct {
+    name: String,
+    id: u64,
+    fields: Vec<(u32, String, String)>, // (ordinal, name, type)
+}
+
+impl ParsedStruct {
+    fn lay_out(&self, enums: &[Enum]) -> Struct {
+        let mut fields = self.fields.clone();
+        fields.sort_by_key(|&(ordinal, _, _)| ordinal);
+
+        let mut data = DataSection::default();
+        let mut pointers = 0;
+
+        let fields = fields
+            .into_iter()
+            .map(|(_, name, ty)| {
+                let lg_bits = match ty.as_str() {
+                    "Bool" => Some(0),
+                    "Int8" | "UInt8" => Some(3),
+                    "Int16" | "UInt16" => Some(4),
+                    "Int32" | "UInt32" | "Float32" => Some(5),
+                    "Int64" | "UInt64" | "Float64" => Some(6),
+                    "Text" | "Data" => None,
+                    t if t.starts_with("List(") => None,
+                    t if enums.iter().any(|e| e.name == t) => Some(4),
+                    t => panic!("unsupported type {} for field {}", t, name),
+                };
+
+                let slot = match lg_bits {
+                    Some(lg_bits) => Slot::Data {
+                        lg_bits,
+                        offset: data.allocate(lg_bits),
+                    },
+                    None => {
+                        pointers += 1;
+
+                        Slot::Pointer(pointers - 1)
+                    }
+                };
+
+                Field { name, slot }
+            })
+            .collect();
+
+        Struct {
+            name: self.name.clone(),
+            id: self.id,
+            data_words: data.words,
+            pointers,
+            fields,
+        }
+    }
+}
+
+/// The data section of a struct without unions or groups, laid out as capnpc does: each field
+/// fills the first free hole of its size left by padding, or else starts a new word.
+#[derive(Default)]
+struct DataSection {
+    words: u16,
+    holes: [Option<u32>; 6], // indexed by lg of the hole size in bits
+}
+
+impl DataSection {
+    fn allocate(&mut self, lg_bits: u32) -> u32 {
+        if let Some(offset) = self.take_hole(lg_bits) {
+            return offset;
+        }
+
+        let offset = u32::from(self.words) << (6 - lg_bits);
+        self.words += 1;
+
+        // the rest of the new word becomes one hole of each size from lg_bits up
+        let mut hole = offset + 1;
+
+        for lg in lg_bits..6 {
+            self.holes[lg as usize] = Some(hole);
+            hole = hole.div_ceil(2);
+        }
+
+        offset
+    }
+
+    fn take_hole(&mut self, lg_bits: u32) -> Option<u32> {
+        if lg_bits >= 6 {
+            return None;
+        }
+
+        if let Some(offset) = self.holes[lg_bits as usize].take() {
+            return Some(offset);
+        }
+
+        // split a hole of twice the size, leaving its upper half
+        let offset = self.take_hole(lg_bits + 1)? * 2;
+        self.holes[lg_bits as usize] = Some(offset + 1);
+
+        Some(offset)
+    }
+}
+
+struct Parser<'t> {
+    tokens: &'t [String],
+    pos: usize,
+}
+
+impl<'t> Parser<'t> {
+    fn next(&mut self) -> &'t str {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .unwrap_or_else(|| panic!("unexpected end of schema"));
+        self.pos += 1;
+
+        token
+    }
+
+    fn expect(&mut self, expected: &str) {
+        let token = self.next();
+        assert_eq!(token, expected, "unexpected token in schema");
+    }
+
+    fn id(&mut self) -> u64 {
+        let token = self.next();
+        let hex = token
+            .strip_prefix("@0x")
+            .unwrap_or_else(|| panic!("expected an id, got {}", token));
+
+        u64::from_str_radix(hex, 16).unwrap()
+    }
+
+    fn ordinal(&mut self) -> u32 {
+        let token = self.next();
+        let ordinal = token
+            .strip_prefix('@')
+            .unwrap_or_else(|| panic!("expected an ordinal, got {}", token));
+
+        ordinal.parse().unwrap()
+    }
+
+    /// Parses a struct or enum, appending it and any nested declarations to `decls`.
+    fn decl(&mut self, decls: &mut Vec<Decl>) {
+        match self.next() {
+            "struct" => self.structure(decls),
+            "enum" => {
+                let e = self.enumeration();
+                decls.push(Decl::Enum(e));
+            }
+            token => panic!("unsupported declaration {}", token),
+        }
+    }
+
+    fn structure(&mut self, decls: &mut Vec<Decl>) {
+        let name = self.next().to_string();
+        let id = self.id();
+        self.expect("{");
+
+        let mut fields = Vec::new();
+
+        loop {
+            match self.tokens.get(self.pos).map(String::as_str) {
+                Some("}") => {
+                    self.pos += 1;
+
+                    break;
+                }
+                Some("struct") | Some("enum") => self.decl(decls),
+                _ => {
+                    let field = self.next().to_string();
+                    let ordinal = self.ordinal();
+                    self.expect(":");
+
+                    let mut ty = self.next().to_string();
+
+                    if self.tokens.get(self.pos).map(String::as_str) == Some("(") {
+                        self.pos += 1;
+                        ty = format!("{}({})", ty, self.next());
+                        self.expect(")");
+                    }
+
+                    self.expect(";");
+                    fields.push((ordinal, field, ty));
+                }
+            }
+        }
+
+        decls.push(Decl::Struct(ParsedStruct { name, id, fields }));
+    }
+
+    fn enumeration(&mut self) -> Enum {
+        let name = self.next().to_string();
+        self.id();
+        self.expect("{");
+
+        let mut enumerants = Vec::new();
+
+        loop {
+            let enumerant = self.next();
+
+            if enumerant == "}" {
+                break;
+            }
+
+            assert_eq!(
+                self.ordinal() as usize,
+                enumerants.len(),
+                "enumerants of {} out of order",
+                name
+            );
+            self.expect(";");
+            enumerants.push(enumerant.to_string());
+        }
+
+        Enum { name, enumerants }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_padding_holes() {
+        let schema = Schema::parse(
+            "@0xd000f8bfaa133d84;
+            struct Mixed @0x9f0c0e5a2b3d4c5e {
+                a @0 :UInt16;
+                b @1 :Int64; # after a word of padding
+                c @2 :UInt32;
+                d @3 :Bool;
+                e @4 :UInt8;
+                f @5 :UInt16;
+                g @6 :Text;
+                h @7 :List(Mixed);
+            }",
+        );
+        let mixed = schema.structure("Mixed");
+
+        let slots: Vec<_> = mixed.fields.iter().map(|f| f.slot).collect();
+        assert_eq!(
+            slots,
+            [
+                Slot::Data {
+                    lg_bits: 4,
+                    offset: 0
+                },
+                Slot::Data {
+                    lg_bits: 6,
+                    offset: 1
+                },
+                Slot::Data {
+                    lg_bits: 5,
+                    offset: 1
+                },
+                Slot::Data {
+                    lg_bits: 0,
+                    offset: 16
+                },
+                Slot::Data {
+                    lg_bits: 3,
+                    offset: 3
+                },
+                Slot::Data {
+                    lg_bits: 4,
+                    offset: 8
+                },
+                Slot::Pointer(0),
+                Slot::Pointer(1),
+            ]
+        );
+        assert_eq!(mixed.data_words, 3);
+        assert_eq!(mixed.pointers, 2);
+        assert_eq!(mixed.id, 0x9f0c_0e5a_2b3d_4c5e);
+    }
+
+    #[test]
+    fn lays_out_fields_in_ordinal_order() {
+        let schema = Schema::parse(
+            "@0xd000f8bfaa133d85;
+            struct Reordered @0xa1b2c3d4e5f60718 {
+                late @1 :UInt64;
+                early @0 :Kind;
+                enum Kind @0xa1b2c3d4e5f60719 { first @0; second @1; }
+            }",
+        );
+        let reordered = schema.structure("Reordered");
+
+        assert_eq!(
+            reordered.field("early").slot,
+            Slot::Data {
+                lg_bits: 4,
+                offset: 0
+            }
+        );
+        assert_eq!(
+            reordered.field("late").slot,
+            Slot::Data {
+                lg_bits: 6,
+                offset: 1
+            }
+        );
+        assert_eq!(schema.enumeration("Kind").ordinal("second"), 1);
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::{
+    sync::atomic::{AtomicBool, AtomicI64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The source of time for the core and all of its nodes.
+///
+/// Times are measured in nanoseconds since the UNIX epoch. By default, the clock follows wall
+/// clock time; once sim time is enabled, it only moves when `set_sim_time` is called.
+pub struct Clock {
+    use_sim_time: AtomicBool,
+    sim_time: AtomicI64, // NO_SIM_TIME until the first sim time is set
+}
+
+const NO_SIM_TIME: i64 = i64::MIN;
+
+impl Clock {
+    pub fn new() -> Clock {
+        Clock {
+            use_sim_time: AtomicBool::new(false),
+            sim_time: AtomicI64::new(NO_SIM_TIME),
+        }
+    }
+
+    /// Returns the current time. In sim time, this is zero until the first sim time is set.
+    pub fn now(&self) -> i64 {
+        if self.is_sim_time() {
+            match self.sim_time.load(Ordering::SeqCst) {
+                NO_SIM_TIME => 0,
+                t => t,
+            }
+        } else {
+            wall_time()
+        }
+    }
+
+    pub fn enable_sim_time(&self) {
+        self.use_sim_time.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_sim_time(&self) -> bool {
+        self.use_sim_time.load(Ordering::SeqCst)
+    }
+
+    /// Returns the previous sim time, if any had been set.
+    pub fn set_sim_time(&self, time: i64) -> Option<i64> {
+        match self.sim_time.swap(time, Ordering::SeqCst) {
+            NO_SIM_TIME => None,
+            t => Some(t),
+        }
+    }
+}
+
+fn wall_time() -> i64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before the UNIX epoch");
+
+    since_epoch.as_secs() as i64 * 1_000_000_000 + i64::from(since_epoch.subsec_nanos())
+}
@@ -0,0 +1,85 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// readers for capnp/clock.capnp, in the form generated by capnpc-rust
+pub mod clock {
+    use capnp::{
+        private::layout,
+        traits::{FromPointerReader, FromStructReader, HasTypeId},
+        Result,
+    };
+
+    pub const TYPE_ID: u64 = 0xdcaa_bf3f_7487_7359;
+
+    #[derive(Clone, Copy)]
+    pub struct Reader<'a> {
+        reader: layout::StructReader<'a>,
+    }
+
+    impl<'a> HasTypeId for Reader<'a> {
+        fn type_id() -> u64 {
+            TYPE_ID
+        }
+    }
+
+    impl<'a> FromStructReader<'a> for Reader<'a> {
+        fn new(reader: layout::StructReader<'a>) -> Reader<'a> {
+            Reader { reader }
+        }
+    }
+
+    impl<'a> FromPointerReader<'a> for Reader<'a> {
+        fn get_from_pointer(reader: &layout::PointerReader<'a>) -> Result<Reader<'a>> {
+            Ok(FromStructReader::new(
+                reader.get_struct(::std::ptr::null())?,
+            ))
+        }
+    }
+
+    impl<'a> Reader<'a> {
+        pub fn get_time(self) -> i64 {
+            self.reader.get_data_field::<i64>(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clock;
+    use crate::capnp_schema::{RawBuilder, Schema};
+
+    use capnp::message;
+
+    #[test]
+    fn clock_matches_schema() {
+        let schema = Schema::parse(include_str!("../capnp/clock.capnp"));
+        let structure = schema.structure("Clock");
+        assert_eq!(clock::TYPE_ID, structure.id);
+        assert_eq!(structure.fields.len(), 1, "a field was added to Clock");
+
+        let mut message = message::Builder::new_default();
+        RawBuilder::init_root(&mut message, structure).set_data(structure.field("time"), 42);
+
+        let clock = message.get_root_as_reader::<clock::Reader>().unwrap();
+        assert_eq!(clock.get_time(), 42);
+    }
+}
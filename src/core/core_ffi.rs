@@ -20,10 +20,10 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use super::{Core, Error, ParamType, Publisher, Subscriber};
+use super::{Core, Error, ParamType, ParamWatcher, Publisher, Subscriber, Synchronizer, Timer};
 use crate::{ffi, util};
 
-use std::{mem, ptr};
+use std::{mem, ptr, slice};
 
 use libc::{c_char, c_int, c_void};
 
@@ -71,6 +71,42 @@ pub unsafe extern "C" fn advertise<C: Core>(
     }
 }
 
+pub unsafe extern "C" fn synchronize<C: Core>(
+    impl_ptr: *const c_void,
+    params: ffi::SyncParams,
+    synchronizer: *mut ffi::Synchronizer,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(!synchronizer.is_null());
+
+    match (*(impl_ptr as *const C)).synchronize(params) {
+        Ok(s) => {
+            *synchronizer = s.into_ffi();
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn get_topic_stats<C: Core>(
+    impl_ptr: *const c_void,
+    topic: ffi::StrView,
+    stats: *mut ffi::TopicStats,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(!stats.is_null());
+
+    match (*(impl_ptr as *const C)).get_topic_stats(util::ffi_to_str(topic).unwrap()) {
+        Ok(s) => {
+            *stats = s;
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
 pub unsafe extern "C" fn get_err_msg<C: Core>(_: *const c_void, err: c_int) -> ffi::StrView {
     let msg = C::Error::from_code(err).what();
 
@@ -80,6 +116,30 @@ pub unsafe extern "C" fn get_err_msg<C: Core>(_: *const c_void, err: c_int) -> f
     }
 }
 
+pub unsafe extern "C" fn now<C: Core>(impl_ptr: *const c_void) -> ffi::Time {
+    assert!(!impl_ptr.is_null());
+
+    (*(impl_ptr as *const C)).now()
+}
+
+pub unsafe extern "C" fn create_timer<C: Core>(
+    impl_ptr: *const c_void,
+    params: ffi::TimerParams,
+    timer: *mut ffi::Timer,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(!timer.is_null());
+
+    match (*(impl_ptr as *const C)).create_timer(params) {
+        Ok(t) => {
+            *timer = t.into_ffi();
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
 pub unsafe extern "C" fn log_error<C: Core>(impl_ptr: *const c_void, msg: ffi::StrView) -> c_int {
     assert!(!impl_ptr.is_null());
 
@@ -125,6 +185,74 @@ pub unsafe extern "C" fn log_trace<C: Core>(impl_ptr: *const c_void, msg: ffi::S
     }
 }
 
+pub unsafe extern "C" fn log<C: Core>(
+    impl_ptr: *const c_void,
+    level: c_int,
+    msg: ffi::StrView,
+    fields: *const ffi::LogField,
+    num_fields: ffi::Index,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(num_fields == 0 || !fields.is_null());
+
+    let fields: Vec<_> = if num_fields == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(fields, num_fields as usize)
+            .iter()
+            .map(|f| {
+                (
+                    util::ffi_to_str(f.key).unwrap(),
+                    util::ffi_to_str(f.value).unwrap(),
+                )
+            })
+            .collect()
+    };
+
+    match (*(impl_ptr as *const C)).log(level, util::ffi_to_str(msg).unwrap(), &fields) {
+        Ok(()) => 0,
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn log_throttled<C: Core>(
+    impl_ptr: *const c_void,
+    level: c_int,
+    key: ffi::StrView,
+    period: ffi::Duration,
+    msg: ffi::StrView,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+
+    match (*(impl_ptr as *const C)).log_throttled(
+        level,
+        util::ffi_to_str(key).unwrap(),
+        period,
+        util::ffi_to_str(msg).unwrap(),
+    ) {
+        Ok(()) => 0,
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn log_once<C: Core>(
+    impl_ptr: *const c_void,
+    level: c_int,
+    key: ffi::StrView,
+    msg: ffi::StrView,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+
+    match (*(impl_ptr as *const C)).log_once(
+        level,
+        util::ffi_to_str(key).unwrap(),
+        util::ffi_to_str(msg).unwrap(),
+    ) {
+        Ok(()) => 0,
+        Err(e) => e.as_code(),
+    }
+}
+
 pub unsafe extern "C" fn param_type<C: Core>(
     impl_ptr: *const c_void,
     key: ffi::StrView,
@@ -135,12 +263,7 @@ pub unsafe extern "C" fn param_type<C: Core>(
 
     match (*(impl_ptr as *const C)).param_type(util::ffi_to_str(key).unwrap()) {
         Ok(t) => {
-            *tp = match t {
-                ParamType::Integer => ffi::ParamType::SRM_INTEGER as c_int,
-                ParamType::Boolean => ffi::ParamType::SRM_BOOLEAN as c_int,
-                ParamType::Real => ffi::ParamType::SRM_REAL as c_int,
-                ParamType::String => ffi::ParamType::SRM_STRING as c_int,
-            };
+            *tp = param_type_to_ffi(&t);
 
             0
         }
@@ -198,6 +321,30 @@ pub unsafe extern "C" fn param_swapi<C: Core>(
     }
 }
 
+pub unsafe extern "C" fn param_compare_swapi<C: Core>(
+    impl_ptr: *const c_void,
+    key: ffi::StrView,
+    expected: isize,
+    desired: isize,
+    result: *mut c_int,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(!result.is_null());
+
+    match (*(impl_ptr as *const C)).param_compare_swapi(
+        util::ffi_to_str(key).unwrap(),
+        expected,
+        desired,
+    ) {
+        Ok(swapped) => {
+            *result = swapped as c_int;
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
 pub unsafe extern "C" fn param_setb<C: Core>(
     impl_ptr: *const c_void,
     key: ffi::StrView,
@@ -248,6 +395,30 @@ pub unsafe extern "C" fn param_swapb<C: Core>(
     }
 }
 
+pub unsafe extern "C" fn param_compare_swapb<C: Core>(
+    impl_ptr: *const c_void,
+    key: ffi::StrView,
+    expected: c_int,
+    desired: c_int,
+    result: *mut c_int,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(!result.is_null());
+
+    match (*(impl_ptr as *const C)).param_compare_swapb(
+        util::ffi_to_str(key).unwrap(),
+        expected != 0,
+        desired != 0,
+    ) {
+        Ok(swapped) => {
+            *result = swapped as c_int;
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
 pub unsafe extern "C" fn param_setr<C: Core>(
     impl_ptr: *const c_void,
     key: ffi::StrView,
@@ -298,6 +469,30 @@ pub unsafe extern "C" fn param_swapr<C: Core>(
     }
 }
 
+pub unsafe extern "C" fn param_compare_swapr<C: Core>(
+    impl_ptr: *const c_void,
+    key: ffi::StrView,
+    expected: f64,
+    desired: f64,
+    result: *mut c_int,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(!result.is_null());
+
+    match (*(impl_ptr as *const C)).param_compare_swapr(
+        util::ffi_to_str(key).unwrap(),
+        expected,
+        desired,
+    ) {
+        Ok(swapped) => {
+            *result = swapped as c_int;
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
 pub unsafe extern "C" fn param_sets<C: Core>(
     impl_ptr: *const c_void,
     key: ffi::StrView,
@@ -354,6 +549,153 @@ pub unsafe extern "C" fn param_swaps<C: Core>(
     }
 }
 
+pub unsafe extern "C" fn param_compare_swaps<C: Core>(
+    impl_ptr: *const c_void,
+    key: ffi::StrView,
+    expected: ffi::StrView,
+    desired: ffi::StrView,
+    result: *mut c_int,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(!result.is_null());
+
+    match (*(impl_ptr as *const C)).param_compare_swaps(
+        util::ffi_to_str(key).unwrap(),
+        util::ffi_to_str(expected).unwrap(),
+        util::ffi_to_str(desired).unwrap().to_string(),
+    ) {
+        Ok(swapped) => {
+            *result = swapped as c_int;
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn param_transaction<C: Core>(
+    impl_ptr: *const c_void,
+    assignments: *const ffi::ParamAssignment,
+    num_assignments: ffi::Index,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(!assignments.is_null() || num_assignments == 0);
+
+    let assignments = if num_assignments == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(assignments, num_assignments as usize)
+    };
+
+    match (*(impl_ptr as *const C)).param_transaction(assignments) {
+        Ok(()) => 0,
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn param_list<C: Core>(
+    impl_ptr: *const c_void,
+    prefix: ffi::StrView,
+    callback: Option<ffi::ParamListCallback>,
+    arg: *mut c_void,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(callback.is_some());
+
+    let callback = callback.unwrap();
+
+    match (*(impl_ptr as *const C)).param_list(prefix_from_ffi(prefix)) {
+        Ok(params) => {
+            for (key, tp) in params.iter() {
+                if callback(util::str_to_ffi(key), param_type_to_ffi(tp), arg) != 0 {
+                    break;
+                }
+            }
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn param_delete<C: Core>(
+    impl_ptr: *const c_void,
+    key: ffi::StrView,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+
+    match (*(impl_ptr as *const C)).param_delete(util::ffi_to_str(key).unwrap()) {
+        Ok(()) => 0,
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn param_dump<C: Core>(
+    impl_ptr: *const c_void,
+    prefix: ffi::StrView,
+    path: ffi::StrView,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+
+    match (*(impl_ptr as *const C))
+        .param_dump(prefix_from_ffi(prefix), util::ffi_to_str(path).unwrap())
+    {
+        Ok(()) => 0,
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn param_declare<C: Core>(
+    impl_ptr: *const c_void,
+    decl: ffi::ParamDecl,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+
+    match (*(impl_ptr as *const C)).param_declare(decl) {
+        Ok(()) => 0,
+        Err(e) => e.as_code(),
+    }
+}
+
+pub unsafe extern "C" fn param_watch<C: Core>(
+    impl_ptr: *const c_void,
+    key: ffi::StrView,
+    callback: Option<ffi::ParamWatchCallback>,
+    arg: *mut c_void,
+    watcher: *mut ffi::ParamWatcher,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+    assert!(callback.is_some());
+    assert!(!watcher.is_null());
+
+    match (*(impl_ptr as *const C)).param_watch(
+        util::ffi_to_str(key).unwrap(),
+        callback.unwrap(),
+        arg,
+    ) {
+        Ok(w) => {
+            *watcher = w.into_ffi();
+
+            0
+        }
+        Err(e) => e.as_code(),
+    }
+}
+
+/// Converts a parameter key prefix, where both a null and an empty prefix match all keys.
+unsafe fn prefix_from_ffi<'a>(prefix: ffi::StrView) -> Option<&'a str> {
+    util::ffi_to_str(prefix).filter(|p| !p.is_empty())
+}
+
+fn param_type_to_ffi(tp: &ParamType) -> c_int {
+    match tp {
+        ParamType::Integer => ffi::ParamType::SRM_INTEGER as c_int,
+        ParamType::Boolean => ffi::ParamType::SRM_BOOLEAN as c_int,
+        ParamType::Real => ffi::ParamType::SRM_REAL as c_int,
+        ParamType::String => ffi::ParamType::SRM_STRING as c_int,
+    }
+}
+
 unsafe extern "C" fn drop_string(data: *mut c_char, capacity: ffi::Index, _: *mut c_void) {
     mem::drop(Vec::from_raw_parts(
         data,
@@ -376,3 +718,22 @@ unsafe fn string_to_ffi(mut s: String) -> ffi::String {
         drop: Some(drop_string),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_from_ffi_matches_all_if_empty() {
+        let null = ffi::StrView {
+            data: ptr::null(),
+            len: 0,
+        };
+
+        unsafe {
+            assert_eq!(prefix_from_ffi(null), None);
+            assert_eq!(prefix_from_ffi(util::str_to_ffi("")), None);
+            assert_eq!(prefix_from_ffi(util::str_to_ffi("~.foo")), Some("~.foo"));
+        }
+    }
+}
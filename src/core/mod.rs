@@ -25,7 +25,7 @@ use super::ffi;
 use std::error;
 
 use capnp::message::Allocator;
-use libc::c_int;
+use libc::{c_int, c_void};
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum ParamType {
@@ -43,35 +43,91 @@ pub trait Core: Send + Sync + CoreBase {
     type Error: Error;
     type Publisher: Publisher;
     type Subscriber: Subscriber;
+    type Synchronizer: Synchronizer;
+    type ParamWatcher: ParamWatcher;
+    type Timer: Timer;
 
     fn get_type(&self) -> &str;
 
     fn subscribe(&self, params: ffi::SubscribeParams) -> Result<Self::Subscriber, Self::Error>;
     fn advertise(&self, params: ffi::AdvertiseParams) -> Result<Self::Publisher, Self::Error>;
+    fn synchronize(&self, params: ffi::SyncParams) -> Result<Self::Synchronizer, Self::Error>;
+    fn get_topic_stats(&self, topic: &str) -> Result<ffi::TopicStats, Self::Error>;
+
+    fn now(&self) -> i64;
+    fn create_timer(&self, params: ffi::TimerParams) -> Result<Self::Timer, Self::Error>;
 
     fn log_error(&self, msg: &str) -> Result<(), Self::Error>;
     fn log_warn(&self, msg: &str) -> Result<(), Self::Error>;
     fn log_info(&self, msg: &str) -> Result<(), Self::Error>;
     fn log_debug(&self, msg: &str) -> Result<(), Self::Error>;
     fn log_trace(&self, msg: &str) -> Result<(), Self::Error>;
+    fn log(&self, level: c_int, msg: &str, fields: &[(&str, &str)]) -> Result<(), Self::Error>;
+    fn log_throttled(
+        &self,
+        level: c_int,
+        key: &str,
+        period: ffi::Duration,
+        msg: &str,
+    ) -> Result<(), Self::Error>;
+    fn log_once(&self, level: c_int, key: &str, msg: &str) -> Result<(), Self::Error>;
 
     fn param_type(&self, key: &str) -> Result<ParamType, Self::Error>;
 
     fn param_seti(&self, key: &str, value: isize) -> Result<(), Self::Error>;
     fn param_geti(&self, key: &str) -> Result<isize, Self::Error>;
     fn param_swapi(&self, key: &str, value: isize) -> Result<isize, Self::Error>;
+    fn param_compare_swapi(
+        &self,
+        key: &str,
+        expected: isize,
+        desired: isize,
+    ) -> Result<bool, Self::Error>;
 
     fn param_setb(&self, key: &str, value: bool) -> Result<(), Self::Error>;
     fn param_getb(&self, key: &str) -> Result<bool, Self::Error>;
     fn param_swapb(&self, key: &str, value: bool) -> Result<bool, Self::Error>;
+    fn param_compare_swapb(
+        &self,
+        key: &str,
+        expected: bool,
+        desired: bool,
+    ) -> Result<bool, Self::Error>;
 
     fn param_setr(&self, key: &str, value: f64) -> Result<(), Self::Error>;
     fn param_getr(&self, key: &str) -> Result<f64, Self::Error>;
     fn param_swapr(&self, key: &str, value: f64) -> Result<f64, Self::Error>;
+    fn param_compare_swapr(
+        &self,
+        key: &str,
+        expected: f64,
+        desired: f64,
+    ) -> Result<bool, Self::Error>;
 
     fn param_sets(&self, key: &str, value: String) -> Result<(), Self::Error>;
     fn param_gets(&self, key: &str) -> Result<String, Self::Error>;
     fn param_swaps(&self, key: &str, value: String) -> Result<String, Self::Error>;
+    fn param_compare_swaps(
+        &self,
+        key: &str,
+        expected: &str,
+        desired: String,
+    ) -> Result<bool, Self::Error>;
+
+    fn param_transaction(&self, assignments: &[ffi::ParamAssignment]) -> Result<(), Self::Error>;
+
+    fn param_list(&self, prefix: Option<&str>) -> Result<Vec<(String, ParamType)>, Self::Error>;
+    fn param_delete(&self, key: &str) -> Result<(), Self::Error>;
+    fn param_dump(&self, prefix: Option<&str>, path: &str) -> Result<(), Self::Error>;
+
+    fn param_declare(&self, decl: ffi::ParamDecl) -> Result<(), Self::Error>;
+
+    fn param_watch(
+        &self,
+        key: &str,
+        callback: ffi::ParamWatchCallback,
+        arg: *mut c_void,
+    ) -> Result<Self::ParamWatcher, Self::Error>;
 }
 
 pub trait Publisher: Send {
@@ -99,6 +155,30 @@ pub trait Subscriber: Send {
     fn into_ffi(self) -> ffi::Subscriber;
 }
 
+pub trait Synchronizer: Send {
+    type Error: Error;
+
+    fn get_num_topics(&self) -> usize;
+
+    fn into_ffi(self) -> ffi::Synchronizer;
+}
+
+pub trait ParamWatcher: Send {
+    type Error: Error;
+
+    fn get_key(&self) -> &str;
+
+    fn into_ffi(self) -> ffi::ParamWatcher;
+}
+
+pub trait Timer: Send {
+    type Error: Error;
+
+    fn get_period(&self) -> i64;
+
+    fn into_ffi(self) -> ffi::Timer;
+}
+
 pub trait MessageBuilder: Send + Allocator {
     type Error: Error;
 
@@ -126,32 +206,53 @@ macro_rules! srm_core_base_impl {
 
                 subscribe: Some($crate::core::core_ffi::subscribe::<$x>),
                 advertise: Some($crate::core::core_ffi::advertise::<$x>),
+                synchronize: Some($crate::core::core_ffi::synchronize::<$x>),
+                get_topic_stats: Some($crate::core::core_ffi::get_topic_stats::<$x>),
 
                 get_err_msg: Some($crate::core::core_ffi::get_err_msg::<$x>),
 
+                now: Some($crate::core::core_ffi::now::<$x>),
+                create_timer: Some($crate::core::core_ffi::create_timer::<$x>),
+
                 log_error: Some($crate::core::core_ffi::log_error::<$x>),
                 log_warn: Some($crate::core::core_ffi::log_warn::<$x>),
                 log_info: Some($crate::core::core_ffi::log_info::<$x>),
                 log_debug: Some($crate::core::core_ffi::log_debug::<$x>),
                 log_trace: Some($crate::core::core_ffi::log_trace::<$x>),
+                log: Some($crate::core::core_ffi::log::<$x>),
+                log_throttled: Some($crate::core::core_ffi::log_throttled::<$x>),
+                log_once: Some($crate::core::core_ffi::log_once::<$x>),
 
                 param_type: Some($crate::core::core_ffi::param_type::<$x>),
 
                 param_seti: Some($crate::core::core_ffi::param_seti::<$x>),
                 param_geti: Some($crate::core::core_ffi::param_geti::<$x>),
                 param_swapi: Some($crate::core::core_ffi::param_swapi::<$x>),
+                param_compare_swapi: Some($crate::core::core_ffi::param_compare_swapi::<$x>),
 
                 param_setb: Some($crate::core::core_ffi::param_setb::<$x>),
                 param_getb: Some($crate::core::core_ffi::param_getb::<$x>),
                 param_swapb: Some($crate::core::core_ffi::param_swapb::<$x>),
+                param_compare_swapb: Some($crate::core::core_ffi::param_compare_swapb::<$x>),
 
                 param_setr: Some($crate::core::core_ffi::param_setr::<$x>),
                 param_getr: Some($crate::core::core_ffi::param_getr::<$x>),
                 param_swapr: Some($crate::core::core_ffi::param_swapr::<$x>),
+                param_compare_swapr: Some($crate::core::core_ffi::param_compare_swapr::<$x>),
 
                 param_sets: Some($crate::core::core_ffi::param_sets::<$x>),
                 param_gets: Some($crate::core::core_ffi::param_gets::<$x>),
                 param_swaps: Some($crate::core::core_ffi::param_swaps::<$x>),
+                param_compare_swaps: Some($crate::core::core_ffi::param_compare_swaps::<$x>),
+
+                param_transaction: Some($crate::core::core_ffi::param_transaction::<$x>),
+
+                param_list: Some($crate::core::core_ffi::param_list::<$x>),
+                param_delete: Some($crate::core::core_ffi::param_delete::<$x>),
+                param_dump: Some($crate::core::core_ffi::param_dump::<$x>),
+
+                param_declare: Some($crate::core::core_ffi::param_declare::<$x>),
+                param_watch: Some($crate::core::core_ffi::param_watch::<$x>),
             };
 
             ffi::Core{ impl_ptr: self as *const $x as *const c_void,
@@ -201,6 +302,60 @@ macro_rules! srm_publisher_impl {
     )
 }
 
+#[macro_export]
+macro_rules! srm_synchronizer_impl {
+    ($x:ty) => (
+        fn into_ffi(self) -> ffi::Synchronizer {
+            use libc::c_void;
+
+            const VTBL: ffi::SynchronizerVtbl = ffi::SynchronizerVtbl{
+                get_num_topics: Some($crate::core::synchronizer_ffi::get_num_topics::<$x>),
+                disconnect: Some($crate::core::synchronizer_ffi::disconnect::<$x>),
+                get_err_msg: Some($crate::core::synchronizer_ffi::get_err_msg::<$x>),
+            };
+
+            ffi::Synchronizer{ impl_ptr: Box::into_raw(Box::new(self)) as *mut c_void,
+                               vptr: &VTBL as *const ffi::SynchronizerVtbl }
+        }
+    )
+}
+
+#[macro_export]
+macro_rules! srm_param_watcher_impl {
+    ($x:ty) => (
+        fn into_ffi(self) -> ffi::ParamWatcher {
+            use libc::c_void;
+
+            const VTBL: ffi::ParamWatcherVtbl = ffi::ParamWatcherVtbl{
+                get_key: Some($crate::core::param_watcher_ffi::get_key::<$x>),
+                disconnect: Some($crate::core::param_watcher_ffi::disconnect::<$x>),
+                get_err_msg: Some($crate::core::param_watcher_ffi::get_err_msg::<$x>),
+            };
+
+            ffi::ParamWatcher{ impl_ptr: Box::into_raw(Box::new(self)) as *mut c_void,
+                               vptr: &VTBL as *const ffi::ParamWatcherVtbl }
+        }
+    )
+}
+
+#[macro_export]
+macro_rules! srm_timer_impl {
+    ($x:ty) => (
+        fn into_ffi(self) -> ffi::Timer {
+            use libc::c_void;
+
+            const VTBL: ffi::TimerVtbl = ffi::TimerVtbl{
+                get_period: Some($crate::core::timer_ffi::get_period::<$x>),
+                cancel: Some($crate::core::timer_ffi::cancel::<$x>),
+                get_err_msg: Some($crate::core::timer_ffi::get_err_msg::<$x>),
+            };
+
+            ffi::Timer{ impl_ptr: Box::into_raw(Box::new(self)) as *mut c_void,
+                        vptr: &VTBL as *const ffi::TimerVtbl }
+        }
+    )
+}
+
 #[macro_export]
 macro_rules! srm_message_builder_impl {
     ($x:ty) => (
@@ -225,3 +380,9 @@ pub mod subscriber_ffi;
 pub mod publisher_ffi;
 
 pub mod message_builder_ffi;
+
+pub mod synchronizer_ffi;
+
+pub mod param_watcher_ffi;
+
+pub mod timer_ffi;
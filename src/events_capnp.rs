@@ -0,0 +1,174 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// builders for capnp/events.capnp, in the form generated by capnpc-rust
+pub mod event {
+    use capnp::{
+        private::layout,
+        traits::{FromPointerBuilder, FromStructBuilder, HasStructSize, HasTypeId, ToU16},
+        Result,
+    };
+
+    pub const TYPE_ID: u64 = 0xbff3_f905_e51a_2367;
+
+    pub struct Builder<'a> {
+        builder: layout::StructBuilder<'a>,
+    }
+
+    impl<'a> HasTypeId for Builder<'a> {
+        fn type_id() -> u64 {
+            TYPE_ID
+        }
+    }
+
+    impl<'a> HasStructSize for Builder<'a> {
+        fn struct_size() -> layout::StructSize {
+            STRUCT_SIZE
+        }
+    }
+
+    impl<'a> FromStructBuilder<'a> for Builder<'a> {
+        fn new(builder: layout::StructBuilder<'a>) -> Builder<'a> {
+            Builder { builder }
+        }
+    }
+
+    impl<'a> FromPointerBuilder<'a> for Builder<'a> {
+        fn init_pointer(builder: layout::PointerBuilder<'a>, _size: u32) -> Builder<'a> {
+            FromStructBuilder::new(builder.init_struct(STRUCT_SIZE))
+        }
+
+        fn get_from_pointer(builder: layout::PointerBuilder<'a>) -> Result<Builder<'a>> {
+            Ok(FromStructBuilder::new(
+                builder.get_struct(STRUCT_SIZE, ::std::ptr::null())?,
+            ))
+        }
+    }
+
+    impl<'a> Builder<'a> {
+        pub fn set_time(&mut self, value: i64) {
+            self.builder.set_data_field::<i64>(0, value);
+        }
+
+        pub fn set_msg_type(&mut self, value: u64) {
+            self.builder.set_data_field::<u64>(1, value);
+        }
+
+        pub fn set_subscriber(&mut self, value: u64) {
+            self.builder.set_data_field::<u64>(2, value);
+        }
+
+        pub fn set_kind(&mut self, value: Kind) {
+            self.builder.set_data_field::<u16>(12, value.to_u16());
+        }
+
+        pub fn set_topic(&mut self, value: &str) {
+            self.builder.get_pointer_field(0).set_text(value);
+        }
+
+        pub fn set_node(&mut self, value: &str) {
+            self.builder.get_pointer_field(1).set_text(value);
+        }
+    }
+
+    const STRUCT_SIZE: layout::StructSize = layout::StructSize {
+        data: 4,
+        pointers: 2,
+    };
+
+    #[repr(u16)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Kind {
+        ChannelCreated = 0,
+        SubscriberAttached = 1,
+        SubscriberDetached = 2,
+        NodeStarted = 3,
+        NodeStopped = 4,
+    }
+
+    impl ToU16 for Kind {
+        fn to_u16(self) -> u16 {
+            self as u16
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::event::{self, Kind};
+    use crate::capnp_schema::{RawReader, Schema};
+
+    use capnp::message;
+
+    fn schema() -> Schema {
+        Schema::parse(include_str!("../capnp/events.capnp"))
+    }
+
+    #[test]
+    fn event_matches_schema() {
+        let schema = schema();
+        let structure = schema.structure("Event");
+        assert_eq!(event::TYPE_ID, structure.id);
+        assert_eq!(structure.fields.len(), 6, "a field was added to Event");
+
+        let mut message = message::Builder::new_default();
+
+        {
+            let mut event = message.init_root::<event::Builder>();
+            event.set_time(1);
+            event.set_msg_type(2);
+            event.set_subscriber(3);
+            event.set_kind(Kind::NodeStopped);
+            event.set_topic("/foo");
+            event.set_node("bar");
+        }
+
+        let root = message.get_root_as_reader::<RawReader>().unwrap();
+        root.assert_size(structure);
+
+        assert_eq!(root.data(structure.field("time")), 1);
+        assert_eq!(root.data(structure.field("msgType")), 2);
+        assert_eq!(root.data(structure.field("subscriber")), 3);
+        assert_eq!(root.data(structure.field("kind")), Kind::NodeStopped as u64);
+        assert_eq!(root.text(structure.field("topic")), "/foo");
+        assert_eq!(root.text(structure.field("node")), "bar");
+    }
+
+    #[test]
+    fn kind_matches_schema() {
+        let schema = schema();
+        let kind = schema.enumeration("Kind");
+        assert_eq!(kind.enumerants.len(), 5, "an enumerant was added to Kind");
+
+        assert_eq!(kind.ordinal("channelCreated"), Kind::ChannelCreated as u16);
+        assert_eq!(
+            kind.ordinal("subscriberAttached"),
+            Kind::SubscriberAttached as u16
+        );
+        assert_eq!(
+            kind.ordinal("subscriberDetached"),
+            Kind::SubscriberDetached as u16
+        );
+        assert_eq!(kind.ordinal("nodeStarted"), Kind::NodeStarted as u16);
+        assert_eq!(kind.ordinal("nodeStopped"), Kind::NodeStopped as u16);
+    }
+}
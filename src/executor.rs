@@ -0,0 +1,303 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::ffi;
+
+use std::{collections::VecDeque, mem, sync::Arc};
+
+use parking_lot::Mutex;
+use rayon::ThreadPool;
+
+/// Where and how a node's callbacks are invoked.
+///
+/// Callbacks run on a dedicated thread pool, or the global rayon pool if none is assigned.
+/// Callbacks that share a callback group never run concurrently with each other; callbacks
+/// without a group are reentrant. A callback whose group is busy is deferred until the group is
+/// free rather than waited for, so callbacks may publish to topics that members of their own
+/// group subscribe to. If ffi::MAX_DEFERRED_CALLBACKS callbacks are already deferred, the
+/// callback is dropped.
+#[derive(Clone, Default)]
+pub struct Executor {
+    pool: Option<Arc<ThreadPool>>,
+    group: Option<Arc<CallbackGroup>>,
+}
+
+impl Executor {
+    pub fn new(pool: Option<Arc<ThreadPool>>, group: Option<Arc<CallbackGroup>>) -> Executor {
+        Executor { pool, group }
+    }
+
+    /// Returns an executor on the same callback group, but running on pool instead.
+    pub fn with_pool(&self, pool: Arc<ThreadPool>) -> Executor {
+        Executor {
+            pool: Some(pool),
+            group: self.group.clone(),
+        }
+    }
+
+    /// Runs f on this executor's pool and callback group, blocking until it returns.
+    ///
+    /// If another member of the callback group is running, f is not run. The callback returned
+    /// by defer is instead run once the group is free, unless too many are already deferred.
+    pub fn install_or_defer<R, F, D>(&self, f: F, defer: D) -> Dispatch<R>
+    where
+        R: Send,
+        F: FnOnce() -> R + Send,
+        D: FnOnce() -> Job,
+    {
+        let _guard = match self.group {
+            Some(ref g) => match g.acquire_or_defer(&self.pool, defer) {
+                Dispatch::Ran(guard) => Some(guard),
+                Dispatch::Deferred => return Dispatch::Deferred,
+                Dispatch::Dropped => return Dispatch::Dropped,
+            },
+            None => None,
+        };
+
+        Dispatch::Ran(match self.pool {
+            Some(ref p) => p.install(f),
+            None => f(),
+        })
+    }
+
+    /// Runs f asynchronously on this executor's pool and callback group.
+    ///
+    /// Returns false if f was dropped because too many callbacks are deferred on the group.
+    pub fn spawn<F: FnOnce() + Send + 'static>(&self, f: F) -> bool {
+        let group = match self.group {
+            Some(ref g) => g,
+            None => {
+                spawn_on(&self.pool, f);
+
+                return true;
+            }
+        };
+
+        let mut f = Some(f);
+
+        match group.acquire_or_defer(&self.pool, || Box::new(f.take().unwrap())) {
+            Dispatch::Ran(guard) => {
+                let f = f.take().unwrap();
+
+                spawn_on(&self.pool, move || {
+                    let _guard = guard;
+
+                    f()
+                });
+
+                true
+            }
+            Dispatch::Deferred => true,
+            Dispatch::Dropped => false,
+        }
+    }
+}
+
+/// What became of a callback passed to an executor.
+#[derive(Debug, PartialEq)]
+pub enum Dispatch<R> {
+    /// The callback ran and returned R.
+    Ran(R),
+    /// The callback's group was busy, so it will run once the group is free.
+    Deferred,
+    /// The callback's group was busy and already had MAX_DEFERRED_CALLBACKS waiting.
+    Dropped,
+}
+
+/// Mutual exclusion between the callbacks of one or more nodes.
+///
+/// No thread ever waits for a group. Callbacks that arrive while the group is busy are queued,
+/// up to MAX_DEFERRED_CALLBACKS, and run in order, one at a time, by whichever callback frees the group.
+#[derive(Default)]
+pub struct CallbackGroup {
+    state: Mutex<GroupState>,
+}
+
+#[derive(Default)]
+struct GroupState {
+    busy: bool,
+    deferred: VecDeque<(Option<Arc<ThreadPool>>, Job)>,
+}
+
+/// A callback deferred until its callback group is free.
+pub type Job = Box<dyn FnOnce() + Send>;
+
+impl CallbackGroup {
+    pub fn new() -> CallbackGroup {
+        CallbackGroup::default()
+    }
+
+    /// Marks the group as busy until the returned guard is dropped. If the group is already busy,
+    /// instead queues the callback returned by defer to run on pool.
+    fn acquire_or_defer<D>(
+        self: &Arc<Self>,
+        pool: &Option<Arc<ThreadPool>>,
+        defer: D,
+    ) -> Dispatch<GroupGuard>
+    where
+        D: FnOnce() -> Job,
+    {
+        let mut state = self.state.lock();
+
+        if state.busy {
+            // each holds on to the message it was published with, so this bounds the memory that
+            // a slow group can pin
+            if state.deferred.len() >= ffi::MAX_DEFERRED_CALLBACKS {
+                return Dispatch::Dropped;
+            }
+
+            state.deferred.push_back((pool.clone(), defer()));
+
+            return Dispatch::Deferred;
+        }
+
+        state.busy = true;
+
+        Dispatch::Ran(GroupGuard {
+            group: self.clone(),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn num_deferred(&self) -> usize {
+        self.state.lock().deferred.len()
+    }
+
+    /// Marks the group as free, or hands it to the next deferred callback.
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock();
+
+        let (pool, f) = match state.deferred.pop_front() {
+            Some(d) => d,
+            None => {
+                state.busy = false;
+
+                return;
+            }
+        };
+
+        mem::drop(state);
+
+        let guard = GroupGuard {
+            group: self.clone(),
+        };
+
+        spawn_on(&pool, move || {
+            let _guard = guard;
+
+            f()
+        });
+    }
+}
+
+/// Holds a callback group busy until dropped, including if the callback panics.
+struct GroupGuard {
+    group: Arc<CallbackGroup>,
+}
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        self.group.release();
+    }
+}
+
+fn spawn_on<F: FnOnce() + Send + 'static>(pool: &Option<Arc<ThreadPool>>, f: F) {
+    match pool {
+        Some(p) => p.spawn(f),
+        None => rayon::spawn(f),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    fn wait_for(count: &AtomicUsize, n: usize) {
+        let start = Instant::now();
+
+        while count.load(Ordering::SeqCst) < n {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn busy_group_defers_in_order_then_drops() {
+        let group = Arc::new(CallbackGroup::new());
+        let executor = Executor::new(None, Some(group.clone()));
+
+        let guard = match group.acquire_or_defer(&None, || unreachable!()) {
+            Dispatch::Ran(g) => g,
+            _ => panic!("expected the group to be free"),
+        };
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for i in 0..ffi::MAX_DEFERRED_CALLBACKS {
+            let order = order.clone();
+
+            assert!(executor.spawn(move || order.lock().push(i)));
+        }
+
+        assert!(!executor.spawn(|| panic!("should have been dropped")));
+
+        let res = executor.install_or_defer(|| (), || panic!("should have been dropped"));
+        assert_eq!(res, Dispatch::Dropped);
+
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        mem::drop(guard);
+
+        {
+            let ran = ran.clone();
+
+            executor.spawn(move || {
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        wait_for(&ran, 1);
+
+        let order = order.lock();
+        assert_eq!(order.len(), ffi::MAX_DEFERRED_CALLBACKS);
+        assert!(order.iter().enumerate().all(|(i, &j)| i == j));
+        assert_eq!(group.num_deferred(), 0);
+    }
+
+    #[test]
+    fn install_runs_inline_when_free() {
+        let executor = Executor::new(None, Some(Arc::new(CallbackGroup::new())));
+
+        let res = executor.install_or_defer(|| 42, || panic!("should not be deferred"));
+        assert_eq!(res, Dispatch::Ran(42));
+
+        // the group is free again once f returns
+        let res = executor.install_or_defer(|| 43, || panic!("should not be deferred"));
+        assert_eq!(res, Dispatch::Ran(43));
+    }
+}
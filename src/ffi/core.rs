@@ -59,6 +59,77 @@ pub struct SubscribeParams {
 pub struct AdvertiseParams {
     pub msg_type: MsgType,
     pub topic: StrView,
+    pub size_hint: Index,
+    pub nonblocking: c_int,
+    pub max_in_flight: Index,
+    pub on_complete: Option<PublishCompleteCallback>,
+    pub on_complete_arg: *mut c_void,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Synchronizer {
+    pub impl_ptr: *mut c_void,
+    pub vptr: *const SynchronizerVtbl,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[allow(non_camel_case_types)]
+pub enum SyncPolicy {
+    SRM_SYNC_EXACT,
+    SRM_SYNC_APPROXIMATE,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SyncTopic {
+    pub msg_type: MsgType,
+    pub topic: StrView,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SyncParams {
+    pub topics: *const SyncTopic,
+    pub num_topics: Index,
+    pub policy: c_int,
+    pub slop: Duration,
+    pub queue_size: Index,
+    pub callback: Option<SyncCallback>,
+    pub arg: *mut c_void,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[allow(non_camel_case_types)]
+pub enum LogLevel {
+    SRM_LOG_ERROR = 1,
+    SRM_LOG_WARN,
+    SRM_LOG_INFO,
+    SRM_LOG_DEBUG,
+    SRM_LOG_TRACE,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct LogField {
+    pub key: StrView,
+    pub value: StrView,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TopicStats {
+    pub msg_type: MsgType,
+    pub num_messages: u64,
+    pub num_bytes: u64,
+    pub num_failures: u64,
+    pub message_rate: f64, // messages per second
+    pub num_subscribers: Index,
+    pub num_callbacks: u64,
+    pub total_callback_duration: u64, // nanoseconds
+    pub latency_buckets: [u64; NUM_LATENCY_BUCKETS],
 }
 
 #[repr(C)]
@@ -71,6 +142,65 @@ pub enum ParamType {
     SRM_STRING,
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Timer {
+    pub impl_ptr: *mut c_void,
+    pub vptr: *const TimerVtbl,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TimerParams {
+    pub period: Duration,
+    pub oneshot: c_int,
+    pub callback: Option<TimerCallback>,
+    pub arg: *mut c_void,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union ParamValue {
+    pub integer: isize,
+    pub boolean: c_int,
+    pub real: f64,
+    pub string: StrView,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ParamView {
+    pub tp: c_int,
+    pub value: ParamValue,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ParamAssignment {
+    pub key: StrView,
+    pub value: ParamView,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ParamDecl {
+    pub key: StrView,
+    pub default_value: ParamView,
+    pub description: StrView,
+    pub min: *const f64,
+    pub max: *const f64,
+    pub allowed_values: *const ParamView,
+    pub num_allowed_values: Index,
+    pub read_only: c_int,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ParamWatcher {
+    pub impl_ptr: *mut c_void,
+    pub vptr: *const ParamWatcherVtbl,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct CoreVtbl {
@@ -80,14 +210,27 @@ pub struct CoreVtbl {
         Option<unsafe extern "C" fn(*const c_void, SubscribeParams, *mut Subscriber) -> c_int>,
     pub advertise:
         Option<unsafe extern "C" fn(*const c_void, AdvertiseParams, *mut Publisher) -> c_int>,
+    pub synchronize:
+        Option<unsafe extern "C" fn(*const c_void, SyncParams, *mut Synchronizer) -> c_int>,
+    pub get_topic_stats:
+        Option<unsafe extern "C" fn(*const c_void, StrView, *mut TopicStats) -> c_int>,
 
     pub get_err_msg: Option<unsafe extern "C" fn(*const c_void, c_int) -> StrView>,
 
+    pub now: Option<unsafe extern "C" fn(*const c_void) -> Time>,
+    pub create_timer: Option<unsafe extern "C" fn(*const c_void, TimerParams, *mut Timer) -> c_int>,
+
     pub log_error: Option<unsafe extern "C" fn(*const c_void, StrView) -> c_int>,
     pub log_warn: Option<unsafe extern "C" fn(*const c_void, StrView) -> c_int>,
     pub log_info: Option<unsafe extern "C" fn(*const c_void, StrView) -> c_int>,
     pub log_debug: Option<unsafe extern "C" fn(*const c_void, StrView) -> c_int>,
     pub log_trace: Option<unsafe extern "C" fn(*const c_void, StrView) -> c_int>,
+    pub log: Option<
+        unsafe extern "C" fn(*const c_void, c_int, StrView, *const LogField, Index) -> c_int,
+    >,
+    pub log_throttled:
+        Option<unsafe extern "C" fn(*const c_void, c_int, StrView, Duration, StrView) -> c_int>,
+    pub log_once: Option<unsafe extern "C" fn(*const c_void, c_int, StrView, StrView) -> c_int>,
 
     pub param_type: Option<unsafe extern "C" fn(*const c_void, StrView, *mut c_int) -> c_int>,
 
@@ -95,21 +238,55 @@ pub struct CoreVtbl {
     pub param_geti: Option<unsafe extern "C" fn(*const c_void, StrView, *mut isize) -> c_int>,
     pub param_swapi:
         Option<unsafe extern "C" fn(*const c_void, StrView, isize, *mut isize) -> c_int>,
+    pub param_compare_swapi:
+        Option<unsafe extern "C" fn(*const c_void, StrView, isize, isize, *mut c_int) -> c_int>,
 
     pub param_setb: Option<unsafe extern "C" fn(*const c_void, StrView, c_int) -> c_int>,
     pub param_getb: Option<unsafe extern "C" fn(*const c_void, StrView, *mut c_int) -> c_int>,
     pub param_swapb:
         Option<unsafe extern "C" fn(*const c_void, StrView, c_int, *mut c_int) -> c_int>,
+    pub param_compare_swapb:
+        Option<unsafe extern "C" fn(*const c_void, StrView, c_int, c_int, *mut c_int) -> c_int>,
 
     pub param_setr: Option<unsafe extern "C" fn(*const c_void, StrView, f64) -> c_int>,
     pub param_getr: Option<unsafe extern "C" fn(*const c_void, StrView, *mut f64) -> c_int>,
     pub param_swapr: Option<unsafe extern "C" fn(*const c_void, StrView, f64, *mut f64) -> c_int>,
+    pub param_compare_swapr:
+        Option<unsafe extern "C" fn(*const c_void, StrView, f64, f64, *mut c_int) -> c_int>,
 
     pub param_sets: Option<unsafe extern "C" fn(*const c_void, StrView, StrView) -> c_int>,
     pub param_gets:
         Option<unsafe extern "C" fn(*const c_void, StrView, *mut util::String) -> c_int>,
     pub param_swaps:
         Option<unsafe extern "C" fn(*const c_void, StrView, StrView, *mut util::String) -> c_int>,
+    pub param_compare_swaps:
+        Option<unsafe extern "C" fn(*const c_void, StrView, StrView, StrView, *mut c_int) -> c_int>,
+
+    pub param_transaction:
+        Option<unsafe extern "C" fn(*const c_void, *const ParamAssignment, Index) -> c_int>,
+
+    pub param_list: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            StrView,
+            Option<ParamListCallback>,
+            *mut c_void,
+        ) -> c_int,
+    >,
+    pub param_delete: Option<unsafe extern "C" fn(*const c_void, StrView) -> c_int>,
+
+    pub param_dump: Option<unsafe extern "C" fn(*const c_void, StrView, StrView) -> c_int>,
+
+    pub param_declare: Option<unsafe extern "C" fn(*const c_void, ParamDecl) -> c_int>,
+    pub param_watch: Option<
+        unsafe extern "C" fn(
+            *const c_void,
+            StrView,
+            Option<ParamWatchCallback>,
+            *mut c_void,
+            *mut ParamWatcher,
+        ) -> c_int,
+    >,
 }
 
 #[repr(C)]
@@ -130,3 +307,27 @@ pub struct SubscriberVtbl {
     pub disconnect: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
     pub get_err_msg: Option<unsafe extern "C" fn(*const c_void, c_int) -> StrView>,
 }
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ParamWatcherVtbl {
+    pub get_key: Option<unsafe extern "C" fn(*const c_void) -> StrView>,
+    pub disconnect: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    pub get_err_msg: Option<unsafe extern "C" fn(*const c_void, c_int) -> StrView>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SynchronizerVtbl {
+    pub get_num_topics: Option<unsafe extern "C" fn(*const c_void) -> Index>,
+    pub disconnect: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    pub get_err_msg: Option<unsafe extern "C" fn(*const c_void, c_int) -> StrView>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct TimerVtbl {
+    pub get_period: Option<unsafe extern "C" fn(*const c_void) -> Duration>,
+    pub cancel: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    pub get_err_msg: Option<unsafe extern "C" fn(*const c_void, c_int) -> StrView>,
+}
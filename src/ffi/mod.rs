@@ -24,8 +24,18 @@ use libc::{c_int, c_void, ptrdiff_t};
 
 pub type MsgType = u64;
 pub type Index = ptrdiff_t;
+pub type Time = i64; // nanoseconds since the UNIX epoch
+pub type Duration = i64; // nanoseconds
+pub const NUM_LATENCY_BUCKETS: usize = 8;
+pub const MAX_DEFERRED_CALLBACKS: usize = 256;
 pub type SubscribeCallback = unsafe extern "C" fn(MsgView, *mut c_void) -> c_int;
 pub type PublishFn = unsafe extern "C" fn(MsgBuilder, *mut c_void) -> c_int;
+pub type PublishCompleteCallback = unsafe extern "C" fn(u64, Index, *mut c_void) -> c_int;
+pub type SyncCallback = unsafe extern "C" fn(*const MsgView, Index, *mut c_void) -> c_int;
+pub type TimerCallback = unsafe extern "C" fn(Time, *mut c_void) -> c_int;
+pub type ParamListCallback = unsafe extern "C" fn(StrView, c_int, *mut c_void) -> c_int;
+pub type ParamWatchCallback =
+    unsafe extern "C" fn(StrView, *const ParamView, *const ParamView, *mut c_void) -> c_int;
 
 pub mod core;
 pub mod msg;
@@ -43,12 +43,26 @@ unsafe impl Send for MsgSegmentView {}
 
 unsafe impl Sync for MsgSegmentView {}
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MsgHeader {
+    pub stamp: Time,
+    pub sequence: u64,
+    pub publisher_id: u64,
+    pub publisher_name: StrView,
+}
+
+unsafe impl Send for MsgHeader {}
+
+unsafe impl Sync for MsgHeader {}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct MsgView {
     pub segments: *const MsgSegmentView,
     pub num_segments: Index,
     pub msg_type: MsgType,
+    pub header: MsgHeader,
 }
 
 unsafe impl Send for MsgView {}
@@ -0,0 +1,157 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::{
+    core::ParamType,
+    ffi::NUM_LATENCY_BUCKETS,
+    static_core::{Param, StaticCore},
+    stats::LATENCY_BUCKET_BOUNDS,
+};
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+/// Describes the statistics of each channel as a YAML document.
+pub fn describe_topics(core: &StaticCore) -> String {
+    let descriptions: BTreeMap<_, _> = core
+        .topic_stats()
+        .into_iter()
+        .map(|(name, stats)| {
+            let description = TopicDescription {
+                msg_type: format!("{:#018x}", stats.msg_type),
+                messages: stats.channel.messages,
+                bytes: stats.channel.bytes,
+                failures: stats.channel.failures,
+                rate: stats.channel.rate,
+                subscribers: stats
+                    .subscribers
+                    .iter()
+                    .map(|(_, owner, s)| SubscriberDescription {
+                        node: owner.clone(),
+                        calls: s.calls,
+                        failures: s.failures,
+                        mean_duration: s.total_duration.checked_div(s.calls).unwrap_or(0),
+                        latency: describe_latency(&s.buckets),
+                    })
+                    .collect(),
+                segment_pool: SegmentPoolDescription {
+                    hits: stats.segment_pool.hits,
+                    misses: stats.segment_pool.misses,
+                    cached_segments: stats.segment_pool.cached_segments,
+                },
+            };
+
+            (name, description)
+        })
+        .collect();
+
+    serde_yaml::to_string(&descriptions).unwrap()
+}
+
+/// Labels each histogram bucket with its upper bound.
+fn describe_latency(buckets: &[u64; NUM_LATENCY_BUCKETS]) -> Vec<(String, u64)> {
+    buckets
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| match LATENCY_BUCKET_BOUNDS.get(i) {
+            Some(bound) => (format!("<= {}ns", bound), count),
+            None => ("+inf".to_string(), count),
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct TopicDescription {
+    msg_type: String,
+    messages: u64,
+    bytes: u64,
+    failures: u64,
+    rate: f64,
+    subscribers: Vec<SubscriberDescription>,
+    segment_pool: SegmentPoolDescription,
+}
+
+#[derive(Serialize)]
+struct SubscriberDescription {
+    node: String,
+    calls: u64,
+    failures: u64,
+    mean_duration: u64, // nanoseconds
+    latency: Vec<(String, u64)>,
+}
+
+#[derive(Serialize)]
+struct SegmentPoolDescription {
+    hits: u64,
+    misses: u64,
+    cached_segments: usize,
+}
+
+/// Describes all declared parameters as a YAML document.
+pub fn describe_params(core: &StaticCore) -> String {
+    let descriptions: BTreeMap<_, _> = core
+        .param_decls()
+        .into_iter()
+        .map(|(key, decl)| {
+            let description = ParamDescription {
+                tp: type_name(&decl.get_type()),
+                value: core.param_value(&key),
+                default: decl.default,
+                min: decl.min,
+                max: decl.max,
+                allowed: decl.allowed,
+                read_only: decl.read_only,
+                description: decl.description,
+            };
+
+            (key, description)
+        })
+        .collect();
+
+    serde_yaml::to_string(&descriptions).unwrap()
+}
+
+#[derive(Serialize)]
+struct ParamDescription {
+    #[serde(rename = "type")]
+    tp: &'static str,
+    value: Option<Param>,
+    default: Param,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed: Option<Vec<Param>>,
+    read_only: bool,
+    description: String,
+}
+
+fn type_name(tp: &ParamType) -> &'static str {
+    match tp {
+        ParamType::Integer => "integer",
+        ParamType::Boolean => "boolean",
+        ParamType::Real => "real",
+        ParamType::String => "string",
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+// builders for capnp/log.capnp, in the form generated by capnpc-rust
+pub mod log_record {
+    use capnp::{
+        private::layout,
+        traits::{FromPointerBuilder, FromStructBuilder, HasStructSize, HasTypeId, ToU16},
+        Result,
+    };
+
+    pub const TYPE_ID: u64 = 0xc26f_05b2_4e6c_fc9d;
+
+    pub struct Builder<'a> {
+        builder: layout::StructBuilder<'a>,
+    }
+
+    impl<'a> HasTypeId for Builder<'a> {
+        fn type_id() -> u64 {
+            TYPE_ID
+        }
+    }
+
+    impl<'a> HasStructSize for Builder<'a> {
+        fn struct_size() -> layout::StructSize {
+            STRUCT_SIZE
+        }
+    }
+
+    impl<'a> FromStructBuilder<'a> for Builder<'a> {
+        fn new(builder: layout::StructBuilder<'a>) -> Builder<'a> {
+            Builder { builder }
+        }
+    }
+
+    impl<'a> FromPointerBuilder<'a> for Builder<'a> {
+        fn init_pointer(builder: layout::PointerBuilder<'a>, _size: u32) -> Builder<'a> {
+            FromStructBuilder::new(builder.init_struct(STRUCT_SIZE))
+        }
+
+        fn get_from_pointer(builder: layout::PointerBuilder<'a>) -> Result<Builder<'a>> {
+            Ok(FromStructBuilder::new(
+                builder.get_struct(STRUCT_SIZE, ::std::ptr::null())?,
+            ))
+        }
+    }
+
+    impl<'a> Builder<'a> {
+        pub fn set_time(&mut self, value: i64) {
+            self.builder.set_data_field::<i64>(0, value);
+        }
+
+        pub fn set_level(&mut self, value: Level) {
+            self.builder.set_data_field::<u16>(4, value.to_u16());
+        }
+
+        pub fn set_target(&mut self, value: &str) {
+            self.builder.get_pointer_field(0).set_text(value);
+        }
+
+        pub fn set_message(&mut self, value: &str) {
+            self.builder.get_pointer_field(1).set_text(value);
+        }
+
+        pub fn init_fields(&mut self, size: u32) -> FieldList<'a> {
+            FieldList {
+                builder: self
+                    .builder
+                    .get_pointer_field(2)
+                    .init_struct_list(size, field::STRUCT_SIZE),
+            }
+        }
+    }
+
+    const STRUCT_SIZE: layout::StructSize = layout::StructSize {
+        data: 2,
+        pointers: 3,
+    };
+
+    pub struct FieldList<'a> {
+        builder: layout::ListBuilder<'a>,
+    }
+
+    impl<'a> FieldList<'a> {
+        pub fn get(&self, index: u32) -> field::Builder<'a> {
+            assert!(index < self.builder.len());
+
+            FromStructBuilder::new(self.builder.get_struct_element(index))
+        }
+    }
+
+    #[repr(u16)]
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Level {
+        Error = 0,
+        Warn = 1,
+        Info = 2,
+        Debug = 3,
+        Trace = 4,
+    }
+
+    impl ToU16 for Level {
+        fn to_u16(self) -> u16 {
+            self as u16
+        }
+    }
+
+    pub mod field {
+        use capnp::{private::layout, traits::FromStructBuilder};
+
+        pub struct Builder<'a> {
+            builder: layout::StructBuilder<'a>,
+        }
+
+        impl<'a> FromStructBuilder<'a> for Builder<'a> {
+            fn new(builder: layout::StructBuilder<'a>) -> Builder<'a> {
+                Builder { builder }
+            }
+        }
+
+        impl<'a> Builder<'a> {
+            pub fn set_key(&mut self, value: &str) {
+                self.builder.get_pointer_field(0).set_text(value);
+            }
+
+            pub fn set_value(&mut self, value: &str) {
+                self.builder.get_pointer_field(1).set_text(value);
+            }
+        }
+
+        pub const STRUCT_SIZE: layout::StructSize = layout::StructSize {
+            data: 0,
+            pointers: 2,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::log_record::{self, Level};
+    use crate::capnp_schema::{RawReader, Schema};
+
+    use capnp::message;
+
+    fn schema() -> Schema {
+        Schema::parse(include_str!("../capnp/log.capnp"))
+    }
+
+    #[test]
+    fn log_record_matches_schema() {
+        let schema = schema();
+        let structure = schema.structure("LogRecord");
+        let field = schema.structure("Field");
+        assert_eq!(log_record::TYPE_ID, structure.id);
+        assert_eq!(structure.fields.len(), 5, "a field was added to LogRecord");
+        assert_eq!(field.fields.len(), 2, "a field was added to Field");
+
+        let mut message = message::Builder::new_default();
+
+        {
+            let mut record = message.init_root::<log_record::Builder>();
+            record.set_time(1);
+            record.set_level(Level::Debug);
+            record.set_target("foo");
+            record.set_message("bar");
+
+            let fields = record.init_fields(2);
+            fields.get(0).set_key("baz");
+            fields.get(0).set_value("qux");
+            fields.get(1).set_key("quux");
+            fields.get(1).set_value("corge");
+        }
+
+        let root = message.get_root_as_reader::<RawReader>().unwrap();
+        root.assert_size(structure);
+
+        assert_eq!(root.data(structure.field("time")), 1);
+        assert_eq!(root.data(structure.field("level")), Level::Debug as u64);
+        assert_eq!(root.text(structure.field("target")), "foo");
+        assert_eq!(root.text(structure.field("message")), "bar");
+
+        let fields = root.struct_list(structure.field("fields"));
+        assert_eq!(fields.len(), 2);
+
+        for (element, &(key, value)) in fields.iter().zip(&[("baz", "qux"), ("quux", "corge")]) {
+            element.assert_size(field);
+            assert_eq!(element.text(field.field("key")), key);
+            assert_eq!(element.text(field.field("value")), value);
+        }
+    }
+
+    #[test]
+    fn level_matches_schema() {
+        let schema = schema();
+        let level = schema.enumeration("Level");
+        assert_eq!(level.enumerants.len(), 5, "an enumerant was added to Level");
+
+        assert_eq!(level.ordinal("error"), Level::Error as u16);
+        assert_eq!(level.ordinal("warn"), Level::Warn as u16);
+        assert_eq!(level.ordinal("info"), Level::Info as u16);
+        assert_eq!(level.ordinal("debug"), Level::Debug as u16);
+        assert_eq!(level.ordinal("trace"), Level::Trace as u16);
+    }
+}
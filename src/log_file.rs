@@ -0,0 +1,280 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::{
+    cmp,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Deserialize;
+
+/// Settings for writing logs to files, from the node graph's `log_file` or `--log-dir`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct LogFileConfig {
+    /// Directory that log files are written to, created if it doesn't exist.
+    pub dir: PathBuf,
+    /// Starts a new file once the current one is at least this many bytes long.
+    pub max_size: Option<u64>,
+    /// Starts a new file once the current one is this many seconds old.
+    pub max_age: Option<u64>,
+    /// Deletes the oldest log files in dir so that at most this many remain.
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+    /// Keeps writing to stderr as well.
+    #[serde(default = "default_stderr")]
+    pub stderr: bool,
+}
+
+impl LogFileConfig {
+    pub fn new(dir: PathBuf) -> LogFileConfig {
+        LogFileConfig {
+            dir,
+            max_size: None,
+            max_age: None,
+            max_files: default_max_files(),
+            stderr: default_stderr(),
+        }
+    }
+}
+
+fn default_max_files() -> usize {
+    10
+}
+
+fn default_stderr() -> bool {
+    true
+}
+
+/// A log file that rotates according to a LogFileConfig.
+///
+/// Files are named `srm-<start time>-<pid>-<index>.log`, so each run writes its own files and
+/// names sort by age.
+pub struct LogFile {
+    config: LogFileConfig,
+    prefix: String,
+    index: usize,
+    file: File,
+    size: u64,
+    opened: Instant,
+}
+
+const FILE_PREFIX: &str = "srm-";
+const FILE_SUFFIX: &str = ".log";
+
+impl LogFile {
+    pub fn create(config: LogFileConfig) -> io::Result<LogFile> {
+        fs::create_dir_all(&config.dir)?;
+
+        let start = humantime::format_rfc3339_seconds(SystemTime::now())
+            .to_string()
+            .replace(':', "");
+        let prefix = format!("{}{}-{}", FILE_PREFIX, start, process::id());
+        let file = File::create(file_path(&config.dir, &prefix, 0))?;
+
+        let log_file = LogFile {
+            config,
+            prefix,
+            index: 0,
+            file,
+            size: 0,
+            opened: Instant::now(),
+        };
+        log_file.prune()?;
+
+        Ok(log_file)
+    }
+
+    pub fn writes_stderr(&self) -> bool {
+        self.config.stderr
+    }
+
+    pub fn path(&self) -> PathBuf {
+        file_path(&self.config.dir, &self.prefix, self.index)
+    }
+
+    /// Writes a batch of messages, rotating afterwards if the current file is full or too old.
+    pub fn write(&mut self, messages: &str) -> io::Result<()> {
+        self.file.write_all(messages.as_bytes())?;
+        self.size += messages.len() as u64;
+
+        if self.should_rotate() {
+            self.rotate()?;
+        }
+
+        Ok(())
+    }
+
+    fn should_rotate(&self) -> bool {
+        let too_big = self.config.max_size.is_some_and(|s| self.size >= s);
+        let too_old = self
+            .config
+            .max_age
+            .is_some_and(|a| self.opened.elapsed() >= Duration::from_secs(a));
+
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        self.index += 1;
+        self.file = File::create(self.path())?;
+        self.size = 0;
+        self.opened = Instant::now();
+
+        self.prune()
+    }
+
+    /// Deletes the oldest log files in the directory until at most max_files remain.
+    fn prune(&self) -> io::Result<()> {
+        let mut paths: Vec<_> = fs::read_dir(&self.config.dir)?
+            .filter_map(Result::ok)
+            .filter(|e| {
+                let name = e.file_name();
+                let name = name.to_string_lossy();
+
+                name.starts_with(FILE_PREFIX) && name.ends_with(FILE_SUFFIX)
+            })
+            .map(|e| e.path())
+            .collect();
+
+        let max_files = cmp::max(self.config.max_files, 1); // never delete the current file
+
+        if paths.len() <= max_files {
+            return Ok(());
+        }
+
+        paths.sort();
+        let num_to_delete = paths.len() - max_files;
+
+        for path in paths.iter().take(num_to_delete) {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn file_path(dir: &Path, prefix: &str, index: usize) -> PathBuf {
+    dir.join(format!("{}-{:04}{}", prefix, index, FILE_SUFFIX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("srm-log-file-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+    fn log_files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    #[test]
+    fn rotates_when_full() {
+        let dir = test_dir("rotate");
+        let mut config = LogFileConfig::new(dir.clone());
+        config.max_size = Some(8);
+
+        let mut log_file = LogFile::create(config).unwrap();
+        let first = log_file.path();
+
+        log_file.write("1234").unwrap();
+        assert_eq!(log_file.path(), first);
+
+        log_file.write("5678").unwrap();
+        assert_ne!(log_file.path(), first);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "12345678");
+
+        log_file.write("9").unwrap();
+        log_file.file.flush().unwrap();
+        assert_eq!(fs::read_to_string(log_file.path()).unwrap(), "9");
+        assert_eq!(log_files(&dir).len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prunes_oldest_files() {
+        let dir = test_dir("prune");
+        fs::create_dir_all(&dir).unwrap();
+
+        for name in &[
+            "srm-0000-old-0000.log",
+            "srm-0001-old-0000.log",
+            "other.txt",
+        ] {
+            File::create(dir.join(name)).unwrap();
+        }
+
+        let mut config = LogFileConfig::new(dir.clone());
+        config.max_size = Some(1);
+        config.max_files = 2;
+
+        let mut log_file = LogFile::create(config).unwrap();
+        let first = log_file.path();
+        let files = log_files(&dir);
+        assert_eq!(files.len(), 3);
+        assert!(files.contains(&"other.txt".to_string()));
+        assert!(files.contains(&"srm-0001-old-0000.log".to_string()));
+
+        log_file.write("x").unwrap();
+        let files = log_files(&dir);
+        assert_eq!(files.len(), 3);
+        assert!(!files.contains(&"srm-0001-old-0000.log".to_string()));
+        assert!(first.exists());
+        assert!(log_file.path().exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn never_prunes_current_file() {
+        let dir = test_dir("current");
+        let mut config = LogFileConfig::new(dir.clone());
+        config.max_size = Some(1);
+        config.max_files = 0;
+
+        let mut log_file = LogFile::create(config).unwrap();
+        log_file.write("x").unwrap();
+        log_file.write("y").unwrap();
+
+        assert_eq!(log_files(&dir).len(), 1);
+        assert!(log_file.path().exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -21,124 +21,422 @@
 // SOFTWARE.
 
 use std::{
-    env, mem,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
+    cell::Cell,
+    cmp,
+    collections::VecDeque,
+    env,
+    fmt::Write,
+    io, mem, panic,
+    sync::{Arc, OnceLock},
     thread::{self, JoinHandle},
     time::{Duration, SystemTime},
 };
 
-use log::{info, LevelFilter, Log, Metadata, Record};
-use parking_lot::Mutex;
+use super::log_file::{LogFile, LogFileConfig};
+
+use hashbrown::HashMap;
+use log::{
+    info,
+    kv::{self, Key, Value, VisitSource},
+    warn, Level, LevelFilter, Log, Metadata, Record,
+};
+use parking_lot::{Condvar, Mutex, RwLock};
 
 pub fn init() {
-    init_with_polling_period(DEFAULT_PERIOD);
-}
+    let (format, unparsed_format) = get_format();
+    let (capacity, unparsed_capacity) = get_capacity();
+    let (overflow, unparsed_overflow) = get_overflow();
+    let spec = env::var("RUST_LOG").ok();
+    let (filter, unparsed_directives) = Filter::parse(spec.as_deref().unwrap_or(""));
+    let filter = Arc::new(filter);
 
-pub fn init_with_polling_period(polling_period: Duration) {
-    let logger = Box::new(AsyncLogger::with_polling_period(polling_period));
-    let (max_level, defaulted) = get_max_level();
+    let max_level = filter.max_level();
+    let logger = Box::new(AsyncLogger::new(format, filter.clone(), capacity, overflow));
 
     log::set_boxed_logger(logger).unwrap();
     log::set_max_level(max_level);
+    FILTER.set(filter).ok().unwrap();
 
-    if let Some(maybe_unparsed) = defaulted {
-        if let Some(unparsed) = maybe_unparsed {
-            info!(
-                "couldn't parse '{}' as a maximum logging level, using INFO",
-                unparsed
-            );
-        } else {
-            info!("no maximum logging level provided, using INFO");
+    let sink = SINK.get().unwrap().clone();
+    panic::set_hook(flushing_hook(sink, panic::take_hook()));
+
+    if spec.is_none() {
+        info!("no maximum logging level provided, using INFO");
+    }
+
+    for unparsed in unparsed_directives.iter() {
+        warn!(
+            "couldn't parse '{}' as a logging directive, ignoring it",
+            unparsed
+        );
+    }
+
+    if let Some(unparsed) = unparsed_format {
+        info!("couldn't parse '{}' as a log format, using text", unparsed);
+    }
+
+    if let Some(unparsed) = unparsed_capacity {
+        warn!(
+            "couldn't parse '{}' as a positive log queue capacity, using {}",
+            unparsed, DEFAULT_CAPACITY
+        );
+    }
+
+    if let Some(unparsed) = unparsed_overflow {
+        warn!(
+            "couldn't parse '{}' as a log overflow policy, dropping the newest messages",
+            unparsed
+        );
+    }
+}
+
+type PanicHook = Box<dyn Fn(&panic::PanicHookInfo) + Send + Sync>;
+
+/// Wraps hook so that queued messages are written first, so that the panic message follows the
+/// records that led up to it.
+fn flushing_hook(sink: Arc<Sink>, hook: PanicHook) -> PanicHook {
+    Box::new(move |info| {
+        sink.try_pop(PANIC_FLUSH_TIMEOUT);
+        hook(info);
+    })
+}
+
+/// How log records are written.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Text,
+    Json, // one JSON object per line
+}
+
+// reads SRM_LOG_FORMAT, returning the unparsed value if it was neither "text" nor "json"
+fn get_format() -> (Format, Option<String>) {
+    let format_str = match env::var("SRM_LOG_FORMAT") {
+        Ok(f) => f,
+        Err(_) => return (Format::Text, None),
+    };
+
+    match format_str.to_lowercase().as_str() {
+        "text" => (Format::Text, None),
+        "json" => (Format::Json, None),
+        _ => (Format::Text, Some(format_str)),
+    }
+}
+
+impl Format {
+    fn apply(self, record: &Record) -> String {
+        match self {
+            Format::Text => AsyncLogger::format_text(record),
+            Format::Json => AsyncLogger::format_json(record),
         }
     }
 }
 
-// returns None if not defaulted
-// returns Some(None) if defaulted because no env var was found
-// returns Some(Some(s)) if defaulted because env var couldn't be parsed
-fn get_max_level() -> (LevelFilter, Option<Option<String>>) {
-    let level_str = match env::var("RUST_LOG") {
-        Ok(l) => l,
-        Err(_) => return (DEFAULT_LOG_LEVEL, Some(None)),
+/// What happens to a message logged while the queue is full.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Overflow {
+    DropNewest,
+    DropOldest,
+    Block, // until the sink thread has written the queue out
+}
+
+// reads SRM_LOG_CAPACITY, returning the unparsed value if it wasn't a positive integer
+fn get_capacity() -> (usize, Option<String>) {
+    let capacity_str = match env::var("SRM_LOG_CAPACITY") {
+        Ok(c) => c,
+        Err(_) => return (DEFAULT_CAPACITY, None),
+    };
+
+    match capacity_str.trim().parse() {
+        Ok(c) if c > 0 => (c, None),
+        _ => (DEFAULT_CAPACITY, Some(capacity_str)),
+    }
+}
+
+// reads SRM_LOG_OVERFLOW, returning the unparsed value if it wasn't a known policy
+fn get_overflow() -> (Overflow, Option<String>) {
+    let overflow_str = match env::var("SRM_LOG_OVERFLOW") {
+        Ok(o) => o,
+        Err(_) => return (Overflow::DropNewest, None),
     };
 
-    match level_str.to_lowercase().parse() {
-        Ok(l) => (l, None),
-        Err(_) => (DEFAULT_LOG_LEVEL, Some(Some(level_str))),
+    match overflow_str.to_lowercase().as_str() {
+        "drop_newest" => (Overflow::DropNewest, None),
+        "drop_oldest" => (Overflow::DropOldest, None),
+        "block" => (Overflow::Block, None),
+        _ => (Overflow::DropNewest, Some(overflow_str)),
     }
 }
 
 const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
 
+/// The number of formatted messages that may be queued before the overflow policy applies.
+const DEFAULT_CAPACITY: usize = 8192;
+
+/// How long a panicking thread waits for a concurrent write to finish before giving up on
+/// flushing.
+const PANIC_FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+static FILTER: OnceLock<Arc<Filter>> = OnceLock::new();
+
+static SINK: OnceLock<Arc<Sink>> = OnceLock::new();
+
+type RecordPublisher = Box<dyn Fn(&Record) + Send + Sync>;
+
+static RECORD_PUBLISHER: OnceLock<RecordPublisher> = OnceLock::new();
+
+thread_local! {
+    // set while publishing a record, so that records logged by subscribers aren't published
+    static PUBLISHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Passes every record that isn't filtered out to publisher, on the thread that logged it.
+///
+/// Records logged from within publisher or while_publishing are written but not passed to it.
+/// Only the first publisher is kept.
+pub fn set_record_publisher(publisher: RecordPublisher) {
+    let _ = RECORD_PUBLISHER.set(publisher);
+}
+
+/// Runs f without passing the records it logs to the record publisher.
+///
+/// For code that handles published records, which would otherwise be fed its own logs.
+pub fn while_publishing<R, F: FnOnce() -> R>(f: F) -> R {
+    struct Reset(bool);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            PUBLISHING.with(|p| p.set(self.0));
+        }
+    }
+
+    let _reset = Reset(PUBLISHING.with(|p| p.replace(true)));
+
+    f()
+}
+
+/// Starts writing logs to rotating files as configured, in addition to or instead of stderr.
+///
+/// Messages already queued are written to the new file. Does nothing if the logger hasn't been
+/// initialized.
+pub fn set_log_file(config: LogFileConfig) -> io::Result<()> {
+    let sink = match SINK.get() {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+
+    let file = LogFile::create(config)?;
+    info!("writing logs to '{}'", file.path().display());
+
+    sink.outputs.lock().file = Some(file);
+
+    Ok(())
+}
+
+/// Overrides the level of records whose target is exactly target, or removes the override if
+/// level is None. Nodes log with their name as the target.
+///
+/// Does nothing if the logger hasn't been initialized.
+pub fn set_target_level(target: &str, level: Option<LevelFilter>) {
+    let filter = match FILTER.get() {
+        Some(f) => f,
+        None => return,
+    };
+
+    {
+        let mut overrides = filter.overrides.write();
+
+        match level {
+            Some(l) => overrides.insert(target.to_string(), l),
+            None => overrides.remove(target),
+        };
+    }
+
+    // the log macros only check the global maximum before calling into the logger
+    log::set_max_level(filter.max_level());
+}
+
+/// Decides which records are logged.
+///
+/// Per-target overrides take precedence over directives, which take precedence over the default.
+struct Filter {
+    default: LevelFilter,
+    directives: Vec<(String, LevelFilter)>, // sorted so that longer targets match first
+    overrides: RwLock<HashMap<String, LevelFilter>>,
+}
+
+impl Filter {
+    /// Parses env_logger-style directives, e.g. `info,lidar=trace,srm::static_core=debug`.
+    ///
+    /// A directive is a level, a target or `target=level`; a bare target is logged at any level.
+    /// Returns the directives that couldn't be parsed, which are otherwise ignored.
+    fn parse(spec: &str) -> (Filter, Vec<String>) {
+        let mut default = DEFAULT_LOG_LEVEL;
+        let mut directives = Vec::new();
+        let mut unparsed = Vec::new();
+
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let mut parts = directive.splitn(2, '=');
+            let target = parts.next().unwrap();
+
+            match parts.next() {
+                None => match target.parse() {
+                    Ok(l) => default = l,
+                    Err(_) => directives.push((target.to_string(), LevelFilter::Trace)),
+                },
+                Some(level) => match level.parse() {
+                    Ok(l) if !target.is_empty() => directives.push((target.to_string(), l)),
+                    _ => unparsed.push(directive.to_string()),
+                },
+            }
+        }
+
+        directives.sort_by_key(|(t, _): &(String, _)| cmp::Reverse(t.len()));
+
+        let filter = Filter {
+            default,
+            directives,
+            overrides: RwLock::new(HashMap::new()),
+        };
+
+        (filter, unparsed)
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        if let Some(&l) = self.overrides.read().get(target) {
+            return l;
+        }
+
+        self.directives
+            .iter()
+            .find(|(t, _)| target.starts_with(t.as_str()))
+            .map_or(self.default, |&(_, l)| l)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        let overrides = self.overrides.read();
+
+        self.directives
+            .iter()
+            .map(|&(_, l)| l)
+            .chain(overrides.values().cloned())
+            .fold(self.default, cmp::max)
+    }
+}
+
 struct AsyncLogger {
+    format: Format,
+    filter: Arc<Filter>,
     sink: Arc<Sink>,
-    keep_running: Arc<AtomicBool>,
     sink_thread: Option<JoinHandle<()>>, // so we can move out of sink_thread and join
 }
 
-const DEFAULT_PERIOD: Duration = Duration::from_millis(100);
-
 impl AsyncLogger {
-    fn with_polling_period(polling_period: Duration) -> AsyncLogger {
-        let sink = Arc::new(Sink::new());
-        let keep_running = Arc::new(AtomicBool::new(true));
+    fn new(
+        format: Format,
+        filter: Arc<Filter>,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> AsyncLogger {
+        let sink = Arc::new(Sink::new(format, capacity, overflow));
+        SINK.set(sink.clone()).ok().unwrap();
 
         let child_sink = sink.clone();
-        let child_keep_running = keep_running.clone();
 
-        let sink_thread = Some(thread::spawn(move || {
-            while child_keep_running.load(Ordering::Relaxed) {
-                child_sink.pop();
-                thread::sleep(polling_period);
-            }
-        }));
+        let sink_thread = Some(
+            thread::Builder::new()
+                .name("srm-logger".to_string())
+                .spawn(move || child_sink.run())
+                .expect("couldn't spawn logger thread"),
+        );
 
         AsyncLogger {
+            format,
+            filter,
             sink,
-            keep_running,
             sink_thread,
         }
     }
 
-    fn format(record: &Record) -> String {
+    fn format_text(record: &Record) -> String {
+        let mut formatted = match record.module_path() {
+            Some(module) if module != record.target() => format!(
+                "[{} {} {}/{}] {}",
+                humantime::format_rfc3339(SystemTime::now()),
+                record.level(),
+                module,
+                record.target(),
+                record.args()
+            ),
+            _ => format!(
+                "[{} {} {}] {}",
+                humantime::format_rfc3339(SystemTime::now()),
+                record.level(),
+                record.target(),
+                record.args()
+            ),
+        };
+
+        // writing to a String can't fail
+        let _ = record.key_values().visit(&mut TextFields(&mut formatted));
+        formatted.push('\n');
+
+        formatted
+    }
+
+    fn format_json(record: &Record) -> String {
+        let mut formatted = String::from("{\"time\":");
+        write_json_str(
+            &mut formatted,
+            &humantime::format_rfc3339(SystemTime::now()).to_string(),
+        );
+        formatted.push_str(",\"level\":");
+        write_json_str(&mut formatted, record.level().as_str());
+        formatted.push_str(",\"target\":");
+        write_json_str(&mut formatted, record.target());
+
         if let Some(module) = record.module_path() {
-            if module != record.target() {
-                return format!(
-                    "[{} {} {}/{}] {}\n",
-                    humantime::format_rfc3339(SystemTime::now()),
-                    record.level(),
-                    module,
-                    record.target(),
-                    record.args()
-                );
-            }
+            formatted.push_str(",\"module\":");
+            write_json_str(&mut formatted, module);
         }
 
-        format!(
-            "[{} {} {}] {}\n",
-            humantime::format_rfc3339(SystemTime::now()),
-            record.level(),
-            record.target(),
-            record.args()
-        )
+        formatted.push_str(",\"msg\":");
+        write_json_str(&mut formatted, &record.args().to_string());
+
+        let mut fields = JsonFields {
+            formatted: &mut formatted,
+            first: true,
+        };
+        let _ = record.key_values().visit(&mut fields);
+
+        if !fields.first {
+            formatted.push('}');
+        }
+
+        formatted.push_str("}\n");
+
+        formatted
     }
 }
 
 impl Log for AsyncLogger {
-    fn enabled(&self, _: &Metadata) -> bool {
-        true
-        // metadata.level() >= Level::Info
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level(metadata.target())
     }
 
     fn log(&self, record: &Record) {
+        // checked before formatting so that filtered records cost next to nothing
         if !self.enabled(record.metadata()) {
             return;
         }
 
-        self.sink.push(AsyncLogger::format(record))
+        if let Some(publisher) = RECORD_PUBLISHER.get() {
+            if !PUBLISHING.with(|p| p.replace(true)) {
+                publisher(record);
+                PUBLISHING.with(|p| p.set(false));
+            }
+        }
+
+        self.sink.push(self.format.apply(record))
     }
 
     fn flush(&self) {
@@ -148,54 +446,590 @@ impl Log for AsyncLogger {
 
 impl Drop for AsyncLogger {
     fn drop(&mut self) {
-        self.keep_running.store(false, Ordering::Relaxed);
+        self.sink.stop();
         self.sink_thread.take().unwrap().join().unwrap();
     }
 }
 
+/// Appends ` key=value` for each field, quoting values that contain whitespace.
+struct TextFields<'a>(&'a mut String);
+
+impl<'a, 'kvs> VisitSource<'kvs> for TextFields<'a> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = value.to_string();
+
+        if value.is_empty() || value.contains(char::is_whitespace) {
+            write!(self.0, " {}={:?}", key, value)?;
+        } else {
+            write!(self.0, " {}={}", key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Appends `,"fields":{...}`, leaving the object open so the caller can close it if any field
+/// was written. Numbers and booleans are written as such, everything else as a string.
+struct JsonFields<'a> {
+    formatted: &'a mut String,
+    first: bool,
+}
+
+impl<'a, 'kvs> VisitSource<'kvs> for JsonFields<'a> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        if self.first {
+            self.formatted.push_str(",\"fields\":{");
+            self.first = false;
+        } else {
+            self.formatted.push(',');
+        }
+
+        write_json_str(self.formatted, key.as_str());
+        self.formatted.push(':');
+
+        if let Some(b) = value.to_bool() {
+            write!(self.formatted, "{}", b)?;
+        } else if let Some(i) = value.to_i64() {
+            write!(self.formatted, "{}", i)?;
+        } else if let Some(u) = value.to_u64() {
+            write!(self.formatted, "{}", u)?;
+        } else if let Some(f) = value.to_f64().filter(|f| f.is_finite()) {
+            write!(self.formatted, "{}", f)?;
+        } else {
+            write_json_str(self.formatted, &value.to_string());
+        }
+
+        Ok(())
+    }
+}
+
+fn write_json_str(formatted: &mut String, s: &str) {
+    formatted.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => formatted.push_str("\\\""),
+            '\\' => formatted.push_str("\\\\"),
+            '\n' => formatted.push_str("\\n"),
+            '\r' => formatted.push_str("\\r"),
+            '\t' => formatted.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(formatted, "\\u{:04x}", c as u32);
+            }
+            c => formatted.push(c),
+        }
+    }
+
+    formatted.push('"');
+}
+
+/// A bounded queue of formatted messages, written out by the sink thread as soon as any arrive.
 struct Sink {
-    queue: Mutex<String>,
+    format: Format,
+    capacity: usize,
+    overflow: Overflow,
+    queue: Mutex<Queue>,
+    ready: Condvar,    // notified when a message is queued or the sink is stopped
+    not_full: Condvar, // notified when the queue is emptied
+    outputs: Mutex<Outputs>,
+}
+
+struct Queue {
+    messages: VecDeque<String>,
+    num_dropped: usize, // since the queue was last written out
+    stopped: bool,
+}
+
+/// Where batches of messages are written.
+struct Outputs {
+    file: Option<LogFile>,
+}
+
+impl Outputs {
+    fn write(&mut self, messages: &str) {
+        if messages.is_empty() {
+            return;
+        }
+
+        let file = match self.file {
+            Some(ref mut f) => f,
+            None => {
+                eprint!("{}", messages);
+
+                return;
+            }
+        };
+
+        if file.writes_stderr() {
+            eprint!("{}", messages);
+        }
+
+        if let Err(e) = file.write(messages) {
+            // fall back to stderr so that nothing is lost
+            eprintln!(
+                "couldn't write to log file '{}', writing to stderr: {}",
+                file.path().display(),
+                e
+            );
+
+            if !file.writes_stderr() {
+                eprint!("{}", messages);
+            }
+
+            self.file = None;
+        }
+    }
 }
 
 impl Sink {
-    fn new() -> Sink {
+    fn new(format: Format, capacity: usize, overflow: Overflow) -> Sink {
         Sink {
-            queue: Mutex::new(String::new()),
+            format,
+            capacity,
+            overflow,
+            queue: Mutex::new(Queue {
+                messages: VecDeque::with_capacity(capacity),
+                num_dropped: 0,
+                stopped: false,
+            }),
+            ready: Condvar::new(),
+            not_full: Condvar::new(),
+            outputs: Mutex::new(Outputs { file: None }),
         }
     }
 
     fn push(&self, msg: String) {
         let mut queue = self.queue.lock();
-        queue.push_str(&msg);
+
+        if queue.messages.len() >= self.capacity {
+            match self.overflow {
+                Overflow::DropNewest => {
+                    queue.num_dropped += 1;
+
+                    return;
+                }
+                Overflow::DropOldest => {
+                    queue.messages.pop_front();
+                    queue.num_dropped += 1;
+                }
+                Overflow::Block => {
+                    // nothing will empty the queue once the sink thread has stopped
+                    while queue.messages.len() >= self.capacity && !queue.stopped {
+                        self.not_full.wait(&mut queue);
+                    }
+
+                    if queue.messages.len() >= self.capacity {
+                        queue.num_dropped += 1;
+
+                        return;
+                    }
+                }
+            }
+        }
+
+        queue.messages.push_back(msg);
+        self.ready.notify_one();
+    }
+
+    /// Writes out queued messages until stopped, then writes out whatever is left.
+    fn run(&self) {
+        loop {
+            let stopped = {
+                let mut queue = self.queue.lock();
+
+                while queue.messages.is_empty() && queue.num_dropped == 0 && !queue.stopped {
+                    self.ready.wait(&mut queue);
+                }
+
+                queue.stopped
+            };
+
+            self.pop();
+
+            if stopped {
+                return;
+            }
+        }
+    }
+
+    fn stop(&self) {
+        self.queue.lock().stopped = true;
+        self.ready.notify_one();
+        self.not_full.notify_all();
     }
 
     fn pop(&self) {
-        let mut messages = String::new();
+        // held while writing so that concurrent pops can't reorder batches
+        let mut outputs = self.outputs.lock();
+        self.write_queued(&mut outputs);
+    }
 
-        {
+    /// Like pop, but gives up if another thread is writing for longer than timeout, which may
+    /// be because it panicked while writing.
+    fn try_pop(&self, timeout: Duration) {
+        if let Some(mut outputs) = self.outputs.try_lock_for(timeout) {
+            self.write_queued(&mut outputs);
+        }
+    }
+
+    fn write_queued(&self, outputs: &mut Outputs) {
+        let (messages, num_dropped) = {
             let mut queue = self.queue.lock();
 
-            if queue.is_empty() {
+            if queue.messages.is_empty() && queue.num_dropped == 0 {
                 return;
             }
 
-            mem::swap(&mut *queue, &mut messages);
+            let messages =
+                mem::replace(&mut queue.messages, VecDeque::with_capacity(self.capacity));
+
+            (messages, mem::take(&mut queue.num_dropped))
+        };
+
+        self.not_full.notify_all();
+
+        let mut batch = String::with_capacity(messages.iter().map(String::len).sum());
+
+        // the summary goes where the gap is: before the queue if the oldest messages were
+        // dropped, after it if the newest were
+        if num_dropped > 0 && self.overflow == Overflow::DropOldest {
+            batch.push_str(&self.format_dropped(num_dropped));
         }
 
-        Sink::print(&messages)
-    }
+        for message in messages.iter() {
+            batch.push_str(message);
+        }
 
-    fn print(messages: &str) {
-        if messages.is_empty() {
-            return;
+        if num_dropped > 0 && self.overflow != Overflow::DropOldest {
+            batch.push_str(&self.format_dropped(num_dropped));
         }
 
-        eprint!("{}", messages);
+        outputs.write(&batch);
+    }
+
+    fn format_dropped(&self, num_dropped: usize) -> String {
+        self.format.apply(
+            &Record::builder()
+                .level(Level::Warn)
+                .target(module_path!())
+                .module_path(Some(module_path!()))
+                .args(format_args!(
+                    "log queue was full, {} messages dropped",
+                    num_dropped
+                ))
+                .build(),
+        )
     }
 }
 
 impl Drop for Sink {
     fn drop(&mut self) {
-        Sink::print(self.queue.get_mut());
+        self.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        fs,
+        path::PathBuf,
+        process,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    #[test]
+    fn parse_default_level() {
+        let (filter, unparsed) = Filter::parse("");
+
+        assert_eq!(filter.level("anything"), DEFAULT_LOG_LEVEL);
+        assert!(unparsed.is_empty());
+
+        let (filter, _) = Filter::parse("warn");
+
+        assert_eq!(filter.level("anything"), LevelFilter::Warn);
+        assert_eq!(filter.max_level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn longest_target_matches_first() {
+        let (filter, unparsed) = Filter::parse("error, srm=info ,srm::static_core=trace");
+
+        assert!(unparsed.is_empty());
+        assert_eq!(filter.level("lidar"), LevelFilter::Error);
+        assert_eq!(filter.level("srm"), LevelFilter::Info);
+        assert_eq!(filter.level("srm::logging"), LevelFilter::Info);
+        assert_eq!(filter.level("srm::static_core"), LevelFilter::Trace);
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn bare_target_logs_everything() {
+        let (filter, _) = Filter::parse("warn,lidar");
+
+        assert_eq!(filter.level("lidar"), LevelFilter::Trace);
+        assert_eq!(filter.level("camera"), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn invalid_directives_are_returned() {
+        let (filter, unparsed) = Filter::parse("lidar=loud,=debug,camera=off");
+
+        assert_eq!(
+            unparsed,
+            vec!["lidar=loud".to_string(), "=debug".to_string()]
+        );
+        assert_eq!(filter.level("lidar"), DEFAULT_LOG_LEVEL);
+        assert_eq!(filter.level("camera"), LevelFilter::Off);
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let (filter, _) = Filter::parse("info,lidar=debug");
+
+        filter
+            .overrides
+            .write()
+            .insert("lidar".to_string(), LevelFilter::Error);
+        filter
+            .overrides
+            .write()
+            .insert("camera".to_string(), LevelFilter::Trace);
+
+        assert_eq!(filter.level("lidar"), LevelFilter::Error);
+        assert_eq!(filter.level("camera"), LevelFilter::Trace);
+        assert_eq!(filter.level("lidar::driver"), LevelFilter::Debug); // overrides are exact
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn while_publishing_nests() {
+        let publishing = || PUBLISHING.with(|p| p.get());
+
+        assert!(!publishing());
+
+        while_publishing(|| {
+            assert!(publishing());
+            while_publishing(|| assert!(publishing()));
+            assert!(publishing());
+        });
+
+        assert!(!publishing());
+    }
+
+    /// A text sink that writes to a file of its own instead of stderr.
+    fn test_sink(name: &str, capacity: usize, overflow: Overflow) -> (Arc<Sink>, PathBuf) {
+        let dir = env::temp_dir().join(format!("srm-logging-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut config = LogFileConfig::new(dir);
+        config.stderr = false;
+
+        let file = LogFile::create(config).unwrap();
+        let path = file.path();
+
+        let sink = Sink::new(Format::Text, capacity, overflow);
+        sink.outputs.lock().file = Some(file);
+
+        (Arc::new(sink), path)
+    }
+
+    fn push_numbers(sink: &Sink, numbers: std::ops::RangeInclusive<usize>) {
+        for i in numbers {
+            sink.push(format!("{}\n", i));
+        }
+    }
+
+    fn written(path: &PathBuf) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn is_summary(line: &str, num_dropped: usize) -> bool {
+        line.contains(" WARN ")
+            && line.ends_with(&format!(
+                "log queue was full, {} messages dropped",
+                num_dropped
+            ))
+    }
+
+    #[test]
+    fn drop_newest_summarizes_after_the_queue() {
+        let (sink, path) = test_sink("drop-newest", 2, Overflow::DropNewest);
+
+        push_numbers(&sink, 1..=4);
+        sink.pop();
+
+        let lines = written(&path);
+        assert_eq!(lines[..2], ["1", "2"]);
+        assert!(is_summary(&lines[2], 2), "{}", lines[2]);
+        assert_eq!(lines.len(), 3);
+
+        // the count starts over once the summary is written
+        push_numbers(&sink, 5..=5);
+        sink.pop();
+
+        assert_eq!(written(&path)[3..], ["5"]);
+    }
+
+    #[test]
+    fn drop_oldest_summarizes_before_the_queue() {
+        let (sink, path) = test_sink("drop-oldest", 2, Overflow::DropOldest);
+
+        push_numbers(&sink, 1..=4);
+        sink.pop();
+
+        let lines = written(&path);
+        assert!(is_summary(&lines[0], 2), "{}", lines[0]);
+        assert_eq!(lines[1..], ["3", "4"]);
+    }
+
+    #[test]
+    fn block_waits_for_the_queue_to_be_written() {
+        let (sink, path) = test_sink("block", 1, Overflow::Block);
+        push_numbers(&sink, 1..=1);
+
+        let pushed = Arc::new(AtomicBool::new(false));
+        let pusher = {
+            let sink = sink.clone();
+            let pushed = pushed.clone();
+
+            thread::spawn(move || {
+                push_numbers(&sink, 2..=2);
+                pushed.store(true, Ordering::SeqCst);
+            })
+        };
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!pushed.load(Ordering::SeqCst));
+
+        sink.pop();
+        pusher.join().unwrap();
+        sink.pop();
+
+        assert_eq!(written(&path), ["1", "2"]);
+
+        // nothing would ever make room once the sink has stopped
+        push_numbers(&sink, 3..=3);
+        sink.stop();
+        push_numbers(&sink, 4..=4);
+        sink.pop();
+
+        let lines = written(&path);
+        assert_eq!(lines[2], "3");
+        assert!(is_summary(&lines[3], 1), "{}", lines[3]);
+    }
+
+    #[test]
+    fn panic_hook_flushes_before_reporting() {
+        let (sink, path) = test_sink("panic", 8, Overflow::DropNewest);
+        push_numbers(&sink, 1..=2);
+
+        let seen_by_hook = Arc::new(Mutex::new(None));
+        let hook = {
+            let seen_by_hook = seen_by_hook.clone();
+            let path = path.clone();
+
+            flushing_hook(
+                sink.clone(),
+                Box::new(move |_| *seen_by_hook.lock() = Some(written(&path))),
+            )
+        };
+
+        let previous = panic::take_hook();
+        panic::set_hook(hook);
+        let result = panic::catch_unwind(|| panic!("boom"));
+        panic::set_hook(previous);
+
+        assert!(result.is_err());
+        assert_eq!(
+            *seen_by_hook.lock(),
+            Some(vec!["1".to_string(), "2".to_string()])
+        );
+    }
+
+    #[test]
+    fn panic_flush_gives_up_on_a_stuck_writer() {
+        let (sink, path) = test_sink("stuck", 8, Overflow::DropNewest);
+        push_numbers(&sink, 1..=1);
+
+        {
+            // e.g. a thread that panicked while writing
+            let _outputs = sink.outputs.lock();
+            sink.try_pop(Duration::from_millis(10));
+        }
+
+        assert!(written(&path).is_empty());
+
+        sink.try_pop(Duration::from_millis(10));
+        assert_eq!(written(&path), ["1"]);
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        let mut formatted = String::new();
+        write_json_str(&mut formatted, "say \"hi\"\\\n\r\t\u{1}\u{1f}é");
+
+        assert_eq!(formatted, r#""say \"hi\"\\\n\r\t\u0001\u001fé""#);
+    }
+
+    /// Returns everything after the time, which is the only part that varies.
+    fn json_after_time(formatted: &str) -> &str {
+        assert!(formatted.starts_with("{\"time\":\""), "{}", formatted);
+
+        &formatted[formatted.find(",\"level\"").unwrap()..]
+    }
+
+    #[test]
+    fn json_records_escape_the_message() {
+        let formatted = AsyncLogger::format_json(
+            &Record::builder()
+                .level(Level::Warn)
+                .target("lidar")
+                .module_path(Some("lidar::driver"))
+                .args(format_args!("line 1\nline \"2\""))
+                .build(),
+        );
+
+        assert_eq!(
+            json_after_time(&formatted),
+            concat!(
+                r#","level":"WARN","target":"lidar","module":"lidar::driver","#,
+                r#""msg":"line 1\nline \"2\""}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn json_records_include_fields() {
+        let fields = [
+            ("count", Value::from(3)),
+            ("ok", Value::from(true)),
+            ("ratio", Value::from(0.5)),
+            ("nan", Value::from(f64::NAN)),
+            ("path", Value::from("C:\\\"logs\"")),
+        ];
+        let formatted = AsyncLogger::format_json(
+            &Record::builder()
+                .level(Level::Info)
+                .target("camera")
+                .args(format_args!("frame"))
+                .key_values(&fields)
+                .build(),
+        );
+
+        assert_eq!(
+            json_after_time(&formatted),
+            concat!(
+                r#","level":"INFO","target":"camera","msg":"frame","fields":{"#,
+                r#""count":3,"ok":true,"ratio":0.5,"nan":"NaN","path":"C:\\\"logs\""}}"#,
+                "\n"
+            )
+        );
     }
 }
@@ -35,16 +35,33 @@ extern crate serde;
 extern crate serde_yaml;
 
 mod alloc;
+#[cfg(test)]
+mod capnp_schema;
+mod clock;
+mod clock_capnp;
 mod core;
 mod error_code;
-mod ffi;
+mod events_capnp;
+mod executor;
+mod introspection;
+mod log_capnp;
+mod log_file;
 mod logging;
+mod metrics;
 mod node;
 mod node_graph;
 mod node_plugin;
+mod options;
+mod param_file;
 mod plugin_loader;
+mod scheduling;
 mod static_core;
-mod util;
+mod stats;
+mod synchronizer;
+mod timer;
+
+use options::Options;
+use srm::{ffi, util};
 
 use std::process;
 
@@ -53,7 +70,17 @@ use log::{error, info};
 fn main() {
     logging::init();
 
-    let core = match node_graph::spawn_core() {
+    let options = match Options::from_args() {
+        Ok(o) => o,
+        Err(e) => {
+            error!("couldn't parse command line: {}", e);
+            log::logger().flush();
+
+            process::exit(1);
+        }
+    };
+
+    let core = match node_graph::spawn_core(&options) {
         Ok(c) => c,
         Err(e) => {
             error!("couldn't spawn core from node graph: {}", e);
@@ -63,11 +90,30 @@ fn main() {
         }
     };
 
+    if options.list_params {
+        print!("{}", introspection::describe_params(&core));
+        log::logger().flush();
+
+        return;
+    }
+
+    if let Some(addr) = options.metrics {
+        if let Err(e) = metrics::serve(core.clone(), addr) {
+            error!("couldn't serve metrics on {}: {}", addr, e);
+            log::logger().flush();
+
+            process::exit(1);
+        }
+    }
+
     let other_core = core.clone();
 
     match ctrlc::set_handler(move || {
         info!("^C received, stopping...");
         other_core.stop();
+
+        // in case a node doesn't stop and the process is killed
+        log::logger().flush();
     }) {
         Ok(_) => (),
         Err(e) => {
@@ -79,5 +125,17 @@ fn main() {
     };
 
     core.run();
+
+    if options.print_stats {
+        print!("{}", introspection::describe_topics(&core));
+    }
+
+    if let Some(ref path) = options.dump_params {
+        match core.param_dump("", path) {
+            Ok(()) => info!("dumped params to '{}'", path.display()),
+            Err(e) => error!("couldn't dump params to '{}': {}", path.display(), e),
+        }
+    }
+
     log::logger().flush();
 }
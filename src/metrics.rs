@@ -0,0 +1,461 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::{
+    node::NodeState,
+    static_core::StaticCore,
+    stats::{CallbackStatsSnapshot, LATENCY_BUCKET_BOUNDS},
+};
+
+use std::{
+    fmt::Write as FmtWrite,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+
+/// The address the metrics listener binds to if none is given.
+pub const DEFAULT_METRICS_ADDR: &str = "127.0.0.1:9464";
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+/// Connections are handled one at a time, so a client that stops reading or writing must not be
+/// able to stall the listener for longer than this.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the core's metrics over HTTP at `/metrics` on a background thread.
+///
+/// The listener is bound before returning so that errors can be reported to the caller.
+pub fn serve(core: Arc<StaticCore>, addr: SocketAddr) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!(
+        "serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+
+    thread::Builder::new()
+        .name("srm-metrics".to_string())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream.and_then(|s| handle(&core, s));
+
+                if let Err(e) = result {
+                    debug!("couldn't serve metrics request: {}", e);
+                }
+            }
+        })?;
+
+    Ok(())
+}
+
+fn handle(core: &StaticCore, stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // discard the headers, we don't use any of them
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+
+    let mut stream = reader.into_inner();
+
+    match (method, path) {
+        ("GET", "/metrics") => respond(&mut stream, "200 OK", CONTENT_TYPE, &render(core)),
+        ("GET", _) => respond(&mut stream, "404 Not Found", "text/plain", "not found\n"),
+        _ => {
+            warn!("unexpected metrics request '{}'", request_line.trim_end());
+
+            respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                "method not allowed\n",
+            )
+        }
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+
+    stream.flush()
+}
+
+/// Renders the core's metrics in the OpenMetrics text format.
+pub fn render(core: &StaticCore) -> String {
+    let mut out = String::new();
+
+    render_nodes(core, &mut out);
+    render_topics(core, &mut out);
+    render_params(core, &mut out);
+    render_plugins(core, &mut out);
+
+    out.push_str("# EOF\n");
+
+    out
+}
+
+fn render_nodes(core: &StaticCore, out: &mut String) {
+    let mut states = core.node_states();
+    states.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    header(
+        out,
+        "srm_node_state",
+        "stateset",
+        "Lifecycle state of each node.",
+    );
+
+    for (name, state) in states.iter() {
+        for s in NodeState::ALL.iter() {
+            writeln!(
+                out,
+                "srm_node_state{{node=\"{}\",srm_node_state=\"{}\"}} {}",
+                escape(name),
+                s.name(),
+                (s == state) as u8
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn render_topics(core: &StaticCore, out: &mut String) {
+    let mut topics = core.topic_stats();
+    topics.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    header(
+        out,
+        "srm_topic_messages",
+        "counter",
+        "Messages published on each topic.",
+    );
+
+    for (name, stats) in topics.iter() {
+        writeln!(
+            out,
+            "srm_topic_messages_total{{topic=\"{}\"}} {}",
+            escape(name),
+            stats.channel.messages
+        )
+        .unwrap();
+    }
+
+    header(
+        out,
+        "srm_topic_bytes",
+        "counter",
+        "Bytes published on each topic.",
+    );
+
+    for (name, stats) in topics.iter() {
+        writeln!(
+            out,
+            "srm_topic_bytes_total{{topic=\"{}\"}} {}",
+            escape(name),
+            stats.channel.bytes
+        )
+        .unwrap();
+    }
+
+    header(
+        out,
+        "srm_callback_failures",
+        "counter",
+        "Subscriber callbacks that returned an error.",
+    );
+
+    for (name, stats) in topics.iter() {
+        for (id, owner, s) in stats.subscribers.iter() {
+            writeln!(
+                out,
+                "srm_callback_failures_total{{{}}} {}",
+                subscriber_labels(name, owner, *id),
+                s.failures
+            )
+            .unwrap();
+        }
+    }
+
+    header(
+        out,
+        "srm_callback_duration_seconds",
+        "histogram",
+        "Time spent in subscriber callbacks.",
+    );
+
+    for (name, stats) in topics.iter() {
+        for (id, owner, s) in stats.subscribers.iter() {
+            render_histogram(out, &subscriber_labels(name, owner, *id), s);
+        }
+    }
+
+    header(
+        out,
+        "srm_segment_pool_hits",
+        "counter",
+        "Message segments reused from each topic's pool.",
+    );
+
+    for (name, stats) in topics.iter() {
+        writeln!(
+            out,
+            "srm_segment_pool_hits_total{{topic=\"{}\"}} {}",
+            escape(name),
+            stats.segment_pool.hits
+        )
+        .unwrap();
+    }
+
+    header(
+        out,
+        "srm_segment_pool_misses",
+        "counter",
+        "Message segments newly allocated for each topic.",
+    );
+
+    for (name, stats) in topics.iter() {
+        writeln!(
+            out,
+            "srm_segment_pool_misses_total{{topic=\"{}\"}} {}",
+            escape(name),
+            stats.segment_pool.misses
+        )
+        .unwrap();
+    }
+}
+
+fn render_histogram(out: &mut String, labels: &str, stats: &CallbackStatsSnapshot) {
+    let mut cumulative = 0;
+
+    for (i, count) in stats.buckets.iter().enumerate() {
+        cumulative += count;
+
+        let le = match LATENCY_BUCKET_BOUNDS.get(i) {
+            Some(&bound) => format!("{}", bound as f64 / 1e9),
+            None => "+Inf".to_string(),
+        };
+
+        writeln!(
+            out,
+            "srm_callback_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+            labels, le, cumulative
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        out,
+        "srm_callback_duration_seconds_sum{{{}}} {}",
+        labels,
+        stats.total_duration as f64 / 1e9
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "srm_callback_duration_seconds_count{{{}}} {}",
+        labels, stats.calls
+    )
+    .unwrap();
+}
+
+fn render_params(core: &StaticCore, out: &mut String) {
+    header(out, "srm_params", "gauge", "Parameters currently set.");
+    writeln!(out, "srm_params {}", core.num_params()).unwrap();
+}
+
+fn render_plugins(core: &StaticCore, out: &mut String) {
+    let (mut plugins, num_failures) = core.plugins();
+    plugins.sort();
+
+    header(
+        out,
+        "srm_plugin",
+        "info",
+        "Node plugins that have been loaded.",
+    );
+
+    for (name, path) in plugins.iter() {
+        writeln!(
+            out,
+            "srm_plugin_info{{name=\"{}\",path=\"{}\"}} 1",
+            escape(name),
+            escape(&path.to_string_lossy())
+        )
+        .unwrap();
+    }
+
+    header(
+        out,
+        "srm_plugin_load_failures",
+        "counter",
+        "Node plugins that couldn't be loaded.",
+    );
+    writeln!(out, "srm_plugin_load_failures_total {}", num_failures).unwrap();
+}
+
+fn header(out: &mut String, name: &str, tp: &str, help: &str) {
+    writeln!(out, "# TYPE {} {}", name, tp).unwrap();
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+}
+
+fn subscriber_labels(topic: &str, node: &str, id: usize) -> String {
+    format!(
+        "topic=\"{}\",node=\"{}\",subscriber=\"{}\"",
+        escape(topic),
+        escape(node),
+        id
+    )
+}
+
+/// Escapes a label value as required by the OpenMetrics text format.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ffi::NUM_LATENCY_BUCKETS;
+
+    #[test]
+    fn render_declares_each_family_and_ends_with_eof() {
+        let core = StaticCore::new(Vec::new());
+        let out = render(&core);
+
+        assert!(out.ends_with("\n# EOF\n"));
+        assert_eq!(out.matches("# EOF").count(), 1);
+
+        let mut declared = Vec::new();
+
+        for line in out.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let name = rest.split(' ').next().unwrap();
+                assert!(!declared.contains(&name), "{} declared twice", name);
+                declared.push(name);
+            } else if !line.starts_with('#') {
+                // every sample belongs to the family declared most recently
+                let family = declared.last().expect("sample before any # TYPE line");
+                assert!(line.starts_with(family), "'{}' isn't in {}", line, family);
+            }
+        }
+
+        assert!(out.contains("# TYPE srm_topic_messages counter\n"));
+        assert!(out.contains("# TYPE srm_callback_duration_seconds histogram\n"));
+        assert!(out.contains("# TYPE srm_node_state stateset\n"));
+        assert!(out.contains("srm_topic_messages_total{topic=\"/srm/events\"} "));
+        assert!(out.contains("\nsrm_params 0\n"));
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let stats = CallbackStatsSnapshot {
+            calls: 6,
+            failures: 0,
+            total_duration: 1_500_000_000,
+            buckets: [1, 0, 2, 0, 0, 0, 0, 3],
+        };
+
+        let mut out = String::new();
+        render_histogram(&mut out, "topic=\"a\"", &stats);
+
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), NUM_LATENCY_BUCKETS + 2);
+
+        assert_eq!(
+            lines[0],
+            "srm_callback_duration_seconds_bucket{topic=\"a\",le=\"0.000001\"} 1"
+        );
+        assert_eq!(
+            lines[2],
+            "srm_callback_duration_seconds_bucket{topic=\"a\",le=\"0.0001\"} 3"
+        );
+        assert_eq!(
+            lines[6],
+            "srm_callback_duration_seconds_bucket{topic=\"a\",le=\"1\"} 3"
+        );
+        assert_eq!(
+            lines[7],
+            "srm_callback_duration_seconds_bucket{topic=\"a\",le=\"+Inf\"} 6"
+        );
+        assert_eq!(
+            lines[8],
+            "srm_callback_duration_seconds_sum{topic=\"a\"} 1.5"
+        );
+        assert_eq!(
+            lines[9],
+            "srm_callback_duration_seconds_count{topic=\"a\"} 6"
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("plain/topic"), "plain/topic");
+        assert_eq!(escape("a\"b"), "a\\\"b");
+        assert_eq!(escape("a\\b"), "a\\\\b");
+        assert_eq!(escape("a\nb"), "a\\nb");
+    }
+
+    #[test]
+    fn escapes_topic_and_node_labels() {
+        assert_eq!(
+            subscriber_labels("/a\"b", "no\\de\n", 3),
+            "topic=\"/a\\\"b\",node=\"no\\\\de\\n\",subscriber=\"3\""
+        );
+    }
+}
@@ -24,7 +24,10 @@ use super::{core::CoreBase, error_code::ErrorCode, ffi, node_plugin::NodePlugin,
 
 use std::{
     ptr,
-    sync::{Arc, Weak},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc, Weak,
+    },
 };
 
 use libc::{c_int, c_void};
@@ -37,6 +40,38 @@ pub struct Node {
     plugin: Arc<NodePlugin>,
     name: String,
     impl_ptr: *mut c_void,
+    state: AtomicU8,
+}
+
+/// The lifecycle state of a node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeState {
+    Created,
+    Running,
+    Stopped,
+    Failed,
+}
+
+impl NodeState {
+    pub const ALL: [NodeState; 4] = [
+        NodeState::Created,
+        NodeState::Running,
+        NodeState::Stopped,
+        NodeState::Failed,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NodeState::Created => "created",
+            NodeState::Running => "running",
+            NodeState::Stopped => "stopped",
+            NodeState::Failed => "failed",
+        }
+    }
+
+    fn from_u8(state: u8) -> NodeState {
+        NodeState::ALL[state as usize]
+    }
 }
 
 struct EmptyCoreBase {}
@@ -59,6 +94,7 @@ impl Node {
             plugin,
             name,
             impl_ptr: ptr::null_mut(),
+            state: AtomicU8::new(NodeState::Created as u8),
         }
     }
 
@@ -75,15 +111,30 @@ impl Node {
                 &mut self.impl_ptr,
             )
         };
-        self.to_result(err)
+        let result = self.to_result(err);
+
+        if result.is_err() {
+            self.set_state(NodeState::Failed);
+        }
+
+        result
     }
 
     /// Tells the node to begin computation. Will not return until the node shuts down.
     pub fn run(&self) -> Result<(), ErrorCode> {
         assert!(self.core.upgrade().is_some());
 
+        self.set_state(NodeState::Running);
+
         let err = unsafe { (self.plugin.vptr().run)(self.impl_ptr) };
-        self.to_result(err)
+        let result = self.to_result(err);
+
+        match result {
+            Ok(()) => self.set_state(NodeState::Stopped),
+            Err(_) => self.set_state(NodeState::Failed),
+        }
+
+        result
     }
 
     /// Tells the node to stop computation. Should not block.
@@ -107,8 +158,6 @@ impl Node {
     ///
     /// Panics if the implementation does not return a string to explain err.
     pub fn get_err_msg(&self, err: c_int) -> Option<&str> {
-        assert!(self.core.upgrade().is_some());
-
         if err == 0 {
             None
         } else {
@@ -118,6 +167,14 @@ impl Node {
         }
     }
 
+    pub fn state(&self) -> NodeState {
+        NodeState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    fn set_state(&self, state: NodeState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
     pub fn name(&self) -> &str {
         assert!(self.core.upgrade().is_some());
 
@@ -143,13 +200,17 @@ impl Node {
 }
 
 impl Drop for Node {
-    /// Calls vptr->destroy.
+    /// Calls vptr->destroy, unless vptr->create failed without creating anything.
+    ///
+    /// The core may already be gone, since nodes are dropped along with their core interface.
     ///
     /// # Panics
     ///
     /// Panics if the call to vptr.destroy returns nonzero.
     fn drop(&mut self) {
-        assert!(self.core.upgrade().is_some());
+        if self.impl_ptr.is_null() {
+            return;
+        }
 
         match unsafe { (self.plugin.vptr().destroy)(self.impl_ptr) } {
             0 => return,
@@ -20,10 +20,15 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use crate::static_core::{self, NodeError, Param, StaticCore};
+use crate::{
+    log_file::LogFileConfig,
+    logging,
+    options::Options,
+    param_file::{self, ParamFileError},
+    static_core::{self, NodeError, NodeOptions, Param, ParamDecl, StaticCore, StaticCoreError},
+};
 
 use std::{
-    env,
     error::Error,
     fmt::{self, Display, Formatter},
     fs::File,
@@ -32,19 +37,21 @@ use std::{
     sync::Arc,
 };
 
+use rayon::ThreadPoolBuildError;
+
 use hashbrown::HashSet;
 use log::info;
 use regex::Regex;
 use serde::Deserialize;
 
-pub fn spawn_core() -> Result<Arc<StaticCore>, GraphError> {
-    let graph = if let Some(filename) = env::args_os().nth(1) {
+pub fn spawn_core(options: &Options) -> Result<Arc<StaticCore>, GraphError> {
+    let graph = if let Some(ref filename) = options.graph {
         if filename == "-" {
             info!("reading node graph from stdin");
             NodeGraph::from_reader(&mut io::stdin())?
         } else {
             info!("reading node graph from '{}'", filename.to_string_lossy());
-            let mut file = File::open(&filename).map_err(|e| GraphError::File(e))?;
+            let mut file = File::open(filename).map_err(|e| GraphError::File(e))?;
 
             NodeGraph::from_reader(&mut file)?
         }
@@ -53,14 +60,67 @@ pub fn spawn_core() -> Result<Arc<StaticCore>, GraphError> {
         NodeGraph::from_reader(&mut io::stdin())?
     };
 
-    graph.into_static_core().map_err(|e| GraphError::Node(e))
+    let log_file = match (graph.log_file.clone(), &options.log_dir) {
+        (Some(config), None) => Some(config),
+        (Some(config), Some(dir)) => Some(LogFileConfig {
+            dir: dir.clone(),
+            ..config
+        }),
+        (None, Some(dir)) => Some(LogFileConfig::new(dir.clone())),
+        (None, None) => None,
+    };
+
+    if let Some(config) = log_file {
+        logging::set_log_file(config).map_err(GraphError::LogFile)?;
+    }
+
+    graph.into_static_core(&options.param_files)
 }
 
 #[derive(Deserialize)]
 struct NodeGraph {
     path: Vec<PathBuf>,
-    nodes: Vec<(String, String)>,         // (name, type)
+    nodes: Vec<NodeEntry>,
+    thread_pools: Option<Vec<(String, usize)>>, // (name, number of threads)
+    callback_groups: Option<Vec<String>>,
     params: Option<Vec<(String, Param)>>, // (key, value)
+    param_decls: Option<Vec<(String, ParamDecl)>>, // (key, declaration)
+    param_files: Option<Vec<PathBuf>>,
+    #[serde(default)]
+    strict_params: bool,
+    log_file: Option<LogFileConfig>,
+    #[serde(default)]
+    publish_logs: bool,
+}
+
+/// Either `[name, type]` or a mapping with a name, type and options.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NodeEntry {
+    Short(String, String),
+    Long {
+        name: String,
+        #[serde(rename = "type")]
+        tp: String,
+        #[serde(flatten)]
+        options: NodeOptions,
+    },
+}
+
+impl NodeEntry {
+    fn name(&self) -> &str {
+        match self {
+            NodeEntry::Short(name, _) => name,
+            NodeEntry::Long { name, .. } => name,
+        }
+    }
+
+    fn into_parts(self) -> (String, String, NodeOptions) {
+        match self {
+            NodeEntry::Short(name, tp) => (name, tp, NodeOptions::default()),
+            NodeEntry::Long { name, tp, options } => (name, tp, options),
+        }
+    }
 }
 
 impl NodeGraph {
@@ -76,16 +136,16 @@ impl NodeGraph {
 
         {
             let mut names = HashSet::new();
-            for name in graph.nodes.iter().map(|(n, _)| n.as_str()) {
+            for name in graph.nodes.iter().map(NodeEntry::name) {
                 if !names.insert(name) {
                     return Err(GraphError::DuplicateName(name.to_string()));
                 }
             }
         }
 
-        if let Some(ref params) = graph.params {
-            let resolved = Regex::new(r"^(?:\.[^.~]+)+$").unwrap();
+        let resolved = Regex::new(r"^(?:\.[^.~]+)+$").unwrap();
 
+        if let Some(ref params) = graph.params {
             for key in params.iter().map(|(k, _)| k) {
                 if !resolved.is_match(key) {
                     return Err(GraphError::InvalidParamKey(key.clone()));
@@ -93,22 +153,69 @@ impl NodeGraph {
             }
         }
 
+        if let Some(ref decls) = graph.param_decls {
+            for key in decls.iter().map(|(k, _)| k) {
+                if !resolved.is_match(key) {
+                    return Err(GraphError::InvalidParamKey(key.clone()));
+                }
+            }
+        }
+
         Ok(graph)
     }
 
-    fn into_static_core(self) -> Result<Arc<StaticCore>, NodeError> {
+    /// Later param files override earlier ones, and all param files override the graph's params.
+    fn into_static_core(self, overlays: &[PathBuf]) -> Result<Arc<StaticCore>, GraphError> {
         let core = Arc::new(StaticCore::new(self.path));
+        core.set_strict_params(self.strict_params);
+
+        if self.publish_logs {
+            core.publish_logs().map_err(GraphError::PublishLogs)?;
+        }
+
+        if let Some(decls) = self.param_decls {
+            for (key, decl) in decls.into_iter() {
+                core.param_declare(key.clone(), decl)
+                    .map_err(|e| GraphError::Param(key, e))?;
+            }
+        }
+
+        for (name, num_threads) in self.thread_pools.unwrap_or_default().into_iter() {
+            core.add_thread_pool(name.clone(), num_threads)
+                .map_err(|e| GraphError::ThreadPool(name, e))?;
+        }
 
-        for (name, tp) in self.nodes.into_iter() {
-            static_core::add_node(&core, name, tp)?;
+        for name in self.callback_groups.unwrap_or_default().into_iter() {
+            core.add_callback_group(name);
+        }
+
+        for (name, tp, options) in self.nodes.into_iter().map(NodeEntry::into_parts) {
+            static_core::add_node(&core, name, tp, &options).map_err(GraphError::Node)?;
         }
 
         if let Some(params) = self.params {
             for (key, value) in params.into_iter() {
-                core.param_set(key, value);
+                core.param_configure(key.clone(), value)
+                    .map_err(|e| GraphError::Param(key, e))?;
             }
         }
 
+        let param_files = self.param_files.unwrap_or_default();
+
+        for path in param_files.iter().chain(overlays.iter()) {
+            info!("loading params from '{}'", path.display());
+            let params =
+                param_file::read(path).map_err(|e| GraphError::ParamFile(path.clone(), e))?;
+
+            for (key, value) in params.into_iter() {
+                core.param_configure(key.clone(), value)
+                    .map_err(|e| GraphError::Param(key, e))?;
+            }
+        }
+
+        core.configure_clock()
+            .map_err(|e| GraphError::Param(".use_sim_time".to_string(), e))?;
+
         Ok(core)
     }
 }
@@ -121,6 +228,11 @@ pub enum GraphError {
     DuplicateName(String),
     Node(NodeError),
     InvalidParamKey(String),
+    Param(String, StaticCoreError),
+    ParamFile(PathBuf, ParamFileError),
+    ThreadPool(String, ThreadPoolBuildError),
+    LogFile(io::Error),
+    PublishLogs(StaticCoreError),
 }
 
 impl Error for GraphError {}
@@ -136,6 +248,15 @@ impl Display for GraphError {
             }
             GraphError::Node(e) => write!(f, "couldn't initialize core from graph: {}", e),
             GraphError::InvalidParamKey(n) => write!(f, "invalid param name '{}'", n),
+            GraphError::Param(n, e) => write!(f, "couldn't initialize param '{}': {}", n, e),
+            GraphError::ParamFile(p, e) => {
+                write!(f, "couldn't load param file '{}': {}", p.display(), e)
+            }
+            GraphError::ThreadPool(n, e) => {
+                write!(f, "couldn't build thread pool '{}': {}", n, e)
+            }
+            GraphError::LogFile(e) => write!(f, "couldn't open log file: {}", e),
+            GraphError::PublishLogs(e) => write!(f, "couldn't publish logs: {}", e),
         }
     }
 }
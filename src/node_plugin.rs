@@ -25,51 +25,51 @@ use super::*;
 use std::{
     error::Error,
     fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
 };
 
 use libloading::Library;
 
 pub struct NodePlugin {
-    library: Library,
+    library: Option<Library>, // None for nodes linked into the core
     vtbl: node::Vtbl,
+    path: PathBuf,
 }
 
 impl NodePlugin {
-    pub fn new(library: Library) -> Result<NodePlugin, LoadError> {
-        let f = unsafe { library.get::<GetVtblFn>(b"srm_Node_get_vtbl\0") }
-            .map_err(|_| LoadError::LibraryMissingSymbol)?;
-        let vptr = unsafe { f().as_ref() }.ok_or(LoadError::VtblNull)?;
-
-        if vptr.create.is_none() {
-            return Err(LoadError::VtblMissingFunction("create"));
-        } else if vptr.destroy.is_none() {
-            return Err(LoadError::VtblMissingFunction("destroy"));
-        } else if vptr.run.is_none() {
-            return Err(LoadError::VtblMissingFunction("run"));
-        } else if vptr.stop.is_none() {
-            return Err(LoadError::VtblMissingFunction("stop"));
-        } else if vptr.get_type.is_none() {
-            return Err(LoadError::VtblMissingFunction("get_type"));
-        } else if vptr.get_err_msg.is_none() {
-            return Err(LoadError::VtblMissingFunction("get_err_msg"));
-        }
+    pub fn new(library: Library, path: PathBuf) -> Result<NodePlugin, LoadError> {
+        let vtbl = {
+            let f = unsafe { library.get::<GetVtblFn>(b"srm_Node_get_vtbl\0") }
+                .map_err(|_| LoadError::LibraryMissingSymbol)?;
+
+            to_vtbl(*f)?
+        };
 
         Ok(NodePlugin {
-            library,
-            vtbl: node::Vtbl {
-                create: vptr.create.unwrap(),
-                destroy: vptr.destroy.unwrap(),
-                run: vptr.run.unwrap(),
-                stop: vptr.stop.unwrap(),
-                get_type: vptr.get_type.unwrap(),
-                get_err_msg: vptr.get_err_msg.unwrap(),
-            },
+            library: Some(library),
+            vtbl,
+            path,
+        })
+    }
+
+    /// Creates a plugin from a node linked into the core, e.g. by srm_export_node! in a test.
+    #[cfg(test)]
+    pub fn from_get_vtbl(f: GetVtblFn, path: PathBuf) -> Result<NodePlugin, LoadError> {
+        Ok(NodePlugin {
+            library: None,
+            vtbl: to_vtbl(f)?,
+            path,
         })
     }
 
     pub fn vptr(&self) -> &node::Vtbl {
         &self.vtbl
     }
+
+    /// Returns the path the library was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 }
 
 unsafe impl Send for NodePlugin {}
@@ -78,6 +78,33 @@ unsafe impl Sync for NodePlugin {}
 
 type GetVtblFn = unsafe extern "C" fn() -> *const ffi::NodeVtbl;
 
+fn to_vtbl(f: GetVtblFn) -> Result<node::Vtbl, LoadError> {
+    let vptr = unsafe { f().as_ref() }.ok_or(LoadError::VtblNull)?;
+
+    if vptr.create.is_none() {
+        return Err(LoadError::VtblMissingFunction("create"));
+    } else if vptr.destroy.is_none() {
+        return Err(LoadError::VtblMissingFunction("destroy"));
+    } else if vptr.run.is_none() {
+        return Err(LoadError::VtblMissingFunction("run"));
+    } else if vptr.stop.is_none() {
+        return Err(LoadError::VtblMissingFunction("stop"));
+    } else if vptr.get_type.is_none() {
+        return Err(LoadError::VtblMissingFunction("get_type"));
+    } else if vptr.get_err_msg.is_none() {
+        return Err(LoadError::VtblMissingFunction("get_err_msg"));
+    }
+
+    Ok(node::Vtbl {
+        create: vptr.create.unwrap(),
+        destroy: vptr.destroy.unwrap(),
+        run: vptr.run.unwrap(),
+        stop: vptr.stop.unwrap(),
+        get_type: vptr.get_type.unwrap(),
+        get_err_msg: vptr.get_err_msg.unwrap(),
+    })
+}
+
 #[derive(Debug)]
 pub enum LoadError {
     NoLibraryFound,
@@ -0,0 +1,223 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::{
+    env,
+    error::Error,
+    ffi::OsString,
+    fmt::{self, Display, Formatter},
+    net::SocketAddr,
+    path::PathBuf,
+};
+
+use super::metrics::DEFAULT_METRICS_ADDR;
+
+/// Command line options for the srm binary.
+///
+/// Usage: `srm [--list-params] [--param-file FILE]... [--dump-params FILE] [--print-stats]
+/// [--metrics [ADDR]] [--log-dir DIR] [GRAPH]`, where GRAPH is a node graph file or `-` for
+/// stdin. Param files are loaded in the order given, after any specified by the node graph.
+/// `--metrics` serves OpenMetrics text at `http://ADDR/metrics`, where ADDR defaults to
+/// 127.0.0.1:9464. `--log-dir` writes logs to rotating files in DIR, overriding the directory
+/// of the node graph's `log_file`.
+pub struct Options {
+    pub graph: Option<OsString>,
+    pub list_params: bool,
+    pub param_files: Vec<PathBuf>,
+    pub dump_params: Option<PathBuf>,
+    pub print_stats: bool,
+    pub metrics: Option<SocketAddr>,
+    pub log_dir: Option<PathBuf>,
+}
+
+impl Options {
+    pub fn from_args() -> Result<Options, OptionsError> {
+        Options::parse(env::args_os().skip(1))
+    }
+
+    fn parse<I: Iterator<Item = OsString>>(args: I) -> Result<Options, OptionsError> {
+        let mut options = Options {
+            graph: None,
+            list_params: false,
+            param_files: Vec::new(),
+            dump_params: None,
+            print_stats: false,
+            metrics: None,
+            log_dir: None,
+        };
+
+        let mut args = args.peekable();
+
+        while let Some(arg) = args.next() {
+            if arg == "--list-params" {
+                options.list_params = true;
+            } else if arg == "--param-file" {
+                let value = args
+                    .next()
+                    .ok_or(OptionsError::MissingValue("--param-file"))?;
+                options.param_files.push(PathBuf::from(value));
+            } else if arg == "--dump-params" {
+                let value = args
+                    .next()
+                    .ok_or(OptionsError::MissingValue("--dump-params"))?;
+                options.dump_params = Some(PathBuf::from(value));
+            } else if arg == "--print-stats" {
+                options.print_stats = true;
+            } else if arg == "--log-dir" {
+                let value = args.next().ok_or(OptionsError::MissingValue("--log-dir"))?;
+                options.log_dir = Some(PathBuf::from(value));
+            } else if arg == "--metrics" {
+                // the address is optional, so only consume the next argument if it is one
+                let addr = args
+                    .peek()
+                    .and_then(|a| a.to_str())
+                    .and_then(|a| a.parse().ok());
+
+                options.metrics = match addr {
+                    Some(a) => {
+                        args.next();
+
+                        Some(a)
+                    }
+                    None => Some(DEFAULT_METRICS_ADDR.parse().unwrap()),
+                };
+            } else if arg.to_string_lossy().starts_with("--") {
+                return Err(OptionsError::UnknownFlag(arg));
+            } else if options.graph.is_none() {
+                options.graph = Some(arg);
+            } else {
+                return Err(OptionsError::UnexpectedArgument(arg));
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+#[derive(Debug)]
+pub enum OptionsError {
+    UnknownFlag(OsString),
+    UnexpectedArgument(OsString),
+    MissingValue(&'static str),
+}
+
+impl Error for OptionsError {}
+
+impl Display for OptionsError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            OptionsError::UnknownFlag(a) => write!(f, "unknown flag '{}'", a.to_string_lossy()),
+            OptionsError::UnexpectedArgument(a) => {
+                write!(f, "unexpected argument '{}'", a.to_string_lossy())
+            }
+            OptionsError::MissingValue(flag) => write!(f, "flag '{}' requires a value", flag),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, OptionsError> {
+        Options::parse(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn parse_empty() {
+        let options = parse(&[]).unwrap();
+
+        assert!(options.graph.is_none());
+        assert!(!options.list_params);
+        assert!(options.param_files.is_empty());
+        assert!(options.dump_params.is_none());
+        assert!(!options.print_stats);
+        assert!(options.metrics.is_none());
+        assert!(options.log_dir.is_none());
+    }
+
+    #[test]
+    fn parse_flags() {
+        let options = parse(&[
+            "--list-params",
+            "--param-file",
+            "a.yaml",
+            "graph.yaml",
+            "--param-file",
+            "b.yaml",
+            "--dump-params",
+            "out.yaml",
+            "--print-stats",
+            "--log-dir",
+            "logs",
+        ])
+        .unwrap();
+
+        assert_eq!(options.graph, Some(OsString::from("graph.yaml")));
+        assert!(options.list_params);
+        assert_eq!(
+            options.param_files,
+            vec![PathBuf::from("a.yaml"), PathBuf::from("b.yaml")]
+        );
+        assert_eq!(options.dump_params, Some(PathBuf::from("out.yaml")));
+        assert!(options.print_stats);
+        assert_eq!(options.log_dir, Some(PathBuf::from("logs")));
+    }
+
+    #[test]
+    fn parse_metrics_address_is_optional() {
+        let options = parse(&["--metrics", "graph.yaml"]).unwrap();
+
+        assert_eq!(options.metrics, Some(DEFAULT_METRICS_ADDR.parse().unwrap()));
+        assert_eq!(options.graph, Some(OsString::from("graph.yaml")));
+
+        let options = parse(&["--metrics", "0.0.0.0:8080", "graph.yaml"]).unwrap();
+
+        assert_eq!(options.metrics, Some("0.0.0.0:8080".parse().unwrap()));
+        assert_eq!(options.graph, Some(OsString::from("graph.yaml")));
+    }
+
+    #[test]
+    fn parse_stdin_graph() {
+        let options = parse(&["-"]).unwrap();
+
+        assert_eq!(options.graph, Some(OsString::from("-")));
+    }
+
+    #[test]
+    fn parse_errors() {
+        match parse(&["--bogus"]) {
+            Err(OptionsError::UnknownFlag(a)) => assert_eq!(a, "--bogus"),
+            _ => panic!("expected UnknownFlag"),
+        }
+
+        match parse(&["a.yaml", "b.yaml"]) {
+            Err(OptionsError::UnexpectedArgument(a)) => assert_eq!(a, "b.yaml"),
+            _ => panic!("expected UnexpectedArgument"),
+        }
+
+        match parse(&["--param-file"]) {
+            Err(OptionsError::MissingValue(f)) => assert_eq!(f, "--param-file"),
+            _ => panic!("expected MissingValue"),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::static_core::Param;
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// A set of parameter values, in the same format as the `params` key of a node graph.
+#[derive(Deserialize, Serialize)]
+struct ParamFile {
+    params: Vec<(String, Param)>, // (key, value)
+}
+
+/// Reads parameters from a YAML file.
+pub fn read(path: &Path) -> Result<Vec<(String, Param)>, ParamFileError> {
+    let mut buf = String::new();
+
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut buf))
+        .map_err(ParamFileError::Io)?;
+
+    let file: ParamFile = serde_yaml::from_str(&buf).map_err(ParamFileError::Yaml)?;
+
+    let resolved = Regex::new(r"^(?:\.[^.~]+)+$").unwrap();
+
+    for key in file.params.iter().map(|(k, _)| k) {
+        if !resolved.is_match(key) {
+            return Err(ParamFileError::InvalidParamKey(key.clone()));
+        }
+    }
+
+    Ok(file.params)
+}
+
+/// Writes parameters to a YAML file, overwriting it if it exists.
+pub fn write(path: &Path, params: Vec<(String, Param)>) -> Result<(), ParamFileError> {
+    let buf = serde_yaml::to_string(&ParamFile { params }).map_err(ParamFileError::Yaml)?;
+
+    fs::write(path, buf).map_err(ParamFileError::Io)
+}
+
+#[derive(Debug)]
+pub enum ParamFileError {
+    Io(io::Error),
+    Yaml(serde_yaml::Error),
+    InvalidParamKey(String),
+}
+
+impl Error for ParamFileError {}
+
+impl Display for ParamFileError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ParamFileError::Io(e) => write!(f, "I/O error: {}", e),
+            ParamFileError::Yaml(e) => write!(f, "invalid YAML: {}", e),
+            ParamFileError::InvalidParamKey(k) => write!(f, "invalid param name '{}'", k),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        node_graph::{self, GraphError},
+        options::Options,
+        static_core::StaticCoreError,
+    };
+
+    use std::{env, path::PathBuf, process};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("srm-param-file-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn write_file(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    /// Options as if srm were run with `--param-file` for each of param_files, in order.
+    fn options(graph: &Path, param_files: Vec<PathBuf>) -> Options {
+        Options {
+            graph: Some(graph.as_os_str().to_owned()),
+            list_params: false,
+            param_files,
+            dump_params: None,
+            print_stats: false,
+            metrics: None,
+            log_dir: None,
+        }
+    }
+
+    #[test]
+    fn later_files_override_earlier_ones() {
+        let dir = test_dir("order");
+        let base = write_file(
+            &dir,
+            "base.yaml",
+            "params:\n  - ['.a', 2]\n  - ['.c', false]\n",
+        );
+        let graph = write_file(
+            &dir,
+            "graph.yaml",
+            &format!(
+                "path: []\nnodes: []\nparams:\n  - ['.a', 1]\n  - ['.b', graph]\n  - ['.c', true]\nparam_files: ['{}']\n",
+                base.display()
+            ),
+        );
+        let first = write_file(
+            &dir,
+            "first.yaml",
+            "params:\n  - ['.a', 3]\n  - ['.b', first]\n",
+        );
+        let second = write_file(&dir, "second.yaml", "params:\n  - ['.a', 4]\n");
+
+        let core = node_graph::spawn_core(&options(&graph, vec![first, second])).unwrap();
+
+        assert_eq!(core.param_value(".a"), Some(Param::Integer(4)));
+        assert_eq!(
+            core.param_value(".b"),
+            Some(Param::String("first".to_string()))
+        );
+        assert_eq!(core.param_value(".c"), Some(Param::Boolean(false)));
+    }
+
+    #[test]
+    fn dumped_params_load_back() {
+        let dir = test_dir("dump");
+        let graph = write_file(
+            &dir,
+            "graph.yaml",
+            "path: []\nnodes: []\nparams:\n  - ['.int', 3]\n  - ['.real', 1.0]\n  - ['.flag', true]\n  - ['.text', '1']\n  - ['.nested.real', -2.5]\n",
+        );
+        let empty = write_file(&dir, "empty.yaml", "path: []\nnodes: []\n");
+        let dump = dir.join("dump.yaml");
+
+        let core = node_graph::spawn_core(&options(&graph, Vec::new())).unwrap();
+        core.param_dump("", &dump).unwrap(); // as --dump-params does
+
+        let params = core.params_under("");
+        assert_eq!(read(&dump).unwrap(), params);
+        assert_eq!(core.param_value(".real"), Some(Param::Real(1.0)));
+        assert_eq!(
+            core.param_value(".text"),
+            Some(Param::String("1".to_string()))
+        );
+
+        let reloaded = node_graph::spawn_core(&options(&empty, vec![dump])).unwrap();
+        assert_eq!(reloaded.params_under(""), params);
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        let dir = test_dir("keys");
+
+        for key in &["foo", ".a..b", ".a.", ".a~"] {
+            let path = write_file(
+                &dir,
+                "params.yaml",
+                &format!("params:\n  - ['{}', 1]\n", key),
+            );
+
+            match read(&path) {
+                Err(ParamFileError::InvalidParamKey(k)) => assert_eq!(&k, key),
+                r => panic!("expected InvalidParamKey, got {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_types_are_rejected() {
+        let dir = test_dir("types");
+        let path = write_file(&dir, "params.yaml", "params:\n  - ['.a', [1, 2]]\n");
+
+        match read(&path) {
+            Err(ParamFileError::Yaml(_)) => (),
+            r => panic!("expected Yaml, got {:?}", r),
+        }
+
+        // a param file can't change the type of a declared parameter
+        let graph = write_file(
+            &dir,
+            "graph.yaml",
+            "path: []\nnodes: []\nparam_decls:\n  - ['.a', {default: 0}]\n",
+        );
+        let overlay = write_file(&dir, "overlay.yaml", "params:\n  - ['.a', zero]\n");
+
+        match node_graph::spawn_core(&options(&graph, vec![overlay])).err() {
+            Some(GraphError::Param(key, StaticCoreError::ParamTypeDiffers)) => {
+                assert_eq!(key, ".a")
+            }
+            e => panic!("expected ParamTypeDiffers, got {:?}", e),
+        }
+    }
+}
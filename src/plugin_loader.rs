@@ -33,6 +33,7 @@ use libloading::Library;
 pub struct PluginLoader {
     paths: Vec<PathBuf>,
     plugins: HashMap<String, Arc<NodePlugin>>,
+    num_failures: usize,
 }
 
 impl PluginLoader {
@@ -40,6 +41,7 @@ impl PluginLoader {
         PluginLoader {
             paths,
             plugins: HashMap::new(),
+            num_failures: 0,
         }
     }
 
@@ -49,7 +51,14 @@ impl PluginLoader {
         match entry {
             Entry::Occupied(e) => Ok(e.get().clone()),
             Entry::Vacant(e) => {
-                let plugin = Arc::new(PluginLoader::do_load(&self.paths, e.key())?);
+                let plugin = match PluginLoader::do_load(&self.paths, e.key()) {
+                    Ok(p) => Arc::new(p),
+                    Err(err) => {
+                        self.num_failures += 1;
+
+                        return Err(err);
+                    }
+                };
                 e.insert(plugin.clone());
 
                 // we never delete a NodePlugin until the PluginLoader is dropped, so this is safe
@@ -60,6 +69,19 @@ impl PluginLoader {
         }
     }
 
+    /// Returns the name and path of each loaded plugin.
+    pub fn plugins(&self) -> Vec<(String, PathBuf)> {
+        self.plugins
+            .iter()
+            .map(|(name, p)| (name.clone(), p.path().to_path_buf()))
+            .collect()
+    }
+
+    /// Returns the number of plugins that failed to load.
+    pub fn num_failures(&self) -> usize {
+        self.num_failures
+    }
+
     fn do_load(paths: &[PathBuf], name: &str) -> Result<NodePlugin, LoadError> {
         for pathname in paths.iter().map(|p| make_lib_name(p, name)) {
             let lib = match Library::new(&pathname) {
@@ -71,7 +93,7 @@ impl PluginLoader {
                 }
             };
 
-            return NodePlugin::new(lib);
+            return NodePlugin::new(lib, pathname);
         }
 
         Err(LoadError::NoLibraryFound)
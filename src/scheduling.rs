@@ -0,0 +1,92 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::io;
+
+#[cfg(target_os = "linux")]
+use std::mem;
+
+use libc::c_int;
+
+#[cfg(target_os = "linux")]
+use libc::cpu_set_t;
+
+/// Restricts the calling thread to run only on the given CPUs.
+#[cfg(target_os = "linux")]
+pub fn set_cpu_affinity(cpus: &[usize]) -> io::Result<()> {
+    let max_cpus = 8 * mem::size_of::<cpu_set_t>();
+
+    if let Some(&cpu) = cpus.iter().find(|&&c| c >= max_cpus) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("CPU {} is out of range", cpu),
+        ));
+    }
+
+    unsafe {
+        let mut set: cpu_set_t = mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+
+        for &cpu in cpus.iter() {
+            libc::CPU_SET(cpu, &mut set);
+        }
+
+        // pid 0 is the calling thread
+        if libc::sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_cpu_affinity(_: &[usize]) -> io::Result<()> {
+    Err(unsupported("CPU affinity"))
+}
+
+/// Sets the niceness of the calling thread. Lowering it usually requires CAP_SYS_NICE.
+#[cfg(target_os = "linux")]
+pub fn set_nice(nice: c_int) -> io::Result<()> {
+    unsafe {
+        let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+
+        if libc::setpriority(libc::PRIO_PROCESS, tid, nice) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_nice(_: c_int) -> io::Result<()> {
+    Err(unsupported("per-thread niceness"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} is only supported on Linux", what),
+    )
+}
@@ -0,0 +1,247 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+mod node_ffi;
+mod publisher;
+mod subscriber;
+
+pub use self::{node_ffi::node_vtbl, publisher::Publisher, subscriber::Subscriber};
+
+use crate::{
+    ffi,
+    util::{ffi_to_str, str_to_ffi},
+};
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    ptr,
+    time::Duration,
+};
+
+use capnp::traits::{HasTypeId, Owned};
+use libc::c_int;
+use log::Level;
+
+/// A node written in Rust. Exported from a shared object by srm_export_node!.
+///
+/// run and stop are called concurrently from different threads, so both take &self.
+pub trait Node: Sized + Send + Sync + 'static {
+    type Error: Display;
+
+    /// The type the node reports to the core, e.g. `rust/lidar`.
+    const TYPE: &'static str;
+
+    /// Creates the node, advertising and subscribing to topics through core.
+    fn create(core: Core, name: &str) -> Result<Self, Self::Error>;
+
+    /// Begins computation. Should not return until stop is called.
+    fn run(&self) -> Result<(), Self::Error>;
+
+    /// Tells run to return. Should not block.
+    fn stop(&self) -> Result<(), Self::Error>;
+}
+
+/// Exports a type that implements srm::sdk::Node as the node in this shared object.
+///
+/// # Examples
+/// ```ignore
+/// srm::srm_export_node!(Chatter);
+/// ```
+#[macro_export]
+macro_rules! srm_export_node {
+    ($x:ty) => {
+        #[no_mangle]
+        pub extern "C" fn srm_Node_get_vtbl() -> *const $crate::ffi::NodeVtbl {
+            static VTBL: $crate::ffi::NodeVtbl = $crate::sdk::node_vtbl::<$x>();
+
+            &VTBL
+        }
+    };
+}
+
+/// A capnp struct that can be sent over a topic, i.e. `foo::Owned` for a struct `foo` compiled
+/// by capnpc-rust.
+pub trait Message: for<'a> Owned<'a> + 'static {
+    fn type_id() -> ffi::MsgType;
+}
+
+impl<T> Message for T
+where
+    T: for<'a> Owned<'a> + 'static,
+    <T as Owned<'static>>::Builder: HasTypeId,
+{
+    fn type_id() -> ffi::MsgType {
+        <<T as Owned<'static>>::Builder as HasTypeId>::type_id()
+    }
+}
+
+/// Safe wrapper around ffi::Core, the interface through which a node reaches the core.
+#[derive(Copy, Clone, Debug)]
+pub struct Core {
+    core: ffi::Core,
+}
+
+// the core's functions may be called from any thread
+unsafe impl Send for Core {}
+
+unsafe impl Sync for Core {}
+
+impl Core {
+    fn new(core: ffi::Core) -> Core {
+        assert!(!core.impl_ptr.is_null());
+        assert!(!core.vptr.is_null());
+
+        Core { core }
+    }
+
+    /// Returns the raw interface, for functionality that this wrapper doesn't cover.
+    pub fn as_ffi(&self) -> ffi::Core {
+        self.core
+    }
+
+    /// Advertises on a topic, creating it if it doesn't exist.
+    pub fn advertise<T: Message>(&self, topic: &str) -> Result<Publisher<T>, CoreError> {
+        Publisher::new(*self, topic)
+    }
+
+    /// Subscribes to a topic, creating it if it doesn't exist.
+    ///
+    /// callback may be invoked from any thread until the Subscriber is dropped. If it returns an
+    /// error, the error is logged and the message is counted as failed.
+    pub fn subscribe<T, F>(&self, topic: &str, callback: F) -> Result<Subscriber<T>, CoreError>
+    where
+        T: Message,
+        F: for<'a> Fn(<T as Owned<'a>>::Reader) -> capnp::Result<()> + Send + Sync + 'static,
+    {
+        Subscriber::new(*self, topic, Box::new(callback))
+    }
+
+    /// Returns the time in nanoseconds since the UNIX epoch, which is sim time if enabled.
+    pub fn now(&self) -> ffi::Time {
+        unsafe { (self.vtbl().now.unwrap())(self.core.impl_ptr) }
+    }
+
+    pub fn log(&self, level: Level, msg: &str) -> Result<(), CoreError> {
+        let err = unsafe {
+            (self.vtbl().log.unwrap())(
+                self.core.impl_ptr,
+                to_ffi_level(level),
+                str_to_ffi(msg),
+                ptr::null(),
+                0,
+            )
+        };
+
+        self.to_result(err)
+    }
+
+    /// Logs msg unless a record with the same key was logged less than period ago.
+    pub fn log_throttled(
+        &self,
+        level: Level,
+        key: &str,
+        period: Duration,
+        msg: &str,
+    ) -> Result<(), CoreError> {
+        let err = unsafe {
+            (self.vtbl().log_throttled.unwrap())(
+                self.core.impl_ptr,
+                to_ffi_level(level),
+                str_to_ffi(key),
+                period.as_nanos() as ffi::Duration,
+                str_to_ffi(msg),
+            )
+        };
+
+        self.to_result(err)
+    }
+
+    /// Logs msg only the first time this node logs with key.
+    pub fn log_once(&self, level: Level, key: &str, msg: &str) -> Result<(), CoreError> {
+        let err = unsafe {
+            (self.vtbl().log_once.unwrap())(
+                self.core.impl_ptr,
+                to_ffi_level(level),
+                str_to_ffi(key),
+                str_to_ffi(msg),
+            )
+        };
+
+        self.to_result(err)
+    }
+
+    fn vtbl(&self) -> &ffi::CoreVtbl {
+        unsafe { &*self.core.vptr }
+    }
+
+    fn to_result(self, err: c_int) -> Result<(), CoreError> {
+        match err {
+            0 => Ok(()),
+            x => {
+                let what = unsafe { (self.vtbl().get_err_msg.unwrap())(self.core.impl_ptr, x) };
+
+                Err(CoreError::new(x, what))
+            }
+        }
+    }
+}
+
+/// An error returned by the core or one of its objects.
+#[derive(Clone, Debug)]
+pub struct CoreError {
+    code: c_int,
+    what: String,
+}
+
+impl CoreError {
+    fn new(code: c_int, what: ffi::StrView) -> CoreError {
+        CoreError {
+            code,
+            what: unsafe { ffi_to_str(what) }.unwrap_or("").to_string(),
+        }
+    }
+
+    pub fn code(&self) -> c_int {
+        self.code
+    }
+}
+
+impl Error for CoreError {}
+
+impl Display for CoreError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.what, self.code)
+    }
+}
+
+fn to_ffi_level(level: Level) -> c_int {
+    let level = match level {
+        Level::Error => ffi::LogLevel::SRM_LOG_ERROR,
+        Level::Warn => ffi::LogLevel::SRM_LOG_WARN,
+        Level::Info => ffi::LogLevel::SRM_LOG_INFO,
+        Level::Debug => ffi::LogLevel::SRM_LOG_DEBUG,
+        Level::Trace => ffi::LogLevel::SRM_LOG_TRACE,
+    };
+
+    level as c_int
+}
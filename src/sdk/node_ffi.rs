@@ -0,0 +1,171 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::{Core, Node};
+use crate::{
+    ffi,
+    util::{ffi_to_str, str_to_ffi},
+};
+
+use std::{
+    any::Any,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use libc::{c_int, c_void};
+use parking_lot::Mutex;
+
+/// Returns the vtable that srm_export_node! exports for N.
+pub const fn node_vtbl<N: Node>() -> ffi::NodeVtbl {
+    ffi::NodeVtbl {
+        create: Some(create::<N>),
+        destroy: Some(destroy::<N>),
+        run: Some(run::<N>),
+        stop: Some(stop::<N>),
+        get_type: Some(get_type::<N>),
+        get_err_msg: Some(get_err_msg::<N>),
+    }
+}
+
+/// What impl_ptr points to. Allocated even if N::create fails, so that the error can be read
+/// back through get_err_msg until the core destroys it.
+struct NodeBox<N: Node> {
+    node: Option<N>,
+    errors: Mutex<Vec<String>>, // the message for code x is at x - 1
+}
+
+impl<N: Node> NodeBox<N> {
+    fn node(&self) -> &N {
+        self.node.as_ref().expect("node was not created")
+    }
+
+    fn to_code(&self, result: Result<Result<(), N::Error>, Box<dyn Any + Send>>) -> c_int {
+        let msg = match result {
+            Ok(Ok(())) => return 0,
+            Ok(Err(e)) => e.to_string(),
+            Err(payload) => panic_msg(payload),
+        };
+
+        let mut errors = self.errors.lock();
+
+        // the core doesn't accept empty strings
+        errors.push(if msg.is_empty() {
+            "unknown error".to_string()
+        } else {
+            msg
+        });
+
+        errors.len() as c_int
+    }
+}
+
+fn panic_msg(payload: Box<dyn Any + Send>) -> String {
+    let msg = if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.as_str()
+    } else {
+        "unknown panic"
+    };
+
+    format!("node panicked: {}", msg)
+}
+
+unsafe extern "C" fn create<N: Node>(
+    core: ffi::Core,
+    name: ffi::StrView,
+    impl_ptr: *mut *mut c_void,
+) -> c_int {
+    assert!(!impl_ptr.is_null());
+
+    let name = ffi_to_str(name).unwrap_or("");
+    let result = panic::catch_unwind(AssertUnwindSafe(|| N::create(Core::new(core), name)));
+
+    let mut node_box = Box::new(NodeBox {
+        node: None,
+        errors: Mutex::new(Vec::new()),
+    });
+
+    let err = match result {
+        Ok(Ok(n)) => {
+            node_box.node = Some(n);
+
+            0
+        }
+        Ok(Err(e)) => node_box.to_code(Ok(Err(e))),
+        Err(payload) => node_box.to_code(Err(payload)),
+    };
+
+    *impl_ptr = Box::into_raw(node_box) as *mut c_void;
+
+    err
+}
+
+unsafe extern "C" fn destroy<N: Node>(impl_ptr: *mut c_void) -> c_int {
+    if !impl_ptr.is_null() {
+        mem::drop(Box::from_raw(impl_ptr as *mut NodeBox<N>));
+    }
+
+    0
+}
+
+unsafe extern "C" fn run<N: Node>(impl_ptr: *mut c_void) -> c_int {
+    assert!(!impl_ptr.is_null());
+
+    let node_box = &*(impl_ptr as *const NodeBox<N>);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| node_box.node().run()));
+
+    node_box.to_code(result)
+}
+
+unsafe extern "C" fn stop<N: Node>(impl_ptr: *mut c_void) -> c_int {
+    assert!(!impl_ptr.is_null());
+
+    let node_box = &*(impl_ptr as *const NodeBox<N>);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| node_box.node().stop()));
+
+    node_box.to_code(result)
+}
+
+unsafe extern "C" fn get_type<N: Node>(_: *const c_void) -> ffi::StrView {
+    str_to_ffi(N::TYPE)
+}
+
+unsafe extern "C" fn get_err_msg<N: Node>(impl_ptr: *const c_void, err: c_int) -> ffi::StrView {
+    if impl_ptr.is_null() || err <= 0 {
+        return ffi::StrView {
+            data: ptr::null(),
+            len: 0,
+        };
+    }
+
+    let node_box = &*(impl_ptr as *const NodeBox<N>);
+    let errors = node_box.errors.lock();
+
+    // messages are never removed, so the view outlives the lock
+    match errors.get(err as usize - 1) {
+        Some(msg) => str_to_ffi(msg),
+        None => str_to_ffi("unknown error"),
+    }
+}
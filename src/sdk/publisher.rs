@@ -0,0 +1,327 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::{Core, CoreError, Message};
+use crate::{
+    ffi,
+    util::{ffi_to_str, str_to_ffi},
+};
+
+use std::{
+    any::Any,
+    cell::Cell,
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use capnp::{message::Allocator, traits::Owned, Word};
+use libc::{c_int, c_void};
+
+/// Publishes messages of type T on a topic. Disconnects when dropped.
+pub struct Publisher<T: Message> {
+    publisher: ffi::Publisher,
+    phantom: PhantomData<fn(T)>,
+}
+
+unsafe impl<T: Message> Send for Publisher<T> {}
+
+unsafe impl<T: Message> Sync for Publisher<T> {}
+
+impl<T: Message> Publisher<T> {
+    pub(super) fn new(core: Core, topic: &str) -> Result<Publisher<T>, CoreError> {
+        let params = ffi::AdvertiseParams {
+            msg_type: T::type_id(),
+            topic: str_to_ffi(topic),
+            size_hint: 0,
+            nonblocking: 0,
+            max_in_flight: 0,
+            on_complete: None,
+            on_complete_arg: ptr::null_mut(),
+        };
+
+        let mut publisher = ffi::Publisher {
+            impl_ptr: ptr::null_mut(),
+            vptr: ptr::null(),
+        };
+
+        let err =
+            unsafe { (core.vtbl().advertise.unwrap())(core.core.impl_ptr, params, &mut publisher) };
+        core.to_result(err)?;
+
+        Ok(Publisher {
+            publisher,
+            phantom: PhantomData,
+        })
+    }
+
+    pub fn topic(&self) -> &str {
+        unsafe {
+            ffi_to_str((self.vtbl().get_channel_name.unwrap())(
+                self.publisher.impl_ptr,
+            ))
+            .unwrap_or("")
+        }
+    }
+
+    /// Builds a message in memory owned by the core and publishes it to all subscribers.
+    ///
+    /// If build panics, the message isn't published and the panic is resumed once control has
+    /// returned from the core.
+    pub fn publish<F>(&mut self, build: F) -> Result<(), CoreError>
+    where
+        F: for<'a> FnOnce(<T as Owned<'a>>::Builder),
+    {
+        let mut state = BuildState {
+            build: Some(build),
+            alloc_err: Cell::new(0),
+            panic: None,
+        };
+
+        let err = unsafe {
+            (self.vtbl().publish.unwrap())(
+                self.publisher.impl_ptr,
+                Some(build_entry::<T, F>),
+                &mut state as *mut BuildState<F> as *mut c_void,
+            )
+        };
+
+        // a failed allocation is reported as an error rather than as the panic it caused
+        let err = match (state.alloc_err.get(), state.panic) {
+            (0, Some(payload)) => panic::resume_unwind(payload),
+            (0, None) => err,
+            (x, _) => x,
+        };
+
+        if err == 0 {
+            return Ok(());
+        }
+
+        let what = unsafe { (self.vtbl().get_err_msg.unwrap())(self.publisher.impl_ptr, err) };
+
+        Err(CoreError::new(err, what))
+    }
+
+    fn vtbl(&self) -> &ffi::PublisherVtbl {
+        unsafe { &*self.publisher.vptr }
+    }
+}
+
+impl<T: Message> Drop for Publisher<T> {
+    fn drop(&mut self) {
+        let err = unsafe { (self.vtbl().disconnect.unwrap())(self.publisher.impl_ptr) };
+        assert_eq!(err, 0, "couldn't disconnect publisher");
+    }
+}
+
+/// Passed through the core to build_entry, which reports back through it.
+struct BuildState<F> {
+    build: Option<F>,
+    alloc_err: Cell<c_int>,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+// panics must not unwind into the core, so they're caught here and resumed by Publisher::publish
+unsafe extern "C" fn build_entry<T, F>(builder: ffi::MsgBuilder, arg: *mut c_void) -> c_int
+where
+    T: Message,
+    F: for<'a> FnOnce(<T as Owned<'a>>::Builder),
+{
+    let state = &mut *(arg as *mut BuildState<F>);
+    let build = state.build.take().unwrap();
+    let alloc_err = &state.alloc_err;
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut message = capnp::message::Builder::new(RemoteAllocator { builder, alloc_err });
+
+        build(message.init_root());
+    }));
+
+    match result {
+        Ok(()) => 0,
+        Err(payload) => {
+            state.panic = Some(payload);
+
+            1
+        }
+    }
+}
+
+/// Allocates segments from the core through ffi::MsgBuilder.
+///
+/// capnp has no way to report a failed allocation, so it panics after storing the error code in
+/// alloc_err.
+struct RemoteAllocator<'a> {
+    builder: ffi::MsgBuilder,
+    alloc_err: &'a Cell<c_int>,
+}
+
+unsafe impl<'a> Allocator for RemoteAllocator<'a> {
+    fn allocate_segment(&mut self, minimum_size: u32) -> (*mut Word, u32) {
+        let mut segment = ffi::MsgSegment {
+            data: ptr::null_mut(),
+            len: minimum_size as ffi::Index,
+        };
+
+        let err = unsafe {
+            ((*self.builder.vptr).alloc_segment.unwrap())(self.builder.impl_ptr, &mut segment)
+        };
+        if err != 0 {
+            self.alloc_err.set(err);
+
+            panic!("couldn't allocate message segment");
+        }
+
+        (segment.data, segment.len as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use capnp::{
+        private::layout,
+        traits::{FromPointerBuilder, FromPointerReader, HasTypeId, SetPointerBuilder},
+    };
+
+    /// A struct with no fields, in the form generated by capnpc-rust.
+    struct Empty;
+
+    impl<'a> Owned<'a> for Empty {
+        type Reader = EmptyReader<'a>;
+        type Builder = EmptyBuilder<'a>;
+    }
+
+    struct EmptyReader<'a> {
+        reader: layout::StructReader<'a>,
+    }
+
+    impl<'a> FromPointerReader<'a> for EmptyReader<'a> {
+        fn get_from_pointer(reader: &layout::PointerReader<'a>) -> capnp::Result<EmptyReader<'a>> {
+            Ok(EmptyReader {
+                reader: reader.get_struct(ptr::null())?,
+            })
+        }
+    }
+
+    impl<'a, 'b> SetPointerBuilder<EmptyBuilder<'b>> for EmptyReader<'a> {
+        fn set_pointer_builder<'c>(
+            builder: layout::PointerBuilder<'c>,
+            value: EmptyReader<'a>,
+            canonicalize: bool,
+        ) -> capnp::Result<()> {
+            builder.set_struct(&value.reader, canonicalize)
+        }
+    }
+
+    struct EmptyBuilder<'a> {
+        _builder: layout::StructBuilder<'a>,
+    }
+
+    impl<'a> HasTypeId for EmptyBuilder<'a> {
+        fn type_id() -> u64 {
+            0xf3a9_1c2e_7b40_d651
+        }
+    }
+
+    impl<'a> FromPointerBuilder<'a> for EmptyBuilder<'a> {
+        fn init_pointer(builder: layout::PointerBuilder<'a>, _size: u32) -> EmptyBuilder<'a> {
+            EmptyBuilder {
+                _builder: builder.init_struct(EMPTY_SIZE),
+            }
+        }
+
+        fn get_from_pointer(
+            builder: layout::PointerBuilder<'a>,
+        ) -> capnp::Result<EmptyBuilder<'a>> {
+            Ok(EmptyBuilder {
+                _builder: builder.get_struct(EMPTY_SIZE, ptr::null())?,
+            })
+        }
+    }
+
+    const EMPTY_SIZE: layout::StructSize = layout::StructSize {
+        data: 0,
+        pointers: 0,
+    };
+
+    const OUT_OF_MEMORY: c_int = 12;
+
+    unsafe extern "C" fn fail_alloc(_: *mut c_void, _: *mut ffi::MsgSegment) -> c_int {
+        OUT_OF_MEMORY
+    }
+
+    unsafe extern "C" fn get_err_msg(_: *const c_void, _: c_int) -> ffi::StrView {
+        str_to_ffi("out of memory")
+    }
+
+    static FAILING_BUILDER: ffi::MsgBuilderVtbl = ffi::MsgBuilderVtbl {
+        alloc_segment: Some(fail_alloc),
+        get_err_msg: Some(get_err_msg),
+    };
+
+    /// Builds into FAILING_BUILDER, reporting build errors as the core does.
+    unsafe extern "C" fn publish(
+        _: *mut c_void,
+        build: Option<ffi::PublishFn>,
+        arg: *mut c_void,
+    ) -> c_int {
+        let builder = ffi::MsgBuilder {
+            impl_ptr: ptr::null_mut(),
+            vptr: &FAILING_BUILDER,
+        };
+
+        -(build.unwrap())(builder, arg)
+    }
+
+    unsafe extern "C" fn disconnect(_: *mut c_void) -> c_int {
+        0
+    }
+
+    static PUBLISHER: ffi::PublisherVtbl = ffi::PublisherVtbl {
+        get_channel_name: None,
+        get_channel_type: None,
+        publish: Some(publish),
+        disconnect: Some(disconnect),
+        get_err_msg: Some(get_err_msg),
+    };
+
+    #[test]
+    fn failed_allocation_is_an_error() {
+        let mut publisher = Publisher::<Empty> {
+            publisher: ffi::Publisher {
+                impl_ptr: ptr::null_mut(),
+                vptr: &PUBLISHER,
+            },
+            phantom: PhantomData,
+        };
+
+        match publisher.publish(|_| panic!("allocation should have failed")) {
+            Err(e) => {
+                assert_eq!(e.code(), OUT_OF_MEMORY);
+                assert_eq!(e.to_string(), "out of memory (12)");
+            }
+            Ok(()) => panic!("expected an error"),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::{Core, CoreError, Message};
+use crate::{ffi, util::str_to_ffi};
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use capnp::{
+    message::{ReaderOptions, SegmentArray},
+    traits::Owned,
+    Word,
+};
+use libc::{c_int, c_void};
+use log::Level;
+
+type CallbackFn<T> =
+    dyn for<'a> Fn(<T as Owned<'a>>::Reader) -> capnp::Result<()> + Send + Sync + 'static;
+
+/// Invokes a callback with each message of type T published on a topic. Disconnects when
+/// dropped.
+pub struct Subscriber<T: Message> {
+    subscriber: ffi::Subscriber,
+    callback: Box<Callback<T>>, // pointed to by the core until disconnected
+}
+
+struct Callback<T: Message> {
+    core: Core,
+    topic: String,
+    f: Box<CallbackFn<T>>,
+}
+
+unsafe impl<T: Message> Send for Subscriber<T> {}
+
+unsafe impl<T: Message> Sync for Subscriber<T> {}
+
+impl<T: Message> Subscriber<T> {
+    pub(super) fn new(
+        core: Core,
+        topic: &str,
+        f: Box<CallbackFn<T>>,
+    ) -> Result<Subscriber<T>, CoreError> {
+        let callback = Box::new(Callback {
+            core,
+            topic: topic.to_string(),
+            f,
+        });
+
+        let params = ffi::SubscribeParams {
+            msg_type: T::type_id(),
+            topic: str_to_ffi(topic),
+            callback: Some(callback_entry::<T>),
+            arg: &*callback as *const Callback<T> as *mut c_void,
+        };
+
+        let mut subscriber = ffi::Subscriber {
+            impl_ptr: ptr::null_mut(),
+            vptr: ptr::null(),
+        };
+
+        let err = unsafe {
+            (core.vtbl().subscribe.unwrap())(core.core.impl_ptr, params, &mut subscriber)
+        };
+        core.to_result(err)?;
+
+        Ok(Subscriber {
+            subscriber,
+            callback,
+        })
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.callback.topic
+    }
+
+    fn vtbl(&self) -> &ffi::SubscriberVtbl {
+        unsafe { &*self.subscriber.vptr }
+    }
+}
+
+impl<T: Message> Drop for Subscriber<T> {
+    /// Disconnects before the callback is dropped, so that it can't be invoked afterwards.
+    fn drop(&mut self) {
+        let err = unsafe { (self.vtbl().disconnect.unwrap())(self.subscriber.impl_ptr) };
+        assert_eq!(err, 0, "couldn't disconnect subscriber");
+    }
+}
+
+unsafe extern "C" fn callback_entry<T: Message>(msg: ffi::MsgView, arg: *mut c_void) -> c_int {
+    let callback = &*(arg as *const Callback<T>);
+
+    let segments: Vec<&[Word]> = slice::from_raw_parts(msg.segments, msg.num_segments as usize)
+        .iter()
+        .map(|s| slice::from_raw_parts(s.data, s.len as usize))
+        .collect();
+    let message = capnp::message::Reader::new(SegmentArray::new(&segments), ReaderOptions::new());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        message.get_root().and_then(|root| (callback.f)(root))
+    }));
+
+    let msg = match result {
+        Ok(Ok(())) => return 0,
+        Ok(Err(e)) => format!("couldn't handle message on '{}': {}", callback.topic, e),
+        Err(_) => format!("callback for '{}' panicked", callback.topic),
+    };
+
+    let _ = callback.core.log(Level::Warn, &msg);
+
+    1
+}
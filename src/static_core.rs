@@ -21,94 +21,521 @@
 // SOFTWARE.
 
 use super::{
-    alloc::CacheAlignedAllocator,
+    alloc::{self, CacheAlignedAllocator, SegmentPool, SegmentPoolStats},
+    clock::Clock,
+    clock_capnp,
     core::{self, CoreBase, MessageBuilder, ParamType},
     error_code::ErrorCode,
+    events_capnp::event::{self, Kind},
+    executor::{CallbackGroup, Dispatch, Executor, Job},
     ffi,
-    node::Node,
+    log_capnp::log_record,
+    logging,
+    node::{Node, NodeState},
+    node_plugin::NodePlugin,
+    param_file::{self, ParamFileError},
     plugin_loader::PluginLoader,
+    scheduling,
+    stats::{CallbackStats, CallbackStatsSnapshot, ChannelStats, ChannelStatsSnapshot},
+    synchronizer::{SyncInput, SyncState},
+    timer::{TimerEntry, TimerWheel},
     util, *,
 };
 
 use std::{
     borrow::Cow,
-    cell::UnsafeCell,
+    cell::{RefCell, UnsafeCell},
+    cmp,
+    collections::VecDeque,
     error::Error,
     fmt::{self, Display, Formatter},
     mem,
-    path::PathBuf,
-    sync::{Arc, Weak},
+    path::{Path, PathBuf},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
 };
 
-use hashbrown::{hash_map::Entry, HashMap};
+use hashbrown::{hash_map::Entry, HashMap, HashSet};
 use libc::{c_int, c_void};
 use lock_api::RwLockUpgradableReadGuard;
-use log::{debug, error, info, trace, warn};
-use parking_lot::{Mutex, RwLock};
-use rayon::prelude::*;
+use log::{
+    debug, error, info,
+    kv::{self, Key, Value, VisitSource},
+    trace, warn, Level, LevelFilter, Metadata, Record,
+};
+use parking_lot::{Condvar, Mutex, RwLock};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+/// The param that switches the core to sim time when set to true.
+const USE_SIM_TIME_KEY: &str = ".use_sim_time";
+
+/// The owner of subscriptions made by the core itself.
+const CORE_OWNER: &str = "srm";
+
+/// The reserved topic on which the core publishes events from capnp/events.capnp.
+const EVENTS_TOPIC: &str = "/srm/events";
+
+/// The first segment size of event messages, which only hold a couple of short strings.
+const EVENT_SEGMENT_WORDS: usize = 32;
+
+/// The bound on messages queued by the core's own publishers. Messages beyond it are dropped.
+const CORE_MAX_IN_FLIGHT: usize = 1024;
+
+/// The reserved topic on which the core publishes log records from capnp/log.capnp.
+const LOG_TOPIC: &str = "/srm/log";
+
+/// The per-node param that overrides RUST_LOG for records the node logs, e.g. `.lidar.log_level`.
+///
+/// An empty string defers to RUST_LOG.
+const LOG_LEVEL_SUFFIX: &str = ".log_level";
+
+const LOG_LEVELS: [&str; 7] = ["", "off", "error", "warn", "info", "debug", "trace"];
+
+/// The topic that drives the clock in sim time.
+const CLOCK_TOPIC: &str = "/clock";
 
 pub struct StaticCore {
     plugin_loader: Mutex<PluginLoader>,
     channels: Mutex<HashMap<String, Weak<Channel>>>,
     nodes: RwLock<HashMap<String, Arc<CoreInterface>>>,
+    thread_pools: RwLock<HashMap<String, Arc<ThreadPool>>>,
+    callback_groups: RwLock<HashMap<String, Arc<CallbackGroup>>>,
     params: RwLock<HashMap<String, Arc<Mutex<Param>>>>,
+    param_decls: RwLock<HashMap<String, ParamDecl>>,
+    param_watchers: Arc<WatchList>,
+    strict_params: AtomicBool,
+    clock: Arc<Clock>,
+    clock_subscriber: Mutex<Option<Subscriber>>,
+    next_publisher_id: AtomicU64,
+    events: Arc<CorePublisher>,
+    timers: TimerWheel,
     valid_key_re: Regex,
 }
 
 impl StaticCore {
     pub fn new(paths: Vec<PathBuf>) -> StaticCore {
+        let clock = Arc::new(Clock::new());
+        let events = Arc::new_cyclic(|weak| {
+            let channel = Channel::new(EVENTS_TOPIC.to_string(), event::TYPE_ID, weak.clone());
+
+            CorePublisher::new(Arc::new(channel), clock.clone(), 0)
+        });
+
+        let mut channels = HashMap::new();
+        channels.insert(EVENTS_TOPIC.to_string(), Arc::downgrade(&events.channel));
+
         StaticCore {
             plugin_loader: Mutex::new(PluginLoader::new(paths)),
-            channels: Mutex::new(HashMap::new()),
+            channels: Mutex::new(channels),
             nodes: RwLock::new(HashMap::new()),
+            thread_pools: RwLock::new(HashMap::new()),
+            callback_groups: RwLock::new(HashMap::new()),
             params: RwLock::new(HashMap::new()),
+            param_decls: RwLock::new(HashMap::new()),
+            param_watchers: Arc::new(WatchList::new()),
+            strict_params: AtomicBool::new(false),
+            timers: TimerWheel::new(clock.clone()),
+            clock,
+            clock_subscriber: Mutex::new(None),
+            next_publisher_id: AtomicU64::new(1), // 0 is the event publisher
+            events,
             valid_key_re: Regex::new(r"^(\.|(?:~\.))?[^.~]+(?:\.[^.~]+)*$").unwrap(),
         }
     }
 
     pub fn run(&self) {
-        let nodes: Vec<Arc<Node>> = {
+        let nodes: Vec<_> = {
             let nodes = self.nodes.read();
-            nodes.iter().map(|(_, c)| c.node().clone()).collect()
+            nodes
+                .iter()
+                .map(|(_, c)| (c.node().clone(), c.cpu_affinity.clone(), c.nice))
+                .collect()
         };
 
+        let timers = &self.timers;
+        let events = &self.events;
+
         crossbeam::scope(move |s| {
-            for node in nodes.into_iter() {
-                s.spawn(move |_| node.run().unwrap());
+            s.builder()
+                .name("srm-timers".to_string())
+                .spawn(move |_| timers.run())
+                .expect("couldn't spawn timer thread");
+
+            for (node, cpu_affinity, nice) in nodes.into_iter() {
+                s.builder()
+                    .name(node.name().to_string())
+                    .spawn(move |_| {
+                        apply_scheduling(node.name(), cpu_affinity.as_ref(), nice);
+
+                        events.publish(Event::NodeStarted { node: node.name() });
+                        let result = node.run();
+                        events.publish(Event::NodeStopped { node: node.name() });
+
+                        result.unwrap()
+                    })
+                    .expect("couldn't spawn node thread");
             }
         })
         .unwrap();
     }
 
+    /// Publishes every record that passes the log filter on LOG_TOPIC from now on.
+    ///
+    /// Records are only built while the topic has subscribers, and are delivered on the global
+    /// rayon pool rather than the logging thread. They're dropped if too many are in flight.
+    pub fn publish_logs(&self) -> Result<(), StaticCoreError> {
+        let channel = self.get_channel(LOG_TOPIC.to_string(), log_record::TYPE_ID)?;
+        let id = self.next_publisher_id.fetch_add(1, Ordering::Relaxed);
+        let publisher = CorePublisher::new(channel, self.clock.clone(), id);
+
+        logging::set_record_publisher(Box::new(move |record| {
+            if publisher.channel.has_callbacks() {
+                publisher.publish_log(record);
+            }
+        }));
+
+        Ok(())
+    }
+
     pub fn stop(&self) {
-        let interfaces = self.nodes.read();
+        {
+            let interfaces = self.nodes.read();
 
-        for i in interfaces.values() {
-            i.node().stop().unwrap();
+            for i in interfaces.values() {
+                i.node().stop().unwrap();
+            }
         }
+
+        self.timers.stop();
     }
 
-    fn subscribe(&self, params: ffi::SubscribeParams) -> Result<Subscriber, StaticCoreError> {
+    /// Returns the lifecycle state of each node.
+    pub fn node_states(&self) -> Vec<(String, NodeState)> {
+        let nodes = self.nodes.read();
+
+        nodes
+            .iter()
+            .map(|(name, i)| (name.clone(), i.node().state()))
+            .collect()
+    }
+
+    /// Returns the name and path of each loaded plugin and the number of plugins that failed to
+    /// load.
+    pub fn plugins(&self) -> (Vec<(String, PathBuf)>, usize) {
+        let plugin_loader = self.plugin_loader.lock();
+
+        (plugin_loader.plugins(), plugin_loader.num_failures())
+    }
+
+    pub fn num_params(&self) -> usize {
+        self.params.read().len()
+    }
+
+    /// Returns the statistics of each live channel.
+    pub fn topic_stats(&self) -> Vec<(String, TopicStats)> {
+        let channels = self.channels.lock();
+
+        channels
+            .iter()
+            .filter_map(|(name, c)| c.upgrade().map(|c| (name.clone(), c.stats())))
+            .collect()
+    }
+
+    fn get_topic_stats(&self, topic: &str) -> Result<TopicStats, StaticCoreError> {
+        let channel = self.channels.lock().get(topic).and_then(Weak::upgrade);
+
+        channel
+            .map(|c| c.stats())
+            .ok_or(StaticCoreError::NoSuchTopic)
+    }
+
+    pub fn add_thread_pool(
+        &self,
+        name: String,
+        num_threads: usize,
+    ) -> Result<(), ThreadPoolBuildError> {
+        let thread_name = name.clone();
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(move |i| format!("{}-{}", thread_name, i))
+            .build()?;
+
+        self.thread_pools.write().insert(name, Arc::new(pool));
+
+        Ok(())
+    }
+
+    /// Adds a callback group that can be shared by several nodes.
+    pub fn add_callback_group(&self, name: String) {
+        self.callback_groups
+            .write()
+            .insert(name, Arc::new(CallbackGroup::new()));
+    }
+
+    /// Returns the executor for a node and the executors for any of its subscriptions that are
+    /// assigned to a different thread pool.
+    fn make_executors(
+        &self,
+        options: &NodeOptions,
+    ) -> Result<(Arc<Executor>, SubscriptionExecutors), NodeError> {
+        let thread_pools = self.thread_pools.read();
+
+        let get_pool = |name: &String| {
+            thread_pools
+                .get(name)
+                .cloned()
+                .ok_or_else(|| NodeError::UnknownThreadPool(name.clone()))
+        };
+
+        let pool = match options.thread_pool {
+            Some(ref p) => Some(get_pool(p)?),
+            None => None,
+        };
+
+        let group = match options.callback_group {
+            Some(ref g) => Some(
+                self.callback_groups
+                    .read()
+                    .get(g)
+                    .cloned()
+                    .ok_or_else(|| NodeError::UnknownCallbackGroup(g.clone()))?,
+            ),
+            None if options.reentrant => None,
+            None => Some(Arc::new(CallbackGroup::new())), // exclusive to this node
+        };
+
+        let executor = Executor::new(pool, group);
+
+        let mut subscription_executors = HashMap::new();
+
+        for (topic, pool) in options.subscriptions.iter() {
+            let subscription_executor = executor.with_pool(get_pool(pool)?);
+            subscription_executors.insert(topic.clone(), Arc::new(subscription_executor));
+        }
+
+        Ok((Arc::new(executor), subscription_executors))
+    }
+
+    pub fn now(&self) -> i64 {
+        self.clock.now()
+    }
+
+    /// Switches the clock to sim time if the `.use_sim_time` param is set.
+    ///
+    /// In sim time, the clock and all timers are driven by messages published on `/clock`.
+    pub fn configure_clock(&self) -> Result<(), StaticCoreError> {
+        match self.param_value(USE_SIM_TIME_KEY) {
+            None | Some(Param::Boolean(false)) => Ok(()),
+            Some(Param::Boolean(true)) => self.enable_sim_time(),
+            Some(_) => Err(StaticCoreError::ParamTypeDiffers),
+        }
+    }
+
+    fn enable_sim_time(&self) -> Result<(), StaticCoreError> {
+        let mut clock_subscriber = self.clock_subscriber.lock();
+
+        if clock_subscriber.is_some() {
+            return Ok(());
+        }
+
+        let channel = self.get_channel(CLOCK_TOPIC.to_string(), clock_capnp::clock::TYPE_ID)?;
+        let callback = Callback::new(
+            StaticCore::on_clock,
+            self as *const StaticCore as *mut c_void,
+            Arc::new(Executor::default()),
+            CORE_OWNER,
+        );
+        let subscriber = Subscriber::new(channel, callback).ok_or(StaticCoreError::ChannelFull)?;
+
+        self.clock.enable_sim_time();
+        *clock_subscriber = Some(subscriber);
+        info!("using sim time from {}", CLOCK_TOPIC);
+
+        Ok(())
+    }
+
+    unsafe extern "C" fn on_clock(msg: ffi::MsgView, arg: *mut c_void) -> c_int {
+        let core = &*(arg as *const StaticCore);
+
+        let segments: Vec<&[capnp::Word]> =
+            slice::from_raw_parts(msg.segments, msg.num_segments as usize)
+                .iter()
+                .map(|s| slice::from_raw_parts(s.data, s.len as usize))
+                .collect();
+        let reader = capnp::message::Reader::new(
+            capnp::message::SegmentArray::new(&segments),
+            capnp::message::ReaderOptions::new(),
+        );
+
+        let time = match reader.get_root::<clock_capnp::clock::Reader>() {
+            Ok(r) => r.get_time(),
+            Err(e) => {
+                warn!("couldn't read message on {}: {}", CLOCK_TOPIC, e);
+
+                return 0;
+            }
+        };
+
+        match core.clock.set_sim_time(time) {
+            Some(prev) if time >= prev => core.timers.advance_to(time),
+            Some(prev) => {
+                warn!("sim time jumped backwards from {} to {}", prev, time);
+                core.timers.reset_to(time);
+            }
+            None => core.timers.reset_to(time),
+        }
+
+        0
+    }
+
+    fn create_timer(
+        &self,
+        params: ffi::TimerParams,
+        executor: Arc<Executor>,
+    ) -> Result<Timer, StaticCoreError> {
+        assert!(params.callback.is_some());
+
+        if params.period <= 0 {
+            return Err(StaticCoreError::InvalidTimerPeriod);
+        }
+
+        let entry = Arc::new(TimerEntry::new(
+            params.period,
+            params.oneshot != 0,
+            params.callback.unwrap(),
+            params.arg,
+            executor,
+        ));
+        self.timers.insert(entry.clone());
+
+        Ok(Timer { entry })
+    }
+
+    fn subscribe(
+        &self,
+        params: ffi::SubscribeParams,
+        executor: Arc<Executor>,
+        owner: &str,
+    ) -> Result<Subscriber, StaticCoreError> {
         assert!(params.callback.is_some());
 
         let name = unsafe { util::ffi_to_str(params.topic) }
             .unwrap()
             .to_string();
         let channel = self.get_channel(name, params.msg_type)?;
+        let callback = Callback::new(params.callback.unwrap(), params.arg, executor, owner);
 
-        Subscriber::new(channel, params.callback.unwrap(), params.arg)
-            .ok_or(StaticCoreError::ChannelFull)
+        Subscriber::new(channel, callback).ok_or(StaticCoreError::ChannelFull)
     }
 
-    fn advertise(&self, params: ffi::AdvertiseParams) -> Result<Publisher, StaticCoreError> {
+    fn advertise(
+        &self,
+        params: ffi::AdvertiseParams,
+        node_name: &str,
+    ) -> Result<Publisher, StaticCoreError> {
         let name = unsafe { util::ffi_to_str(params.topic) }
             .unwrap()
             .to_string();
+
+        if name == EVENTS_TOPIC || name == LOG_TOPIC {
+            return Err(StaticCoreError::ReservedTopic);
+        }
+
         let channel = self.get_channel(name, params.msg_type)?;
 
-        Ok(Publisher { channel })
+        let first_segment_words = match params.size_hint {
+            x if x < 0 => return Err(StaticCoreError::InvalidSizeHint),
+            0 => alloc::DEFAULT_FIRST_SEGMENT_WORDS,
+            x => (x as usize).div_ceil(mem::size_of::<capnp::Word>()),
+        };
+
+        let queue = if params.nonblocking != 0 {
+            let max_in_flight = match params.max_in_flight {
+                x if x < 0 => return Err(StaticCoreError::InvalidMaxInFlight),
+                0 => DEFAULT_MAX_IN_FLIGHT,
+                x => x as usize,
+            };
+
+            Some(Arc::new(PublishQueue::new(
+                max_in_flight,
+                params.on_complete,
+                params.on_complete_arg,
+            )))
+        } else {
+            None
+        };
+
+        Ok(Publisher {
+            channel,
+            queue,
+            first_segment_words,
+            clock: self.clock.clone(),
+            id: self.next_publisher_id.fetch_add(1, Ordering::Relaxed),
+            node_name: Arc::from(node_name),
+            sequence: 0,
+        })
+    }
+
+    fn synchronize(
+        &self,
+        params: ffi::SyncParams,
+        executor: Arc<Executor>,
+        owner: &str,
+    ) -> Result<Synchronizer, StaticCoreError> {
+        assert!(params.callback.is_some());
+
+        if params.num_topics <= 0 || params.queue_size <= 0 || params.slop < 0 {
+            return Err(StaticCoreError::InvalidSyncParams);
+        }
+
+        let slop = if params.policy == ffi::SyncPolicy::SRM_SYNC_EXACT as c_int {
+            0
+        } else if params.policy == ffi::SyncPolicy::SRM_SYNC_APPROXIMATE as c_int {
+            params.slop
+        } else {
+            return Err(StaticCoreError::InvalidSyncParams);
+        };
+
+        let topics = unsafe { slice::from_raw_parts(params.topics, params.num_topics as usize) };
+        let state = Arc::new(SyncState::new(
+            topics.len(),
+            slop,
+            params.queue_size as usize,
+            params.callback.unwrap(),
+            params.arg,
+        ));
+
+        let inputs: Vec<_> = (0..topics.len())
+            .map(|i| SyncInput::new(state.clone(), i))
+            .collect();
+
+        let subscribers = topics
+            .iter()
+            .zip(inputs.iter())
+            .map(|(t, input)| {
+                self.subscribe(
+                    ffi::SubscribeParams {
+                        msg_type: t.msg_type,
+                        topic: t.topic,
+                        callback: Some(SyncInput::callback),
+                        arg: input as *const SyncInput as *mut c_void,
+                    },
+                    executor.clone(),
+                    owner,
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Synchronizer {
+            subscribers,
+            inputs,
+        })
     }
 
     fn param_type(&self, key: &str) -> Option<ParamType> {
@@ -127,37 +554,215 @@ impl StaticCore {
         Some(guard.get_type())
     }
 
-    pub fn param_set(&self, key: String, mut value: Param) -> Option<Param> {
+    pub fn param_set(&self, key: String, value: Param) -> Result<Option<Param>, StaticCoreError> {
+        self.do_param_set(key, value, false)
+    }
+
+    /// Sets a parameter on behalf of the operator, e.g. from the node graph.
+    ///
+    /// Unlike `param_set`, read-only parameters may be modified.
+    pub fn param_configure(
+        &self,
+        key: String,
+        value: Param,
+    ) -> Result<Option<Param>, StaticCoreError> {
+        self.do_param_set(key, value, true)
+    }
+
+    fn do_param_set(
+        &self,
+        key: String,
+        value: Param,
+        allow_read_only: bool,
+    ) -> Result<Option<Param>, StaticCoreError> {
+        self.check_param(&key, &value, allow_read_only)?;
+
         let param = {
             let mut params = self.params.write();
 
-            match params.entry(key) {
+            match params.entry(key.clone()) {
                 Entry::Occupied(o) => o.get().clone(),
                 Entry::Vacant(v) => {
-                    v.insert(Arc::new(Mutex::new(value)));
+                    v.insert(Arc::new(Mutex::new(value.clone())));
+                    mem::drop(params);
+
+                    self.param_watchers.notify(&key, None, Some(&value));
+
+                    return Ok(None);
+                }
+            }
+        };
+
+        let old = {
+            let mut guard = param.lock();
+
+            mem::replace(&mut *guard, value.clone())
+        };
+
+        self.param_watchers.notify(&key, Some(&old), Some(&value));
+
+        Ok(Some(old))
+    }
+
+    /// Declares a parameter, setting it to its default value if it is not yet set.
+    ///
+    /// Once declared, all modifications to the parameter must satisfy the declaration.
+    pub fn param_declare(&self, key: String, decl: ParamDecl) -> Result<(), StaticCoreError> {
+        decl.check(&decl.default)?;
+
+        let mut decls = self.param_decls.write();
+
+        if decls.contains_key(&key) {
+            return Err(StaticCoreError::ParamAlreadyDeclared);
+        }
+
+        let default = {
+            let mut params = self.params.write();
+
+            match params.entry(key.clone()) {
+                Entry::Occupied(o) => {
+                    decl.check(&o.get().lock())?;
+
+                    None
+                }
+                Entry::Vacant(v) => {
+                    v.insert(Arc::new(Mutex::new(decl.default.clone())));
 
-                    return None;
+                    Some(decl.default.clone())
                 }
             }
         };
 
-        let mut guard = param.lock();
-        mem::swap(&mut *guard, &mut value);
+        decls.insert(key.clone(), decl);
+        mem::drop(decls);
+
+        if let Some(d) = default {
+            self.param_watchers.notify(&key, None, Some(&d));
+        }
+
+        Ok(())
+    }
+
+    /// Returns a copy of all parameters equal to or nested under prefix, sorted by key.
+    ///
+    /// An empty prefix matches all parameters.
+    pub fn params_under(&self, prefix: &str) -> Vec<(String, Param)> {
+        let cells: Vec<_> = {
+            let params = self.params.read();
+
+            params
+                .iter()
+                .filter(|(k, _)| is_key_under(k, prefix))
+                .map(|(k, p)| (k.clone(), p.clone()))
+                .collect()
+        };
+
+        let mut params: Vec<_> = cells
+            .into_iter()
+            .map(|(k, p)| {
+                let value = p.lock().clone();
+
+                (k, value)
+            })
+            .collect();
+
+        params.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+
+        params
+    }
+
+    /// Removes a parameter and its declaration, if any.
+    pub fn param_delete(&self, key: &str) -> Result<Param, StaticCoreError> {
+        let param = {
+            let mut decls = self.param_decls.write();
+
+            if let Some(true) = decls.get(key).map(|d| d.read_only) {
+                return Err(StaticCoreError::ParamReadOnly);
+            }
+
+            let param = {
+                let mut params = self.params.write();
+
+                params.remove(key).ok_or(StaticCoreError::NoSuchParam)?
+            };
+
+            decls.remove(key);
+
+            param
+        };
+
+        // other threads may still hold a reference to this parameter
+        let old = param.lock().clone();
+
+        self.param_watchers.notify(key, Some(&old), None);
+
+        Ok(old)
+    }
+
+    /// Writes all parameters equal to or nested under prefix to a param file.
+    pub fn param_dump(&self, prefix: &str, path: &Path) -> Result<(), ParamFileError> {
+        param_file::write(path, self.params_under(prefix))
+    }
+
+    /// Returns all parameter declarations, sorted by key.
+    pub fn param_decls(&self) -> Vec<(String, ParamDecl)> {
+        let mut decls: Vec<_> = {
+            let decls = self.param_decls.read();
+
+            decls.iter().map(|(k, d)| (k.clone(), d.clone())).collect()
+        };
+
+        decls.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+
+        decls
+    }
+
+    /// If set, parameters must be declared before they can be set.
+    pub fn set_strict_params(&self, strict: bool) {
+        self.strict_params.store(strict, Ordering::Relaxed);
+    }
+
+    fn check_param(
+        &self,
+        key: &str,
+        value: &Param,
+        allow_read_only: bool,
+    ) -> Result<(), StaticCoreError> {
+        let decls = self.param_decls.read();
+
+        match decls.get(key) {
+            Some(d) => {
+                if d.read_only && !allow_read_only {
+                    return Err(StaticCoreError::ParamReadOnly);
+                }
 
-        Some(value)
+                d.check(value)
+            }
+            None if self.strict_params.load(Ordering::Relaxed) => {
+                Err(StaticCoreError::ParamUndeclared)
+            }
+            None => Ok(()),
+        }
     }
 
     pub fn is_param_key_valid(&self, key: &str) -> bool {
         self.valid_key_re.is_match(key)
     }
 
+    /// Returns a copy of the current value of a parameter.
+    pub fn param_value(&self, key: &str) -> Option<Param> {
+        self.param_get(key).map(|p| p.lock().clone())
+    }
+
     fn param_get(&self, key: &str) -> Option<Arc<Mutex<Param>>> {
         let params = self.params.read();
 
         params.get(key).map(|v| v.clone())
     }
 
-    fn param_swap(&self, key: String, mut value: Param) -> Result<Param, StaticCoreError> {
+    fn param_swap(&self, key: String, value: Param) -> Result<Param, StaticCoreError> {
+        self.check_param(&key, &value, false)?;
+
         let param = {
             let mut params = self.params.write();
 
@@ -167,59 +772,246 @@ impl StaticCore {
             }
         };
 
-        let mut guard = param.lock();
+        let old = {
+            let mut guard = param.lock();
+
+            if guard.get_type() != value.get_type() {
+                return Err(StaticCoreError::ParamTypeDiffers);
+            }
 
-        if guard.get_type() == value.get_type() {
-            mem::swap(&mut *guard, &mut value);
+            mem::replace(&mut *guard, value.clone())
+        };
 
-            Ok(value)
-        } else {
-            Err(StaticCoreError::ParamTypeDiffers)
-        }
+        self.param_watchers.notify(&key, Some(&old), Some(&value));
+
+        Ok(old)
     }
 
-    fn get_channel(&self, name: String, msg_type: u64) -> Result<Arc<Channel>, StaticCoreError> {
-        let mut channels = self.channels.lock();
+    fn param_compare_swap(
+        &self,
+        key: String,
+        expected: &Param,
+        desired: Param,
+    ) -> Result<bool, StaticCoreError> {
+        self.check_param(&key, &desired, false)?;
 
-        let make_channel = |n| Arc::new(Channel::new(n, msg_type));
+        let param = self.param_get(&key).ok_or(StaticCoreError::NoSuchParam)?;
 
-        match channels.entry(name) {
-            Entry::Vacant(e) => {
-                let channel = make_channel(e.key().clone());
-                e.insert(Arc::downgrade(&channel));
+        let old = {
+            let mut guard = param.lock();
 
-                Ok(channel)
+            if guard.get_type() != desired.get_type() {
+                return Err(StaticCoreError::ParamTypeDiffers);
+            } else if *guard != *expected {
+                return Ok(false);
             }
-            Entry::Occupied(mut e) => {
-                match e.get_mut().upgrade() {
-                    Some(c) => {
-                        if c.msg_type() != msg_type {
-                            return Err(StaticCoreError::ChannelTypeDiffers);
-                        }
 
-                        Ok(c)
-                    }
+            mem::replace(&mut *guard, desired.clone())
+        };
+
+        self.param_watchers.notify(&key, Some(&old), Some(&desired));
+
+        Ok(true)
+    }
+
+    /// Sets several parameters atomically.
+    ///
+    /// If any assignment is invalid, no parameters are modified. If a key is assigned to more
+    /// than once, the last assignment takes precedence.
+    pub fn param_transaction(
+        &self,
+        mut assignments: Vec<(String, Param)>,
+    ) -> Result<(), StaticCoreError> {
+        assignments.reverse();
+        assignments.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        assignments.dedup_by(|(lhs, _), (rhs, _)| lhs == rhs);
+
+        for (key, value) in assignments.iter() {
+            self.check_param(key, value, false)?;
+        }
+
+        let olds: Vec<Option<Param>> = {
+            let mut params = self.params.write();
+
+            let cells: Vec<_> = assignments
+                .iter()
+                .map(|(k, _)| params.get(k).cloned())
+                .collect();
+
+            // assignments are sorted by key, so all transactions lock parameters in the same order
+            let mut guards: Vec<_> = cells.iter().map(|c| c.as_ref().map(|p| p.lock())).collect();
+
+            assignments
+                .iter()
+                .zip(guards.iter_mut())
+                .map(|((key, value), guard)| match guard {
+                    Some(g) => Some(mem::replace(&mut **g, value.clone())),
                     None => {
-                        let channel = make_channel(e.key().clone()); // all subscribers destroyed
-                        e.insert(Arc::downgrade(&channel));
+                        params.insert(key.clone(), Arc::new(Mutex::new(value.clone())));
+
+                        None
+                    }
+                })
+                .collect()
+        };
+
+        for ((key, value), old) in assignments.iter().zip(olds.iter()) {
+            self.param_watchers.notify(key, old.as_ref(), Some(value));
+        }
+
+        Ok(())
+    }
+
+    /// Declares a node's log level param and keeps the logger in sync with it.
+    fn configure_log_level(
+        &self,
+        node: &str,
+        level: Option<&String>,
+    ) -> Result<(), StaticCoreError> {
+        let key = format!(".{}{}", node, LOG_LEVEL_SUFFIX);
+
+        // watch first so that the logger sees the value set below
+        self.param_watchers
+            .insert(key.clone(), StaticCore::on_log_level, ptr::null_mut());
+
+        let decl = ParamDecl {
+            default: Param::String(String::new()),
+            description: format!("log level of node '{}', or empty to use RUST_LOG", node),
+            min: None,
+            max: None,
+            allowed: Some(
+                LOG_LEVELS
+                    .iter()
+                    .map(|l| Param::String(l.to_string()))
+                    .collect(),
+            ),
+            read_only: false,
+        };
+
+        match self.param_declare(key.clone(), decl) {
+            Ok(()) | Err(StaticCoreError::ParamAlreadyDeclared) => (),
+            Err(e) => return Err(e),
+        }
+
+        if let Some(level) = level {
+            self.param_configure(key, Param::String(level.to_lowercase()))?;
+        }
+
+        Ok(())
+    }
+
+    unsafe extern "C" fn on_log_level(
+        key: ffi::StrView,
+        _: *const ffi::ParamView,
+        new: *const ffi::ParamView,
+        _: *mut c_void,
+    ) -> c_int {
+        let key = util::ffi_to_str(key).unwrap();
+
+        let node = match key.strip_suffix(LOG_LEVEL_SUFFIX) {
+            Some(n) => &n[1..], // strip the leading dot
+            None => return 0,   // nested under the param
+        };
+
+        let level = match new.as_ref().and_then(|v| Param::from_ffi(v)) {
+            Some(Param::String(ref l)) if !l.is_empty() => l.parse::<LevelFilter>().ok(),
+            _ => None,
+        };
+
+        logging::set_target_level(node, level);
+
+        0
+    }
+
+    fn param_watch(
+        &self,
+        key: String,
+        f: ffi::ParamWatchCallback,
+        arg: *mut c_void,
+    ) -> ParamWatcher {
+        ParamWatcher::new(self.param_watchers.clone(), key, f, arg)
+    }
+
+    fn get_channel(&self, name: String, msg_type: u64) -> Result<Arc<Channel>, StaticCoreError> {
+        let (channel, created) = {
+            let mut channels = self.channels.lock();
+
+            let make_channel =
+                |n| Arc::new(Channel::new(n, msg_type, Arc::downgrade(&self.events)));
+
+            match channels.entry(name) {
+                Entry::Vacant(e) => {
+                    let channel = make_channel(e.key().clone());
+                    e.insert(Arc::downgrade(&channel));
+
+                    (channel, true)
+                }
+                Entry::Occupied(mut e) => {
+                    match e.get_mut().upgrade() {
+                        Some(c) => {
+                            if c.msg_type() != msg_type {
+                                return Err(StaticCoreError::ChannelTypeDiffers);
+                            }
+
+                            (c, false)
+                        }
+                        None => {
+                            let channel = make_channel(e.key().clone()); // all subscribers destroyed
+                            e.insert(Arc::downgrade(&channel));
 
-                        Ok(channel)
+                            (channel, true)
+                        }
                     }
                 }
             }
+        };
+
+        // published without holding the lock, since subscribers may create channels in turn
+        if created {
+            self.events.publish(Event::ChannelCreated {
+                topic: channel.name(),
+                msg_type,
+            });
         }
+
+        Ok(channel)
     }
 }
 
-pub fn add_node(core: &Arc<StaticCore>, name: String, tp: String) -> Result<(), NodeError> {
+pub fn add_node(
+    core: &Arc<StaticCore>,
+    name: String,
+    tp: String,
+    options: &NodeOptions,
+) -> Result<(), NodeError> {
     let plugin = {
         let mut plugin_loader = core.plugin_loader.lock();
         plugin_loader.load(tp).map_err(|e| NodeError::Load(e))?
     };
 
+    start_node(core, name, plugin, options)
+}
+
+/// Creates a node from a loaded plugin and adds it to the core.
+fn start_node(
+    core: &Arc<StaticCore>,
+    name: String,
+    plugin: Arc<NodePlugin>,
+    options: &NodeOptions,
+) -> Result<(), NodeError> {
+    let (executor, subscription_executors) = core.make_executors(options)?;
+
+    core.configure_log_level(&name, options.log_level.as_ref())
+        .map_err(NodeError::LogLevel)?;
+
     let interface = Arc::new(CoreInterface {
         core: Arc::downgrade(&core),
         node: UnsafeCell::new(Arc::new(Node::new(plugin, name.clone()))),
+        executor,
+        subscription_executors,
+        cpu_affinity: options.cpu_affinity.clone(),
+        nice: options.nice,
+        log_limits: Mutex::new(LogLimits::default()),
     });
 
     Arc::get_mut(unsafe { interface.node_mut() })
@@ -236,7 +1028,7 @@ pub fn add_node(core: &Arc<StaticCore>, name: String, tp: String) -> Result<(),
     Ok(())
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum Param {
     Integer(isize),
@@ -254,19 +1046,174 @@ impl Param {
             Param::String(_) => ParamType::String,
         }
     }
-}
 
-struct CoreInterface {
-    core: Weak<StaticCore>,
-    node: UnsafeCell<Arc<Node>>,
-}
+    /// Copies a parameter out of an FFI-compatible view.
+    ///
+    /// Returns None if the view does not have a valid type.
+    unsafe fn from_ffi(view: &ffi::ParamView) -> Option<Param> {
+        let param = match view.tp {
+            x if x == ffi::ParamType::SRM_INTEGER as c_int => Param::Integer(view.value.integer),
+            x if x == ffi::ParamType::SRM_BOOLEAN as c_int => {
+                Param::Boolean(view.value.boolean != 0)
+            }
+            x if x == ffi::ParamType::SRM_REAL as c_int => Param::Real(view.value.real),
+            x if x == ffi::ParamType::SRM_STRING as c_int => {
+                Param::String(util::ffi_to_str(view.value.string).unwrap().to_string())
+            }
+            _ => return None,
+        };
 
-impl CoreInterface {
-    fn node(&self) -> &Arc<Node> {
-        unsafe { &*self.node.get() }
+        Some(param)
     }
 
-    unsafe fn node_mut(&self) -> &mut Arc<Node> {
+    /// Borrows this parameter as an FFI-compatible view.
+    ///
+    /// String views point into self, so the view must not be used after self is dropped or
+    /// modified.
+    fn as_ffi(&self) -> ffi::ParamView {
+        match self {
+            Param::Integer(i) => ffi::ParamView {
+                tp: ffi::ParamType::SRM_INTEGER as c_int,
+                value: ffi::ParamValue { integer: *i },
+            },
+            Param::Boolean(b) => ffi::ParamView {
+                tp: ffi::ParamType::SRM_BOOLEAN as c_int,
+                value: ffi::ParamValue {
+                    boolean: *b as c_int,
+                },
+            },
+            Param::Real(r) => ffi::ParamView {
+                tp: ffi::ParamType::SRM_REAL as c_int,
+                value: ffi::ParamValue { real: *r },
+            },
+            Param::String(s) => ffi::ParamView {
+                tp: ffi::ParamType::SRM_STRING as c_int,
+                value: ffi::ParamValue {
+                    string: util::str_to_ffi(s),
+                },
+            },
+        }
+    }
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Param::Integer(i) => write!(f, "{}", i),
+            Param::Boolean(b) => write!(f, "{}", b),
+            Param::Real(r) => write!(f, "{:?}", r),
+            Param::String(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// Constraints on the value of a parameter.
+///
+/// The type of a declared parameter is the type of its default value.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ParamDecl {
+    pub default: Param,
+    #[serde(default)]
+    pub description: String,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub allowed: Option<Vec<Param>>,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl ParamDecl {
+    /// Copies a declaration out of its FFI-compatible form.
+    unsafe fn from_ffi(decl: &ffi::ParamDecl) -> Result<ParamDecl, StaticCoreError> {
+        let allowed = if decl.allowed_values.is_null() {
+            None
+        } else {
+            let values =
+                slice::from_raw_parts(decl.allowed_values, decl.num_allowed_values as usize);
+            let allowed: Option<Vec<Param>> = values.iter().map(|v| Param::from_ffi(v)).collect();
+
+            Some(allowed.ok_or(StaticCoreError::InvalidParamType)?)
+        };
+
+        Ok(ParamDecl {
+            default: Param::from_ffi(&decl.default_value)
+                .ok_or(StaticCoreError::InvalidParamType)?,
+            description: util::ffi_to_str(decl.description).unwrap_or("").to_string(),
+            min: decl.min.as_ref().cloned(),
+            max: decl.max.as_ref().cloned(),
+            allowed,
+            read_only: decl.read_only != 0,
+        })
+    }
+
+    pub fn get_type(&self) -> ParamType {
+        self.default.get_type()
+    }
+
+    /// Checks that value satisfies this declaration. Read-only status is not checked.
+    fn check(&self, value: &Param) -> Result<(), StaticCoreError> {
+        if value.get_type() != self.get_type() {
+            return Err(StaticCoreError::ParamTypeDiffers);
+        }
+
+        let as_real = match value {
+            Param::Integer(i) => Some(*i as f64),
+            Param::Real(r) => Some(*r),
+            _ => None,
+        };
+
+        if let Some(x) = as_real {
+            let below = self.min.iter().any(|&m| x < m);
+            let above = self.max.iter().any(|&m| x > m);
+
+            if below || above {
+                return Err(StaticCoreError::ParamOutOfRange);
+            }
+        }
+
+        match self.allowed {
+            Some(ref allowed) if !allowed.contains(value) => Err(StaticCoreError::ParamNotAllowed),
+            _ => Ok(()),
+        }
+    }
+}
+
+struct CoreInterface {
+    core: Weak<StaticCore>,
+    node: UnsafeCell<Arc<Node>>,
+    executor: Arc<Executor>,
+    subscription_executors: SubscriptionExecutors,
+    cpu_affinity: Option<Vec<usize>>,
+    nice: Option<i32>,
+    log_limits: Mutex<LogLimits>,
+}
+
+/// The node's state for log_throttled and log_once, keyed by the key the node passed.
+#[derive(Default)]
+struct LogLimits {
+    throttled: HashMap<String, (Instant, usize)>, // (last logged, number suppressed since)
+    once: HashSet<String>,
+}
+
+type SubscriptionExecutors = HashMap<String, Arc<Executor>>; // keyed by topic
+
+fn to_level(level: c_int) -> Result<Level, StaticCoreError> {
+    match level {
+        x if x == ffi::LogLevel::SRM_LOG_ERROR as c_int => Ok(Level::Error),
+        x if x == ffi::LogLevel::SRM_LOG_WARN as c_int => Ok(Level::Warn),
+        x if x == ffi::LogLevel::SRM_LOG_INFO as c_int => Ok(Level::Info),
+        x if x == ffi::LogLevel::SRM_LOG_DEBUG as c_int => Ok(Level::Debug),
+        x if x == ffi::LogLevel::SRM_LOG_TRACE as c_int => Ok(Level::Trace),
+        _ => Err(StaticCoreError::InvalidLogLevel),
+    }
+}
+
+impl CoreInterface {
+    fn node(&self) -> &Arc<Node> {
+        unsafe { &*self.node.get() }
+    }
+
+    unsafe fn node_mut(&self) -> &mut Arc<Node> {
         &mut *self.node.get()
     }
 
@@ -274,6 +1221,31 @@ impl CoreInterface {
         self.node().name()
     }
 
+    fn is_log_enabled(&self, level: Level) -> bool {
+        level <= log::max_level()
+            && log::logger().enabled(&Metadata::builder().level(level).target(self.name()).build())
+    }
+
+    fn log_record(&self, level: Level, msg: &str, fields: &[(&str, &str)]) {
+        if level <= log::max_level() {
+            log::logger().log(
+                &Record::builder()
+                    .level(level)
+                    .target(self.name())
+                    .args(format_args!("{}", msg))
+                    .key_values(&fields)
+                    .build(),
+            );
+        }
+    }
+
+    fn executor_for(&self, topic: &str) -> Arc<Executor> {
+        self.subscription_executors
+            .get(topic)
+            .unwrap_or(&self.executor)
+            .clone()
+    }
+
     fn resolve<'a>(&self, key: &'a str) -> Result<Cow<'a, str>, StaticCoreError> {
         if !self.core.upgrade().unwrap().is_param_key_valid(key) {
             return Err(StaticCoreError::InvalidKey);
@@ -299,6 +1271,9 @@ impl core::Core for CoreInterface {
     type Error = StaticCoreError;
     type Publisher = Publisher;
     type Subscriber = Subscriber;
+    type Synchronizer = Synchronizer;
+    type ParamWatcher = ParamWatcher;
+    type Timer = Timer;
 
     fn get_type(&self) -> &'static str {
         assert!(self.core.upgrade().is_some());
@@ -309,13 +1284,45 @@ impl core::Core for CoreInterface {
     fn subscribe(&self, params: ffi::SubscribeParams) -> Result<Subscriber, StaticCoreError> {
         assert!(self.core.upgrade().is_some());
 
-        self.core.upgrade().unwrap().subscribe(params)
+        let topic = unsafe { util::ffi_to_str(params.topic) }.unwrap();
+        let executor = self.executor_for(topic);
+
+        self.core
+            .upgrade()
+            .unwrap()
+            .subscribe(params, executor, self.name())
     }
 
     fn advertise(&self, params: ffi::AdvertiseParams) -> Result<Publisher, StaticCoreError> {
         assert!(self.core.upgrade().is_some());
 
-        self.core.upgrade().unwrap().advertise(params)
+        self.core.upgrade().unwrap().advertise(params, self.name())
+    }
+
+    fn synchronize(&self, params: ffi::SyncParams) -> Result<Synchronizer, StaticCoreError> {
+        self.core
+            .upgrade()
+            .unwrap()
+            .synchronize(params, self.executor.clone(), self.name())
+    }
+
+    fn get_topic_stats(&self, topic: &str) -> Result<ffi::TopicStats, StaticCoreError> {
+        self.core
+            .upgrade()
+            .unwrap()
+            .get_topic_stats(topic)
+            .map(|s| s.as_ffi())
+    }
+
+    fn now(&self) -> i64 {
+        self.core.upgrade().unwrap().now()
+    }
+
+    fn create_timer(&self, params: ffi::TimerParams) -> Result<Timer, StaticCoreError> {
+        self.core
+            .upgrade()
+            .unwrap()
+            .create_timer(params, self.executor.clone())
     }
 
     fn log_error(&self, msg: &str) -> Result<(), StaticCoreError> {
@@ -348,6 +1355,88 @@ impl core::Core for CoreInterface {
         Ok(())
     }
 
+    fn log(&self, level: c_int, msg: &str, fields: &[(&str, &str)]) -> Result<(), StaticCoreError> {
+        self.log_record(to_level(level)?, msg, fields);
+
+        Ok(())
+    }
+
+    fn log_throttled(
+        &self,
+        level: c_int,
+        key: &str,
+        period: ffi::Duration,
+        msg: &str,
+    ) -> Result<(), StaticCoreError> {
+        let level = to_level(level)?;
+
+        if period <= 0 {
+            return Err(StaticCoreError::InvalidLogPeriod);
+        }
+
+        // records that would be filtered out don't start a period
+        if !self.is_log_enabled(level) {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+
+        let num_suppressed = {
+            let mut limits = self.log_limits.lock();
+
+            match limits.throttled.get_mut(key) {
+                Some((last, num_suppressed)) => {
+                    if now.duration_since(*last) < Duration::from_nanos(period as u64) {
+                        *num_suppressed += 1;
+
+                        return Ok(());
+                    }
+
+                    *last = now;
+
+                    mem::take(num_suppressed)
+                }
+                None => {
+                    limits.throttled.insert(key.to_string(), (now, 0));
+
+                    0
+                }
+            }
+        };
+
+        // logged without holding the lock, since records may be published to subscribers
+        if num_suppressed > 0 {
+            let num_suppressed = num_suppressed.to_string();
+            self.log_record(level, msg, &[("suppressed", &num_suppressed)]);
+        } else {
+            self.log_record(level, msg, &[]);
+        }
+
+        Ok(())
+    }
+
+    fn log_once(&self, level: c_int, key: &str, msg: &str) -> Result<(), StaticCoreError> {
+        let level = to_level(level)?;
+
+        if !self.is_log_enabled(level) {
+            return Ok(());
+        }
+
+        {
+            let mut limits = self.log_limits.lock();
+
+            if limits.once.contains(key) {
+                return Ok(());
+            }
+
+            limits.once.insert(key.to_string());
+        }
+
+        self.log_record(level, msg, &[]);
+
+        Ok(())
+    }
+
     fn param_type(&self, key: &str) -> Result<ParamType, StaticCoreError> {
         let resolved = self.resolve(key)?;
 
@@ -361,13 +1450,11 @@ impl core::Core for CoreInterface {
     fn param_seti(&self, key: &str, value: isize) -> Result<(), StaticCoreError> {
         let resolved = self.resolve(key)?;
 
-        let _ = self
-            .core
+        self.core
             .upgrade()
             .unwrap()
-            .param_set(resolved.into_owned(), Param::Integer(value));
-
-        Ok(())
+            .param_set(resolved.into_owned(), Param::Integer(value))
+            .map(|_| ())
     }
 
     fn param_geti(&self, key: &str) -> Result<isize, StaticCoreError> {
@@ -388,6 +1475,21 @@ impl core::Core for CoreInterface {
             })
     }
 
+    fn param_compare_swapi(
+        &self,
+        key: &str,
+        expected: isize,
+        desired: isize,
+    ) -> Result<bool, StaticCoreError> {
+        let resolved = self.resolve(key)?;
+
+        self.core.upgrade().unwrap().param_compare_swap(
+            resolved.into_owned(),
+            &Param::Integer(expected),
+            Param::Integer(desired),
+        )
+    }
+
     fn param_swapi(&self, key: &str, value: isize) -> Result<isize, StaticCoreError> {
         let resolved = self.resolve(key)?;
 
@@ -404,13 +1506,11 @@ impl core::Core for CoreInterface {
     fn param_setb(&self, key: &str, value: bool) -> Result<(), StaticCoreError> {
         let resolved = self.resolve(key)?;
 
-        let _ = self
-            .core
+        self.core
             .upgrade()
             .unwrap()
-            .param_set(resolved.into_owned(), Param::Boolean(value));
-
-        Ok(())
+            .param_set(resolved.into_owned(), Param::Boolean(value))
+            .map(|_| ())
     }
 
     fn param_getb(&self, key: &str) -> Result<bool, StaticCoreError> {
@@ -431,6 +1531,21 @@ impl core::Core for CoreInterface {
             })
     }
 
+    fn param_compare_swapb(
+        &self,
+        key: &str,
+        expected: bool,
+        desired: bool,
+    ) -> Result<bool, StaticCoreError> {
+        let resolved = self.resolve(key)?;
+
+        self.core.upgrade().unwrap().param_compare_swap(
+            resolved.into_owned(),
+            &Param::Boolean(expected),
+            Param::Boolean(desired),
+        )
+    }
+
     fn param_swapb(&self, key: &str, value: bool) -> Result<bool, StaticCoreError> {
         let resolved = self.resolve(key)?;
 
@@ -447,13 +1562,11 @@ impl core::Core for CoreInterface {
     fn param_setr(&self, key: &str, value: f64) -> Result<(), StaticCoreError> {
         let resolved = self.resolve(key)?;
 
-        let _ = self
-            .core
+        self.core
             .upgrade()
             .unwrap()
-            .param_set(resolved.into_owned(), Param::Real(value));
-
-        Ok(())
+            .param_set(resolved.into_owned(), Param::Real(value))
+            .map(|_| ())
     }
 
     fn param_getr(&self, key: &str) -> Result<f64, StaticCoreError> {
@@ -474,6 +1587,21 @@ impl core::Core for CoreInterface {
             })
     }
 
+    fn param_compare_swapr(
+        &self,
+        key: &str,
+        expected: f64,
+        desired: f64,
+    ) -> Result<bool, StaticCoreError> {
+        let resolved = self.resolve(key)?;
+
+        self.core.upgrade().unwrap().param_compare_swap(
+            resolved.into_owned(),
+            &Param::Real(expected),
+            Param::Real(desired),
+        )
+    }
+
     fn param_swapr(&self, key: &str, value: f64) -> Result<f64, StaticCoreError> {
         let resolved = self.resolve(key)?;
 
@@ -490,13 +1618,11 @@ impl core::Core for CoreInterface {
     fn param_sets(&self, key: &str, value: String) -> Result<(), StaticCoreError> {
         let resolved = self.resolve(key)?;
 
-        let _ = self
-            .core
+        self.core
             .upgrade()
             .unwrap()
-            .param_set(resolved.into_owned(), Param::String(value));
-
-        Ok(())
+            .param_set(resolved.into_owned(), Param::String(value))
+            .map(|_| ())
     }
 
     fn param_gets(&self, key: &str) -> Result<String, StaticCoreError> {
@@ -529,6 +1655,110 @@ impl core::Core for CoreInterface {
                 _ => unreachable!(),
             })
     }
+
+    fn param_compare_swaps(
+        &self,
+        key: &str,
+        expected: &str,
+        desired: String,
+    ) -> Result<bool, StaticCoreError> {
+        let resolved = self.resolve(key)?;
+
+        self.core.upgrade().unwrap().param_compare_swap(
+            resolved.into_owned(),
+            &Param::String(expected.to_string()),
+            Param::String(desired),
+        )
+    }
+
+    fn param_transaction(
+        &self,
+        assignments: &[ffi::ParamAssignment],
+    ) -> Result<(), StaticCoreError> {
+        let mut resolved = Vec::with_capacity(assignments.len());
+
+        for assignment in assignments.iter() {
+            let key = unsafe { util::ffi_to_str(assignment.key) }.unwrap();
+            let value = unsafe { Param::from_ffi(&assignment.value) }
+                .ok_or(StaticCoreError::InvalidParamType)?;
+
+            resolved.push((self.resolve(key)?.into_owned(), value));
+        }
+
+        self.core.upgrade().unwrap().param_transaction(resolved)
+    }
+
+    fn param_list(
+        &self,
+        prefix: Option<&str>,
+    ) -> Result<Vec<(String, ParamType)>, StaticCoreError> {
+        let resolved = match prefix {
+            Some(p) => self.resolve(p)?,
+            None => Cow::Borrowed(""),
+        };
+
+        Ok(self
+            .core
+            .upgrade()
+            .unwrap()
+            .params_under(&resolved)
+            .into_iter()
+            .map(|(k, v)| (k, v.get_type()))
+            .collect())
+    }
+
+    fn param_delete(&self, key: &str) -> Result<(), StaticCoreError> {
+        let resolved = self.resolve(key)?;
+
+        self.core
+            .upgrade()
+            .unwrap()
+            .param_delete(&resolved)
+            .map(|_| ())
+    }
+
+    fn param_dump(&self, prefix: Option<&str>, path: &str) -> Result<(), StaticCoreError> {
+        let resolved = match prefix {
+            Some(p) => self.resolve(p)?,
+            None => Cow::Borrowed(""),
+        };
+
+        self.core
+            .upgrade()
+            .unwrap()
+            .param_dump(&resolved, Path::new(path))
+            .map_err(|e| {
+                error!(target: self.name(), "couldn't dump params to '{}': {}", path, e);
+
+                StaticCoreError::ParamDumpFailed
+            })
+    }
+
+    fn param_declare(&self, decl: ffi::ParamDecl) -> Result<(), StaticCoreError> {
+        let key = unsafe { util::ffi_to_str(decl.key) }.unwrap();
+        let resolved = self.resolve(key)?;
+        let decl = unsafe { ParamDecl::from_ffi(&decl) }?;
+
+        self.core
+            .upgrade()
+            .unwrap()
+            .param_declare(resolved.into_owned(), decl)
+    }
+
+    fn param_watch(
+        &self,
+        key: &str,
+        callback: ffi::ParamWatchCallback,
+        arg: *mut c_void,
+    ) -> Result<ParamWatcher, StaticCoreError> {
+        let resolved = self.resolve(key)?;
+
+        Ok(self
+            .core
+            .upgrade()
+            .unwrap()
+            .param_watch(resolved.into_owned(), callback, arg))
+    }
 }
 
 impl CoreBase for CoreInterface {
@@ -541,6 +1771,12 @@ unsafe impl Sync for CoreInterface {}
 
 pub struct Publisher {
     channel: Arc<Channel>,
+    queue: Option<Arc<PublishQueue>>, // only for nonblocking publishers
+    first_segment_words: usize,
+    clock: Arc<Clock>,
+    id: u64,
+    node_name: Arc<str>,
+    sequence: u64,
 }
 
 impl core::Publisher for Publisher {
@@ -562,30 +1798,59 @@ impl core::Publisher for Publisher {
             return Err(StaticCoreError::ChannelDisconnected);
         }
 
-        self.channel.publish(allocator);
+        // grow the first segment so that messages like this one fit in a single segment, but not
+        // past what the pool recycles, so that one oversized message can't make every later one
+        // bypass the pool
+        if allocator.num_segments() > 1 {
+            let num_words = cmp::min(allocator.num_words(), alloc::MAX_POOLED_SEGMENT_WORDS);
+            self.first_segment_words = cmp::max(self.first_segment_words, num_words);
+        }
+
+        let header = Header {
+            stamp: self.clock.now(),
+            sequence: self.sequence,
+            publisher_id: self.id,
+            publisher_name: self.node_name.clone(),
+        };
+
+        match self.queue {
+            Some(ref q) => q.push(&self.channel, allocator, header)?,
+            None => {
+                self.channel.publish(allocator, header);
+            }
+        }
+
+        self.sequence += 1;
 
         Ok(())
     }
 
     fn get_allocator(&self) -> CacheAlignedAllocator {
-        CacheAlignedAllocator::new()
+        CacheAlignedAllocator::new(
+            self.channel.segment_pool().clone(),
+            self.first_segment_words,
+        )
     }
 
     srm_publisher_impl!(Publisher);
 }
 
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        if let Some(ref q) = self.queue {
+            q.wait_until_empty();
+        }
+    }
+}
+
 pub struct Subscriber {
     channel: Arc<Channel>,
     id: usize,
 }
 
 impl Subscriber {
-    fn new(
-        channel: Arc<Channel>,
-        f: ffi::SubscribeCallback,
-        arg: *mut c_void,
-    ) -> Option<Subscriber> {
-        let id = match channel.insert_callback(f, arg) {
+    fn new(channel: Arc<Channel>, callback: Callback) -> Option<Subscriber> {
+        let id = match channel.insert_callback(callback) {
             Some(i) => i,
             None => return None,
         };
@@ -614,6 +1879,83 @@ impl Drop for Subscriber {
     }
 }
 
+pub struct Synchronizer {
+    subscribers: Vec<Subscriber>,
+    inputs: Vec<SyncInput>, // never resized, as subscribers point into it
+}
+
+impl core::Synchronizer for Synchronizer {
+    type Error = StaticCoreError;
+
+    fn get_num_topics(&self) -> usize {
+        self.inputs.len()
+    }
+
+    srm_synchronizer_impl!(Synchronizer);
+}
+
+impl Drop for Synchronizer {
+    fn drop(&mut self) {
+        // no callbacks can be running on inputs once all subscribers are disconnected
+        self.subscribers.clear();
+    }
+}
+
+pub struct ParamWatcher {
+    watchers: Arc<WatchList>,
+    key: String,
+    id: usize,
+}
+
+impl ParamWatcher {
+    fn new(
+        watchers: Arc<WatchList>,
+        key: String,
+        f: ffi::ParamWatchCallback,
+        arg: *mut c_void,
+    ) -> ParamWatcher {
+        let id = watchers.insert(key.clone(), f, arg);
+
+        ParamWatcher { watchers, key, id }
+    }
+}
+
+impl core::ParamWatcher for ParamWatcher {
+    type Error = StaticCoreError;
+
+    fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    srm_param_watcher_impl!(ParamWatcher);
+}
+
+impl Drop for ParamWatcher {
+    fn drop(&mut self) {
+        self.watchers.remove(self.id);
+    }
+}
+
+pub struct Timer {
+    entry: Arc<TimerEntry>,
+}
+
+impl core::Timer for Timer {
+    type Error = StaticCoreError;
+
+    fn get_period(&self) -> i64 {
+        self.entry.period()
+    }
+
+    srm_timer_impl!(Timer);
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.entry.cancel();
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum StaticCoreError {
     OutOfMemory = 1,
@@ -624,6 +1966,22 @@ pub enum StaticCoreError {
     NoSuchParam,
     ParamTypeDiffers,
     InvalidKey,
+    ParamReadOnly,
+    ParamOutOfRange,
+    ParamNotAllowed,
+    ParamUndeclared,
+    ParamAlreadyDeclared,
+    ParamDumpFailed,
+    InvalidTimerPeriod,
+    InvalidSyncParams,
+    InvalidMaxInFlight,
+    TooManyInFlight,
+    InvalidSizeHint,
+    NoSuchTopic,
+    ReservedTopic,
+    InvalidLogLevel,
+    InvalidLogPeriod,
+    InvalidParamType,
 }
 
 impl core::Error for StaticCoreError {
@@ -637,6 +1995,22 @@ impl core::Error for StaticCoreError {
             6 => StaticCoreError::NoSuchParam,
             7 => StaticCoreError::ParamTypeDiffers,
             8 => StaticCoreError::InvalidKey,
+            9 => StaticCoreError::ParamReadOnly,
+            10 => StaticCoreError::ParamOutOfRange,
+            11 => StaticCoreError::ParamNotAllowed,
+            12 => StaticCoreError::ParamUndeclared,
+            13 => StaticCoreError::ParamAlreadyDeclared,
+            14 => StaticCoreError::ParamDumpFailed,
+            15 => StaticCoreError::InvalidTimerPeriod,
+            16 => StaticCoreError::InvalidSyncParams,
+            17 => StaticCoreError::InvalidMaxInFlight,
+            18 => StaticCoreError::TooManyInFlight,
+            19 => StaticCoreError::InvalidSizeHint,
+            20 => StaticCoreError::NoSuchTopic,
+            21 => StaticCoreError::ReservedTopic,
+            22 => StaticCoreError::InvalidLogLevel,
+            23 => StaticCoreError::InvalidLogPeriod,
+            24 => StaticCoreError::InvalidParamType,
             x => panic!("unknown code to construct StaticCoreError from: {}", x),
         }
     }
@@ -655,6 +2029,26 @@ impl core::Error for StaticCoreError {
             StaticCoreError::NoSuchParam => "no parameter with that name exists",
             StaticCoreError::ParamTypeDiffers => "parameter exists, but has differing type",
             StaticCoreError::InvalidKey => "parameter key is invalid",
+            StaticCoreError::ParamReadOnly => "parameter is read-only",
+            StaticCoreError::ParamOutOfRange => "value is outside of the parameter's range",
+            StaticCoreError::ParamNotAllowed => {
+                "value is not one of the parameter's allowed values"
+            }
+            StaticCoreError::ParamUndeclared => "parameter has not been declared",
+            StaticCoreError::ParamAlreadyDeclared => "parameter has already been declared",
+            StaticCoreError::ParamDumpFailed => "couldn't write parameters to file",
+            StaticCoreError::InvalidTimerPeriod => "timer period must be positive",
+            StaticCoreError::InvalidSyncParams => "synchronizer parameters are invalid",
+            StaticCoreError::InvalidMaxInFlight => {
+                "maximum messages in flight must not be negative"
+            }
+            StaticCoreError::TooManyInFlight => "publisher has too many messages in flight",
+            StaticCoreError::InvalidSizeHint => "message size hint must not be negative",
+            StaticCoreError::NoSuchTopic => "no channel with that name exists",
+            StaticCoreError::ReservedTopic => "topic is reserved for the core",
+            StaticCoreError::InvalidLogLevel => "log level is not one of SrmLogLevel",
+            StaticCoreError::InvalidLogPeriod => "log throttling period must be positive",
+            StaticCoreError::InvalidParamType => "parameter type is not one of SrmParamType",
         }
     }
 }
@@ -674,24 +2068,38 @@ struct Channel {
     msg_type: u64,
     max_num_callbacks: Option<usize>,
     callbacks: Arc<RwLock<(Vec<(usize, Callback)>, usize)>>,
+    segment_pool: Arc<SegmentPool>,
+    stats: ChannelStats,
+    events: Weak<CorePublisher>,
 }
 
 impl Channel {
-    pub fn new(name: String, msg_type: u64) -> Channel {
+    pub fn new(name: String, msg_type: u64, events: Weak<CorePublisher>) -> Channel {
         Channel {
             name,
             msg_type,
             max_num_callbacks: None,
             callbacks: Arc::new(RwLock::new((Vec::with_capacity(8), 0))),
+            segment_pool: Arc::new(SegmentPool::new()),
+            stats: ChannelStats::new(),
+            events,
         }
     }
 
-    pub fn with_max_callbacks(name: String, msg_type: u64, max_num_callbacks: usize) -> Channel {
+    pub fn with_max_callbacks(
+        name: String,
+        msg_type: u64,
+        max_num_callbacks: usize,
+        events: Weak<CorePublisher>,
+    ) -> Channel {
         Channel {
             name,
             msg_type,
             max_num_callbacks: Some(max_num_callbacks),
             callbacks: Arc::new(RwLock::new((Vec::with_capacity(max_num_callbacks), 0))),
+            segment_pool: Arc::new(SegmentPool::new()),
+            stats: ChannelStats::new(),
+            events,
         }
     }
 
@@ -703,8 +2111,31 @@ impl Channel {
         self.msg_type
     }
 
-    pub fn insert_callback(&self, f: ffi::SubscribeCallback, arg: *mut c_void) -> Option<usize> {
-        let mut callbacks = if let Some(max) = self.max_num_callbacks {
+    pub fn segment_pool(&self) -> &Arc<SegmentPool> {
+        &self.segment_pool
+    }
+
+    pub fn has_callbacks(&self) -> bool {
+        !self.callbacks.read().0.is_empty()
+    }
+
+    pub fn stats(&self) -> TopicStats {
+        let callbacks = self.callbacks.read();
+
+        TopicStats {
+            msg_type: self.msg_type,
+            channel: self.stats.snapshot(),
+            subscribers: callbacks
+                .0
+                .iter()
+                .map(|(id, c)| (*id, c.owner.to_string(), c.stats.snapshot()))
+                .collect(),
+            segment_pool: self.segment_pool.stats(),
+        }
+    }
+
+    pub fn insert_callback(&self, callback: Callback) -> Option<usize> {
+        let mut callbacks = if let Some(max) = self.max_num_callbacks {
             let callbacks = self.callbacks.upgradable_read();
 
             if callbacks.0.len() == max {
@@ -719,7 +2150,18 @@ impl Channel {
         let id = callbacks.1;
         callbacks.1 += 1;
 
-        callbacks.0.push((id, Callback::new(f, arg)));
+        let owner = callback.owner.clone();
+        callbacks.0.push((id, callback));
+        drop(callbacks); // subscribers to the event topic may subscribe again
+
+        if let Some(events) = self.events.upgrade() {
+            events.publish(Event::SubscriberAttached {
+                topic: &self.name,
+                msg_type: self.msg_type,
+                node: &owner,
+                id,
+            });
+        }
 
         Some(id)
     }
@@ -734,47 +2176,523 @@ impl Channel {
 
         let mut callbacks = RwLockUpgradableReadGuard::upgrade(callbacks);
 
-        callbacks.0.remove(index);
+        let (_, callback) = callbacks.0.remove(index);
+        drop(callbacks);
+
+        if let Some(events) = self.events.upgrade() {
+            events.publish(Event::SubscriberDetached {
+                topic: &self.name,
+                msg_type: self.msg_type,
+                node: &callback.owner,
+                id,
+            });
+        }
 
         Some(())
     }
 
-    pub fn publish(&self, allocator: alloc::CacheAlignedAllocator) {
-        let callbacks = self.callbacks.read();
-        Channel::do_publish(allocator, &callbacks.0, self.msg_type);
+    /// Returns the number of callbacks that failed or were dropped.
+    ///
+    /// Callbacks whose callback group is busy are deferred until the group is free, or dropped if
+    /// the group has too many deferred callbacks already. Deferred callbacks are not waited for,
+    /// so their failures are not counted in the result.
+    pub fn publish(
+        self: &Arc<Self>,
+        allocator: alloc::CacheAlignedAllocator,
+        header: Header,
+    ) -> usize {
+        self.publish_then(allocator, header, None)
     }
 
-    pub fn publish_nonblocking(&self, allocator: alloc::CacheAlignedAllocator) {
-        let callbacks = self.callbacks.clone();
-        let msg_type = self.msg_type;
-
-        rayon::spawn(move || {
-            let guard = callbacks.read();
-            Channel::do_publish(allocator, &guard.0, msg_type)
+    /// Publishes like publish, then calls on_delivered with the number of callbacks that failed or
+    /// were dropped once every callback has returned, including deferred ones.
+    fn publish_then(
+        self: &Arc<Self>,
+        allocator: alloc::CacheAlignedAllocator,
+        header: Header,
+        on_delivered: Option<OnDelivered>,
+    ) -> usize {
+        let num_bytes = allocator.message_words() * mem::size_of::<capnp::Word>();
+        let delivery = Arc::new(Delivery {
+            allocator,
+            header,
+            num_failed: AtomicUsize::new(0),
+            on_delivered,
         });
+
+        // callbacks may publish to this channel again
+        let callbacks = self.callbacks.read_recursive();
+        let num_failed = self.do_publish(&delivery, &callbacks.0);
+        mem::drop(callbacks);
+
+        delivery.num_failed.fetch_add(num_failed, Ordering::Relaxed);
+        self.stats.record(num_bytes, num_failed);
+
+        num_failed
     }
 
     fn do_publish(
-        allocator: alloc::CacheAlignedAllocator,
-        callbacks: &Vec<(usize, Callback)>,
-        msg_type: u64,
-    ) {
-        let segments = unsafe { allocator.as_view() };
-        let msg = slice_to_msg(&segments, msg_type);
+        self: &Arc<Self>,
+        delivery: &Arc<Delivery>,
+        callbacks: &[(usize, Callback)],
+    ) -> usize {
+        let segments = unsafe { delivery.allocator.as_view() };
+        let msg = slice_to_msg(&segments, self.msg_type, delivery.header.as_ffi());
 
         callbacks
             .par_iter()
-            .for_each(|(_, c)| match unsafe { c.invoke(msg) } {
-                0 => (),
-                x => eprintln!("callback {:p} failed with errc {}", c.f, x),
+            .filter(|(id, c)| {
+                let defer = || -> Job {
+                    let this = self.clone();
+                    let id = *id;
+                    let delivery = delivery.clone();
+
+                    Box::new(move || this.deliver_deferred(id, &delivery))
+                };
+
+                match c
+                    .executor
+                    .install_or_defer(|| unsafe { self.invoke(c, msg) }, defer)
+                {
+                    Dispatch::Ran(failed) => failed,
+                    Dispatch::Deferred => false,
+                    Dispatch::Dropped => {
+                        c.warn_dropped(&self.name);
+
+                        true
+                    }
+                }
+            })
+            .count()
+    }
+
+    /// Invokes a callback whose callback group was busy when the message was published, unless
+    /// it has since been removed.
+    fn deliver_deferred(&self, id: usize, delivery: &Delivery) {
+        let callbacks = self.callbacks.read_recursive();
+
+        let callback = match callbacks.0.iter().find(|(i, _)| *i == id) {
+            Some((_, c)) => c,
+            None => return,
+        };
+
+        let segments = unsafe { delivery.allocator.as_view() };
+        let msg = slice_to_msg(&segments, self.msg_type, delivery.header.as_ffi());
+
+        if unsafe { self.invoke(callback, msg) } {
+            self.stats.record_failures(1);
+            delivery.num_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Invokes a callback and returns true if it failed.
+    ///
+    /// Records logged while invoking a callback on LOG_TOPIC aren't published, since they would
+    /// be delivered to it again.
+    unsafe fn invoke(&self, callback: &Callback, msg: ffi::MsgView) -> bool {
+        let invoke = || match callback.invoke_timed(msg) {
+            0 => false,
+            x => {
+                callback.warn_failed(&self.name, x);
+
+                true
+            }
+        };
+
+        if self.name == LOG_TOPIC {
+            logging::while_publishing(invoke)
+        } else {
+            invoke()
+        }
+    }
+}
+
+/// A published message, shared by the callbacks that were deferred until their group was free.
+///
+/// Calls on_delivered once the last of them has returned and the delivery is dropped.
+struct Delivery {
+    allocator: alloc::CacheAlignedAllocator,
+    header: Header,
+    num_failed: AtomicUsize,
+    on_delivered: Option<OnDelivered>,
+}
+
+type OnDelivered = Box<dyn FnOnce(usize) + Send + Sync>;
+
+impl Drop for Delivery {
+    fn drop(&mut self) {
+        if let Some(f) = self.on_delivered.take() {
+            f(*self.num_failed.get_mut());
+        }
+    }
+}
+
+/// A change to the node graph, published on EVENTS_TOPIC.
+enum Event<'a> {
+    ChannelCreated {
+        topic: &'a str,
+        msg_type: u64,
+    },
+    SubscriberAttached {
+        topic: &'a str,
+        msg_type: u64,
+        node: &'a str,
+        id: usize,
+    },
+    SubscriberDetached {
+        topic: &'a str,
+        msg_type: u64,
+        node: &'a str,
+        id: usize,
+    },
+    NodeStarted {
+        node: &'a str,
+    },
+    NodeStopped {
+        node: &'a str,
+    },
+}
+
+impl<'a> Event<'a> {
+    fn build(&self, builder: &mut event::Builder) {
+        match *self {
+            Event::ChannelCreated { topic, msg_type } => {
+                builder.set_kind(Kind::ChannelCreated);
+                builder.set_topic(topic);
+                builder.set_msg_type(msg_type);
+            }
+            Event::SubscriberAttached {
+                topic,
+                msg_type,
+                node,
+                id,
+            } => {
+                builder.set_kind(Kind::SubscriberAttached);
+                builder.set_topic(topic);
+                builder.set_msg_type(msg_type);
+                builder.set_node(node);
+                builder.set_subscriber(id as u64);
+            }
+            Event::SubscriberDetached {
+                topic,
+                msg_type,
+                node,
+                id,
+            } => {
+                builder.set_kind(Kind::SubscriberDetached);
+                builder.set_topic(topic);
+                builder.set_msg_type(msg_type);
+                builder.set_node(node);
+                builder.set_subscriber(id as u64);
+            }
+            Event::NodeStarted { node } => {
+                builder.set_kind(Kind::NodeStarted);
+                builder.set_node(node);
+            }
+            Event::NodeStopped { node } => {
+                builder.set_kind(Kind::NodeStopped);
+                builder.set_node(node);
+            }
+        }
+    }
+}
+
+/// Publishes the core's own messages on EVENTS_TOPIC or LOG_TOPIC.
+///
+/// Messages are delivered asynchronously, in order, since the thread that caused them may be
+/// running a callback or holding the core's locks.
+struct CorePublisher {
+    channel: Arc<Channel>,
+    queue: Arc<PublishQueue>,
+    clock: Arc<Clock>,
+    id: u64,
+    name: Arc<str>,
+    sequence: AtomicU64,
+}
+
+impl CorePublisher {
+    fn new(channel: Arc<Channel>, clock: Arc<Clock>, id: u64) -> CorePublisher {
+        CorePublisher {
+            channel,
+            queue: Arc::new(PublishQueue::new(CORE_MAX_IN_FLIGHT, None, ptr::null_mut())),
+            clock,
+            id,
+            name: Arc::from(CORE_OWNER),
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    fn publish(&self, event: Event) {
+        let (allocator, header) = self.build(EVENT_SEGMENT_WORDS, |message, stamp| {
+            let mut builder = message.init_root::<event::Builder>();
+
+            builder.set_time(stamp);
+            event.build(&mut builder);
+        });
+
+        if self.queue.push(&self.channel, allocator, header).is_err() {
+            warn!("too many events in flight, dropping one");
+        }
+    }
+
+    fn publish_log(&self, record: &Record) {
+        let mut fields = FieldCollector(Vec::new());
+        let _ = record.key_values().visit(&mut fields);
+
+        let (allocator, header) =
+            self.build(alloc::DEFAULT_FIRST_SEGMENT_WORDS, |message, stamp| {
+                let mut builder = message.init_root::<log_record::Builder>();
+
+                builder.set_time(stamp);
+                builder.set_level(match record.level() {
+                    Level::Error => log_record::Level::Error,
+                    Level::Warn => log_record::Level::Warn,
+                    Level::Info => log_record::Level::Info,
+                    Level::Debug => log_record::Level::Debug,
+                    Level::Trace => log_record::Level::Trace,
+                });
+                builder.set_target(record.target());
+                builder.set_message(&record.args().to_string());
+
+                let list = builder.init_fields(fields.0.len() as u32);
+
+                for (i, (key, value)) in fields.0.iter().enumerate() {
+                    let mut field = list.get(i as u32);
+                    field.set_key(key);
+                    field.set_value(value);
+                }
             });
+
+        // dropped silently, since warning about it would log another record
+        let _ = self.queue.push(&self.channel, allocator, header);
+    }
+
+    fn build<F: FnOnce(&mut capnp::message::Builder<&mut CacheAlignedAllocator>, i64)>(
+        &self,
+        first_segment_words: usize,
+        build: F,
+    ) -> (CacheAlignedAllocator, Header) {
+        let stamp = self.clock.now();
+        let mut allocator =
+            CacheAlignedAllocator::new(self.channel.segment_pool().clone(), first_segment_words);
+
+        {
+            let mut message = capnp::message::Builder::new(&mut allocator);
+            build(&mut message, stamp);
+        }
+
+        let header = Header {
+            stamp,
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+            publisher_id: self.id,
+            publisher_name: self.name.clone(),
+        };
+
+        (allocator, header)
+    }
+}
+
+/// Collects a record's fields as strings.
+struct FieldCollector(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for FieldCollector {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+
+        Ok(())
+    }
+}
+
+/// A snapshot of a channel's statistics.
+#[derive(Clone, Debug)]
+pub struct TopicStats {
+    pub msg_type: u64,
+    pub channel: ChannelStatsSnapshot,
+    pub subscribers: Vec<(usize, String, CallbackStatsSnapshot)>, // (id, owner, stats)
+    pub segment_pool: SegmentPoolStats,
+}
+
+impl TopicStats {
+    /// Returns the callback statistics of all subscribers added together.
+    pub fn total_callback_stats(&self) -> CallbackStatsSnapshot {
+        let mut total = CallbackStatsSnapshot::default();
+
+        for (_, _, s) in self.subscribers.iter() {
+            total.merge(s);
+        }
+
+        total
+    }
+
+    fn as_ffi(&self) -> ffi::TopicStats {
+        let callbacks = self.total_callback_stats();
+
+        ffi::TopicStats {
+            msg_type: self.msg_type,
+            num_messages: self.channel.messages,
+            num_bytes: self.channel.bytes,
+            num_failures: self.channel.failures,
+            message_rate: self.channel.rate,
+            num_subscribers: self.subscribers.len() as ffi::Index,
+            num_callbacks: callbacks.calls,
+            total_callback_duration: callbacks.total_duration,
+            latency_buckets: callbacks.buckets,
+        }
+    }
+}
+
+/// The default bound on messages queued by a nonblocking publisher.
+const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+/// Delivers the messages of a nonblocking publisher or a CorePublisher in order on the global
+/// rayon pool.
+///
+/// At most one task per queue is running at a time, which drains messages until the queue is
+/// empty. A message stays in flight until every callback has returned, including those deferred
+/// until their callback group is free, so max_in_flight also bounds the messages that deferred
+/// callbacks hold on to.
+struct PublishQueue {
+    state: Mutex<PublishQueueState>,
+    empty: Condvar,
+    max_in_flight: usize,
+    on_complete: Option<ffi::PublishCompleteCallback>,
+    arg: *mut c_void,
+}
+
+struct PublishQueueState {
+    messages: VecDeque<(Arc<Channel>, alloc::CacheAlignedAllocator, Header)>,
+    in_flight: usize, // queued or being delivered
+    draining: bool,
+}
+
+impl PublishQueue {
+    fn new(
+        max_in_flight: usize,
+        on_complete: Option<ffi::PublishCompleteCallback>,
+        arg: *mut c_void,
+    ) -> PublishQueue {
+        PublishQueue {
+            state: Mutex::new(PublishQueueState {
+                messages: VecDeque::new(),
+                in_flight: 0,
+                draining: false,
+            }),
+            empty: Condvar::new(),
+            max_in_flight,
+            on_complete,
+            arg,
+        }
+    }
+
+    fn push(
+        self: &Arc<Self>,
+        channel: &Arc<Channel>,
+        allocator: alloc::CacheAlignedAllocator,
+        header: Header,
+    ) -> Result<(), StaticCoreError> {
+        let mut state = self.state.lock();
+
+        if state.in_flight >= self.max_in_flight {
+            return Err(StaticCoreError::TooManyInFlight);
+        }
+
+        state
+            .messages
+            .push_back((channel.clone(), allocator, header));
+        state.in_flight += 1;
+
+        if !state.draining {
+            state.draining = true;
+
+            let this = self.clone();
+            rayon::spawn(move || this.drain());
+        }
+
+        Ok(())
+    }
+
+    fn drain(self: &Arc<Self>) {
+        loop {
+            let (channel, allocator, header) = {
+                let mut state = self.state.lock();
+
+                match state.messages.pop_front() {
+                    Some(m) => m,
+                    None => {
+                        state.draining = false;
+
+                        return;
+                    }
+                }
+            };
+
+            let sequence = header.sequence;
+            let this = self.clone();
+            let on_delivered = move |num_failed| this.complete(sequence, num_failed);
+
+            channel.publish_then(allocator, header, Some(Box::new(on_delivered)));
+        }
+    }
+
+    /// Called once every callback has been invoked on a message, possibly from the thread of a
+    /// deferred callback.
+    fn complete(&self, sequence: u64, num_failed: usize) {
+        if let Some(f) = self.on_complete {
+            match unsafe { f(sequence, num_failed as ffi::Index, self.arg) } {
+                0 => (),
+                x => warn!("publish completion callback {:p} failed with errc {}", f, x),
+            }
+        }
+
+        let mut state = self.state.lock();
+        state.in_flight -= 1;
+
+        if state.in_flight == 0 {
+            self.empty.notify_all();
+        }
+    }
+
+    fn wait_until_empty(&self) {
+        let mut state = self.state.lock();
+
+        while state.in_flight > 0 {
+            self.empty.wait(&mut state);
+        }
     }
 }
 
+unsafe impl Send for PublishQueue {}
+
+unsafe impl Sync for PublishQueue {}
+
+/// Per-node settings from the node graph.
+#[derive(Deserialize, Default, Debug)]
+pub struct NodeOptions {
+    /// Runs the node's callbacks on this thread pool instead of the global one.
+    pub thread_pool: Option<String>,
+    /// Shares mutual exclusion with the other nodes in this callback group.
+    pub callback_group: Option<String>,
+    /// Allows the node's callbacks to run concurrently; ignored if callback_group is set.
+    #[serde(default)]
+    pub reentrant: bool,
+    /// Runs subscription callbacks on these topics on a different thread pool.
+    #[serde(default)]
+    pub subscriptions: Vec<(String, String)>, // (topic, thread pool)
+    /// Pins the node's thread to these CPUs. Linux only; elsewhere, a warning is logged.
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// Sets the niceness of the node's thread. Linux only; elsewhere, a warning is logged.
+    pub nice: Option<i32>,
+    /// Initial value of the node's log_level param.
+    pub log_level: Option<String>,
+}
+
 #[derive(Debug)]
 pub enum NodeError {
     Load(node_plugin::LoadError),
     Start(ErrorCode),
+    UnknownThreadPool(String),
+    UnknownCallbackGroup(String),
+    LogLevel(StaticCoreError),
 }
 
 impl Error for NodeError {}
@@ -784,34 +2702,1161 @@ impl Display for NodeError {
         match self {
             NodeError::Load(e) => write!(f, "load error: {}", e),
             NodeError::Start(e) => write!(f, "start error: {}", e),
+            NodeError::UnknownThreadPool(p) => write!(f, "no thread pool named '{}'", p),
+            NodeError::UnknownCallbackGroup(g) => write!(f, "no callback group named '{}'", g),
+            NodeError::LogLevel(e) => write!(f, "couldn't set log level: {}", e),
+        }
+    }
+}
+
+/// Applies a node's scheduling options to the calling thread. Failures are only warned about.
+fn apply_scheduling(name: &str, cpu_affinity: Option<&Vec<usize>>, nice: Option<i32>) {
+    if let Some(cpus) = cpu_affinity {
+        match scheduling::set_cpu_affinity(cpus) {
+            Ok(()) => debug!("pinned node '{}' to CPUs {:?}", name, cpus),
+            Err(e) => warn!("couldn't set CPU affinity of node '{}': {}", name, e),
+        }
+    }
+
+    if let Some(nice) = nice {
+        match scheduling::set_nice(nice) {
+            Ok(()) => debug!("set niceness of node '{}' to {}", name, nice),
+            Err(e) => warn!("couldn't set niceness of node '{}': {}", name, e),
         }
     }
 }
 
-fn slice_to_msg(slice: &[ffi::MsgSegmentView], msg_type: u64) -> ffi::MsgView {
+/// Returns true if key is equal to or nested under prefix, e.g. `.foo.bar` is under `.foo`.
+fn is_key_under(key: &str, prefix: &str) -> bool {
+    key.starts_with(prefix) && (key.len() == prefix.len() || key[prefix.len()..].starts_with('.'))
+}
+
+fn slice_to_msg(
+    slice: &[ffi::MsgSegmentView],
+    msg_type: u64,
+    header: ffi::MsgHeader,
+) -> ffi::MsgView {
     ffi::MsgView {
         segments: slice.as_ptr(),
         num_segments: slice.len() as ffi::Index,
         msg_type,
+        header,
+    }
+}
+
+/// Metadata attached to a message when it is published.
+struct Header {
+    stamp: i64,
+    sequence: u64,
+    publisher_id: u64,
+    publisher_name: Arc<str>,
+}
+
+impl Header {
+    /// The returned header borrows publisher_name from self.
+    fn as_ffi(&self) -> ffi::MsgHeader {
+        ffi::MsgHeader {
+            stamp: self.stamp,
+            sequence: self.sequence,
+            publisher_id: self.publisher_id,
+            publisher_name: util::str_to_ffi(&self.publisher_name),
+        }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone)]
 struct Callback {
     f: ffi::SubscribeCallback,
     arg: *mut c_void,
+    executor: Arc<Executor>,
+    owner: Arc<str>, // name of the subscribing node
+    stats: Arc<CallbackStats>,
+    last_warning: Arc<Mutex<(Option<Instant>, usize)>>, // (last warned, failures since)
 }
 
+/// Minimum time between warnings about a failing subscriber callback.
+const CALLBACK_WARNING_PERIOD: Duration = Duration::from_secs(1);
+
 impl Callback {
-    fn new(f: ffi::SubscribeCallback, arg: *mut c_void) -> Callback {
-        Callback { f, arg }
+    fn new(
+        f: ffi::SubscribeCallback,
+        arg: *mut c_void,
+        executor: Arc<Executor>,
+        owner: &str,
+    ) -> Callback {
+        Callback {
+            f,
+            arg,
+            executor,
+            owner: Arc::from(owner),
+            stats: Arc::new(CallbackStats::default()),
+            last_warning: Arc::new(Mutex::new((None, 0))),
+        }
     }
 
     unsafe fn invoke(&self, segments: ffi::MsgView) -> c_int {
         (self.f)(segments, self.arg)
     }
+
+    /// Invokes the callback and records how long it took and whether it failed.
+    unsafe fn invoke_timed(&self, segments: ffi::MsgView) -> c_int {
+        let start = Instant::now();
+        let res = self.invoke(segments);
+        self.stats.record(start.elapsed(), res != 0);
+
+        res
+    }
+
+    /// Warns that the callback failed, at most once per CALLBACK_WARNING_PERIOD.
+    fn warn_failed(&self, topic: &str, errc: c_int) {
+        if let Some(num_suppressed) = self.should_warn() {
+            warn!(
+                target: &self.owner,
+                "callback {:p} on '{}' failed with errc {}{}",
+                self.f,
+                topic,
+                errc,
+                suppressed_suffix(num_suppressed)
+            );
+        }
+    }
+
+    /// Warns that a message was dropped because the callback's group had too many deferred
+    /// callbacks, at most once per CALLBACK_WARNING_PERIOD.
+    fn warn_dropped(&self, topic: &str) {
+        if let Some(num_suppressed) = self.should_warn() {
+            warn!(
+                target: &self.owner,
+                "callback {:p} on '{}' dropped a message, its callback group is backed up{}",
+                self.f,
+                topic,
+                suppressed_suffix(num_suppressed)
+            );
+        }
+    }
+
+    /// Returns the number of warnings suppressed since the last one, or None if this one should
+    /// be suppressed too.
+    fn should_warn(&self) -> Option<usize> {
+        let now = Instant::now();
+
+        let mut last_warning = self.last_warning.lock();
+        let (ref mut last, ref mut num_suppressed) = *last_warning;
+
+        match *last {
+            Some(l) if now.duration_since(l) < CALLBACK_WARNING_PERIOD => {
+                *num_suppressed += 1;
+
+                None
+            }
+            _ => {
+                *last = Some(now);
+
+                Some(mem::take(num_suppressed))
+            }
+        }
+    }
+}
+
+fn suppressed_suffix(num_suppressed: usize) -> String {
+    match num_suppressed {
+        0 => String::new(),
+        n => format!(" ({} more failures since the last warning)", n),
+    }
 }
 
 unsafe impl Send for Callback {}
 
 unsafe impl Sync for Callback {}
+
+/// Callbacks to invoke when a parameter is modified.
+///
+/// Each watch is registered on a key and is notified of changes to that key and all keys
+/// nested beneath it, e.g. a watch on `.foo` is notified of changes to `.foo` and `.foo.bar`.
+struct WatchList {
+    watches: RwLock<(WatchEntries, usize)>,
+}
+
+type WatchEntries = Vec<(usize, Arc<Watch>)>; // keyed by id
+
+thread_local! {
+    // the watches being invoked on this thread, innermost last
+    static NOTIFYING: RefCell<Vec<*const Watch>> = const { RefCell::new(Vec::new()) };
+}
+
+impl WatchList {
+    fn new() -> WatchList {
+        WatchList {
+            watches: RwLock::new((Vec::new(), 0)),
+        }
+    }
+
+    fn insert(&self, key: String, f: ffi::ParamWatchCallback, arg: *mut c_void) -> usize {
+        let mut watches = self.watches.write();
+
+        let id = watches.1;
+        watches.1 += 1;
+
+        watches.0.push((
+            id,
+            Arc::new(Watch {
+                key,
+                f,
+                arg,
+                in_flight: Mutex::new(0),
+                done: Condvar::new(),
+            }),
+        ));
+
+        id
+    }
+
+    /// Removes a watch, waiting for any invocations of it on other threads to return so that its
+    /// arg can be freed afterwards.
+    fn remove(&self, id: usize) -> Option<()> {
+        let watches = self.watches.upgradable_read();
+
+        let index = watches.0.iter().position(|(i, _)| *i == id)?;
+
+        let mut watches = RwLockUpgradableReadGuard::upgrade(watches);
+
+        let (_, watch) = watches.0.remove(index);
+        mem::drop(watches);
+
+        watch.wait_until_idle();
+
+        Some(())
+    }
+
+    /// Invokes each watch on key or a prefix of key.
+    ///
+    /// old is None if the parameter was just created, and new is None if it was just deleted.
+    ///
+    /// Must not be called while holding a lock on any parameter, as watches are free to access
+    /// parameters themselves.
+    fn notify(&self, key: &str, old: Option<&Param>, new: Option<&Param>) {
+        // watches may add or remove watches, so they are invoked without holding the lock. each
+        // is marked in flight before the lock is released so that remove waits for it
+        let watches: Vec<Arc<Watch>> = self
+            .watches
+            .read()
+            .0
+            .iter()
+            .filter(|(_, w)| w.matches(key))
+            .map(|(_, w)| {
+                *w.in_flight.lock() += 1;
+
+                w.clone()
+            })
+            .collect();
+
+        if watches.is_empty() {
+            return;
+        }
+
+        let old_view = old.map(Param::as_ffi);
+        let old_ptr = old_view
+            .as_ref()
+            .map_or(ptr::null(), |v| v as *const ffi::ParamView);
+        let new_view = new.map(Param::as_ffi);
+        let new_ptr = new_view
+            .as_ref()
+            .map_or(ptr::null(), |v| v as *const ffi::ParamView);
+
+        for w in watches.iter() {
+            NOTIFYING.with(|n| n.borrow_mut().push(Arc::as_ptr(w)));
+            let result = unsafe { (w.f)(util::str_to_ffi(key), old_ptr, new_ptr, w.arg) };
+            NOTIFYING.with(|n| n.borrow_mut().pop());
+
+            w.finish();
+
+            match result {
+                0 => (),
+                x => warn!(
+                    "param watch {:p} on '{}' failed with errc {}",
+                    w.f, w.key, x
+                ),
+            }
+        }
+    }
+}
+
+struct Watch {
+    key: String,
+    f: ffi::ParamWatchCallback,
+    arg: *mut c_void,
+    in_flight: Mutex<usize>, // invocations that have started but not returned
+    done: Condvar,
+}
+
+impl Watch {
+    fn matches(&self, key: &str) -> bool {
+        is_key_under(key, &self.key)
+    }
+
+    fn finish(&self) {
+        let mut in_flight = self.in_flight.lock();
+        *in_flight -= 1;
+
+        if *in_flight == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    /// Waits until the only invocations in flight are those this thread is inside of, since a
+    /// watch may remove itself.
+    fn wait_until_idle(&self) {
+        let this = self as *const Watch;
+        let num_on_this_thread =
+            NOTIFYING.with(|n| n.borrow().iter().filter(|&&w| w == this).count());
+
+        let mut in_flight = self.in_flight.lock();
+
+        while *in_flight > num_on_this_thread {
+            self.done.wait(&mut in_flight);
+        }
+    }
+}
+
+unsafe impl Send for Watch {}
+
+unsafe impl Sync for Watch {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        cell::Cell,
+        panic::{self, AssertUnwindSafe},
+        thread,
+    };
+
+    use srm::sdk;
+
+    fn decl(default: Param) -> ParamDecl {
+        ParamDecl {
+            default,
+            description: String::new(),
+            min: None,
+            max: None,
+            allowed: None,
+            read_only: false,
+        }
+    }
+
+    #[test]
+    fn param_decl_checks_type() {
+        let d = decl(Param::Integer(0));
+
+        assert!(d.check(&Param::Integer(5)).is_ok());
+
+        match d.check(&Param::Real(5.0)) {
+            Err(StaticCoreError::ParamTypeDiffers) => (),
+            r => panic!("expected ParamTypeDiffers, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn param_decl_checks_range() {
+        let d = ParamDecl {
+            min: Some(0.0),
+            max: Some(10.0),
+            ..decl(Param::Real(1.0))
+        };
+
+        assert!(d.check(&Param::Real(0.0)).is_ok());
+        assert!(d.check(&Param::Real(10.0)).is_ok());
+
+        for &x in [-0.5, 10.5].iter() {
+            match d.check(&Param::Real(x)) {
+                Err(StaticCoreError::ParamOutOfRange) => (),
+                r => panic!("expected ParamOutOfRange for {}, got {:?}", x, r),
+            }
+        }
+
+        let d = ParamDecl {
+            min: Some(1.0),
+            ..decl(Param::Integer(1))
+        };
+
+        assert!(d.check(&Param::Integer(1)).is_ok());
+        assert!(d.check(&Param::Integer(0)).is_err());
+    }
+
+    #[test]
+    fn param_decl_checks_allowed() {
+        let d = ParamDecl {
+            allowed: Some(vec![
+                Param::String("fast".to_string()),
+                Param::String("slow".to_string()),
+            ]),
+            ..decl(Param::String("fast".to_string()))
+        };
+
+        assert!(d.check(&Param::String("slow".to_string())).is_ok());
+
+        match d.check(&Param::String("medium".to_string())) {
+            Err(StaticCoreError::ParamNotAllowed) => (),
+            r => panic!("expected ParamNotAllowed, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn param_declare_rejects_invalid_default() {
+        let core = StaticCore::new(Vec::new());
+        let d = ParamDecl {
+            max: Some(1.0),
+            ..decl(Param::Integer(2))
+        };
+
+        assert!(core.param_declare(".foo".to_string(), d).is_err());
+        assert!(core.param_value(".foo").is_none());
+    }
+
+    #[test]
+    fn param_declare_sets_default() {
+        let core = StaticCore::new(Vec::new());
+
+        core.param_declare(".foo".to_string(), decl(Param::Integer(3)))
+            .unwrap();
+
+        assert_eq!(core.param_value(".foo"), Some(Param::Integer(3)));
+
+        match core.param_declare(".foo".to_string(), decl(Param::Integer(4))) {
+            Err(StaticCoreError::ParamAlreadyDeclared) => (),
+            r => panic!("expected ParamAlreadyDeclared, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn param_set_respects_read_only() {
+        let core = StaticCore::new(Vec::new());
+        let d = ParamDecl {
+            read_only: true,
+            ..decl(Param::Boolean(false))
+        };
+
+        core.param_declare(".foo".to_string(), d).unwrap();
+
+        match core.param_set(".foo".to_string(), Param::Boolean(true)) {
+            Err(StaticCoreError::ParamReadOnly) => (),
+            r => panic!("expected ParamReadOnly, got {:?}", r),
+        }
+
+        core.param_configure(".foo".to_string(), Param::Boolean(true))
+            .unwrap();
+
+        assert_eq!(core.param_value(".foo"), Some(Param::Boolean(true)));
+    }
+
+    #[test]
+    fn strict_params_require_declaration() {
+        let core = StaticCore::new(Vec::new());
+
+        core.set_strict_params(true);
+
+        match core.param_set(".foo".to_string(), Param::Integer(1)) {
+            Err(StaticCoreError::ParamUndeclared) => (),
+            r => panic!("expected ParamUndeclared, got {:?}", r),
+        }
+
+        core.param_declare(".foo".to_string(), decl(Param::Integer(0)))
+            .unwrap();
+        core.param_set(".foo".to_string(), Param::Integer(1))
+            .unwrap();
+
+        assert_eq!(core.param_value(".foo"), Some(Param::Integer(1)));
+    }
+
+    #[test]
+    fn param_compare_swap_checks_expected() {
+        let core = StaticCore::new(Vec::new());
+
+        core.param_set(".mode".to_string(), Param::Integer(0))
+            .unwrap();
+
+        assert!(!core
+            .param_compare_swap(".mode".to_string(), &Param::Integer(1), Param::Integer(2))
+            .unwrap());
+        assert_eq!(core.param_value(".mode"), Some(Param::Integer(0)));
+
+        assert!(core
+            .param_compare_swap(".mode".to_string(), &Param::Integer(0), Param::Integer(2))
+            .unwrap());
+        assert_eq!(core.param_value(".mode"), Some(Param::Integer(2)));
+    }
+
+    #[test]
+    fn param_transaction_last_assignment_wins() {
+        let core = StaticCore::new(Vec::new());
+
+        core.param_transaction(vec![
+            (".b".to_string(), Param::Integer(1)),
+            (".a".to_string(), Param::Integer(2)),
+            (".b".to_string(), Param::Integer(3)),
+        ])
+        .unwrap();
+
+        assert_eq!(core.param_value(".a"), Some(Param::Integer(2)));
+        assert_eq!(core.param_value(".b"), Some(Param::Integer(3)));
+    }
+
+    #[test]
+    fn param_transaction_is_all_or_nothing() {
+        let core = StaticCore::new(Vec::new());
+        let d = ParamDecl {
+            max: Some(10.0),
+            ..decl(Param::Integer(0))
+        };
+
+        core.param_declare(".b".to_string(), d).unwrap();
+
+        match core.param_transaction(vec![
+            (".a".to_string(), Param::Integer(1)),
+            (".b".to_string(), Param::Integer(11)),
+        ]) {
+            Err(StaticCoreError::ParamOutOfRange) => (),
+            r => panic!("expected ParamOutOfRange, got {:?}", r),
+        }
+
+        assert_eq!(core.param_value(".a"), None);
+        assert_eq!(core.param_value(".b"), Some(Param::Integer(0)));
+    }
+
+    #[test]
+    fn param_transactions_in_opposite_orders_do_not_deadlock() {
+        const ITERATIONS: isize = 10_000;
+
+        let core = Arc::new(StaticCore::new(Vec::new()));
+
+        core.param_set(".a".to_string(), Param::Integer(0)).unwrap();
+        core.param_set(".b".to_string(), Param::Integer(0)).unwrap();
+
+        let forward = {
+            let core = core.clone();
+
+            thread::spawn(move || {
+                for i in 0..ITERATIONS {
+                    core.param_transaction(vec![
+                        (".a".to_string(), Param::Integer(i)),
+                        (".b".to_string(), Param::Integer(i)),
+                    ])
+                    .unwrap();
+                }
+            })
+        };
+
+        let backward = {
+            let core = core.clone();
+
+            thread::spawn(move || {
+                for i in 0..ITERATIONS {
+                    core.param_transaction(vec![
+                        (".b".to_string(), Param::Integer(-i)),
+                        (".a".to_string(), Param::Integer(-i)),
+                    ])
+                    .unwrap();
+                }
+            })
+        };
+
+        forward.join().unwrap();
+        backward.join().unwrap();
+
+        // both keys are always assigned together
+        assert_eq!(core.param_value(".a"), core.param_value(".b"));
+    }
+
+    /// A subscriber that republishes each message it receives to target, up to a limit.
+    struct Relay {
+        target: Mutex<Option<Arc<Channel>>>,
+        hops: Arc<AtomicUsize>,
+        max_hops: usize,
+    }
+
+    unsafe extern "C" fn relay(_: ffi::MsgView, arg: *mut c_void) -> c_int {
+        let this = &*(arg as *const Relay);
+
+        if this.hops.fetch_add(1, Ordering::SeqCst) + 1 < this.max_hops {
+            let target = this.target.lock().clone().unwrap();
+            publish_empty(&target);
+        }
+
+        0
+    }
+
+    fn empty_message(channel: &Arc<Channel>, sequence: u64) -> (CacheAlignedAllocator, Header) {
+        let allocator = CacheAlignedAllocator::new(channel.segment_pool().clone(), 1);
+        let header = Header {
+            stamp: 0,
+            sequence,
+            publisher_id: 0,
+            publisher_name: Arc::from("test"),
+        };
+
+        (allocator, header)
+    }
+
+    fn publish_empty(channel: &Arc<Channel>) -> usize {
+        let (allocator, header) = empty_message(channel, 0);
+
+        channel.publish(allocator, header)
+    }
+
+    fn subscribe_relay(channel: &Arc<Channel>, relay_: &Relay, group: &Arc<CallbackGroup>) {
+        let executor = Executor::new(None, Some(group.clone()));
+        let arg = relay_ as *const Relay as *mut c_void;
+
+        channel
+            .insert_callback(Callback::new(relay, arg, Arc::new(executor), "relay"))
+            .unwrap();
+    }
+
+    fn wait_for_hops(hops: &AtomicUsize, n: usize) {
+        let start = Instant::now();
+
+        while hops.load(Ordering::SeqCst) < n {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn callback_can_publish_to_its_own_subscription() {
+        let channel = Arc::new(Channel::new("foo".to_string(), 0, Weak::new()));
+        let hops = Arc::new(AtomicUsize::new(0));
+        let echo = Relay {
+            target: Mutex::new(Some(channel.clone())),
+            hops: hops.clone(),
+            max_hops: 3,
+        };
+
+        subscribe_relay(&channel, &echo, &Arc::new(CallbackGroup::new()));
+
+        assert_eq!(publish_empty(&channel), 0);
+        wait_for_hops(&hops, 3);
+
+        *echo.target.lock() = None;
+        channel.remove_callback(0).unwrap();
+    }
+
+    #[test]
+    fn callbacks_can_publish_in_a_cycle() {
+        let a = Arc::new(Channel::new("a".to_string(), 0, Weak::new()));
+        let b = Arc::new(Channel::new("b".to_string(), 0, Weak::new()));
+        let hops = Arc::new(AtomicUsize::new(0));
+
+        let a_to_b = Relay {
+            target: Mutex::new(Some(b.clone())),
+            hops: hops.clone(),
+            max_hops: 6,
+        };
+        let b_to_a = Relay {
+            target: Mutex::new(Some(a.clone())),
+            hops: hops.clone(),
+            max_hops: 6,
+        };
+
+        subscribe_relay(&a, &a_to_b, &Arc::new(CallbackGroup::new()));
+        subscribe_relay(&b, &b_to_a, &Arc::new(CallbackGroup::new()));
+
+        assert_eq!(publish_empty(&a), 0);
+        wait_for_hops(&hops, 6);
+
+        *a_to_b.target.lock() = None;
+        *b_to_a.target.lock() = None;
+        a.remove_callback(0).unwrap();
+        b.remove_callback(0).unwrap();
+    }
+
+    /// The state of a watch, which must not be touched after it's disconnected.
+    #[derive(Default)]
+    struct SlowWatch {
+        entered: AtomicBool,
+        returned: AtomicBool,
+        disconnected: AtomicBool,
+        watcher: Mutex<Option<ParamWatcher>>,
+    }
+
+    unsafe extern "C" fn slow_watch(
+        _: ffi::StrView,
+        _: *const ffi::ParamView,
+        _: *const ffi::ParamView,
+        arg: *mut c_void,
+    ) -> c_int {
+        let this = &*(arg as *const SlowWatch);
+
+        this.entered.store(true, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(50));
+        assert!(!this.disconnected.load(Ordering::SeqCst));
+        this.returned.store(true, Ordering::SeqCst);
+
+        0
+    }
+
+    unsafe extern "C" fn self_removing_watch(
+        _: ffi::StrView,
+        _: *const ffi::ParamView,
+        _: *const ffi::ParamView,
+        arg: *mut c_void,
+    ) -> c_int {
+        let this = &*(arg as *const SlowWatch);
+
+        this.entered.store(true, Ordering::SeqCst);
+        mem::drop(this.watcher.lock().take());
+        this.returned.store(true, Ordering::SeqCst);
+
+        0
+    }
+
+    #[test]
+    fn disconnect_waits_for_watches_in_flight() {
+        let core = Arc::new(StaticCore::new(Vec::new()));
+        let state = Arc::new(SlowWatch::default());
+        let arg = Arc::as_ptr(&state) as *mut c_void;
+
+        let watcher = core.param_watch(".foo".to_string(), slow_watch, arg);
+
+        let setter = {
+            let core = core.clone();
+
+            thread::spawn(move || {
+                core.param_set(".foo.bar".to_string(), Param::Integer(1))
+                    .unwrap();
+            })
+        };
+
+        while !state.entered.load(Ordering::SeqCst) {
+            thread::yield_now();
+        }
+
+        mem::drop(watcher);
+        assert!(state.returned.load(Ordering::SeqCst));
+        state.disconnected.store(true, Ordering::SeqCst);
+
+        setter.join().unwrap();
+    }
+
+    #[test]
+    fn watch_can_disconnect_itself() {
+        let core = StaticCore::new(Vec::new());
+        let state = SlowWatch::default();
+        let arg = &state as *const SlowWatch as *mut c_void;
+
+        *state.watcher.lock() =
+            Some(core.param_watch(".foo".to_string(), self_removing_watch, arg));
+
+        core.param_set(".foo".to_string(), Param::Integer(1))
+            .unwrap();
+        assert!(state.returned.load(Ordering::SeqCst));
+        assert!(state.watcher.lock().is_none());
+
+        state.entered.store(false, Ordering::SeqCst);
+        core.param_set(".foo".to_string(), Param::Integer(2))
+            .unwrap();
+        assert!(!state.entered.load(Ordering::SeqCst));
+    }
+
+    /// A subscriber that blocks until opened, so that its callback group stays busy.
+    struct Gate {
+        open: Mutex<bool>,
+        opened: Condvar,
+        entered: AtomicBool,
+        calls: AtomicUsize,
+        result: c_int,
+    }
+
+    impl Gate {
+        fn new(result: c_int) -> Gate {
+            Gate {
+                open: Mutex::new(false),
+                opened: Condvar::new(),
+                entered: AtomicBool::new(false),
+                calls: AtomicUsize::new(0),
+                result,
+            }
+        }
+
+        fn subscribe(&self, channel: &Arc<Channel>, group: &Arc<CallbackGroup>) {
+            let executor = Executor::new(None, Some(group.clone()));
+            let arg = self as *const Gate as *mut c_void;
+
+            channel
+                .insert_callback(Callback::new(gated, arg, Arc::new(executor), "gate"))
+                .unwrap();
+        }
+
+        fn wait_until_entered(&self) {
+            while !self.entered.load(Ordering::SeqCst) {
+                thread::yield_now();
+            }
+        }
+
+        fn open(&self) {
+            *self.open.lock() = true;
+            self.opened.notify_all();
+        }
+    }
+
+    unsafe extern "C" fn gated(_: ffi::MsgView, arg: *mut c_void) -> c_int {
+        let this = &*(arg as *const Gate);
+
+        this.entered.store(true, Ordering::SeqCst);
+
+        let mut open = this.open.lock();
+
+        while !*open {
+            this.opened.wait(&mut open);
+        }
+
+        this.calls.fetch_add(1, Ordering::SeqCst);
+
+        this.result
+    }
+
+    #[test]
+    fn busy_group_drops_messages_beyond_its_bound() {
+        let channel = Arc::new(Channel::new("foo".to_string(), 0, Weak::new()));
+        let gate = Arc::new(Gate::new(0));
+        let group = Arc::new(CallbackGroup::new());
+
+        gate.subscribe(&channel, &group);
+
+        let first = {
+            let channel = channel.clone();
+
+            thread::spawn(move || publish_empty(&channel))
+        };
+
+        gate.wait_until_entered();
+
+        for _ in 0..ffi::MAX_DEFERRED_CALLBACKS {
+            assert_eq!(publish_empty(&channel), 0);
+        }
+
+        assert_eq!(publish_empty(&channel), 1);
+        assert_eq!(channel.stats.snapshot().failures, 1);
+
+        gate.open();
+        assert_eq!(first.join().unwrap(), 0);
+        wait_for_hops(&gate.calls, ffi::MAX_DEFERRED_CALLBACKS + 1);
+
+        channel.remove_callback(0).unwrap();
+    }
+
+    unsafe extern "C" fn record_completion(
+        sequence: u64,
+        num_failed: ffi::Index,
+        arg: *mut c_void,
+    ) -> c_int {
+        let completions = &*(arg as *const Mutex<Vec<(u64, ffi::Index)>>);
+        completions.lock().push((sequence, num_failed));
+
+        0
+    }
+
+    #[test]
+    fn on_complete_waits_for_deferred_callbacks() {
+        let channel = Arc::new(Channel::new("foo".to_string(), 0, Weak::new()));
+        let gate = Arc::new(Gate::new(1));
+        let group = Arc::new(CallbackGroup::new());
+        let completions: Mutex<Vec<(u64, ffi::Index)>> = Mutex::new(Vec::new());
+
+        gate.subscribe(&channel, &group);
+
+        let first = {
+            let channel = channel.clone();
+
+            thread::spawn(move || publish_empty(&channel))
+        };
+
+        gate.wait_until_entered();
+
+        let arg = &completions as *const _ as *mut c_void;
+        let queue = Arc::new(PublishQueue::new(8, Some(record_completion), arg));
+        let (allocator, header) = empty_message(&channel, 7);
+        queue.push(&channel, allocator, header).unwrap();
+
+        while group.num_deferred() == 0 {
+            thread::yield_now();
+        }
+
+        assert!(completions.lock().is_empty());
+
+        gate.open();
+        assert_eq!(first.join().unwrap(), 1);
+        queue.wait_until_empty();
+
+        assert_eq!(*completions.lock(), vec![(7, 1)]);
+        assert_eq!(channel.stats.snapshot().failures, 2);
+
+        channel.remove_callback(0).unwrap();
+    }
+
+    #[test]
+    fn num_bytes_counts_message_not_segments() {
+        let channel = Arc::new(Channel::new("foo".to_string(), 0, Weak::new()));
+        let mut allocator = CacheAlignedAllocator::new(
+            channel.segment_pool().clone(),
+            alloc::DEFAULT_FIRST_SEGMENT_WORDS,
+        );
+
+        {
+            let mut message = capnp::message::Builder::new(&mut allocator);
+            message.set_root(&[7u8; 100][..]).unwrap();
+        }
+
+        let (_, header) = empty_message(&channel, 0);
+        channel.publish(allocator, header);
+
+        // a root pointer, then 100 bytes rounded up to 13 words
+        let stats = channel.stats();
+        assert_eq!(stats.channel.bytes, 14 * 8);
+        assert_eq!(stats.as_ffi().num_bytes, 14 * 8);
+    }
+
+    /// A message with a single text field, in the form generated by capnpc-rust.
+    mod chat {
+        use capnp::{
+            private::layout,
+            traits::{FromPointerBuilder, FromPointerReader, HasTypeId, SetPointerBuilder},
+            Result,
+        };
+
+        pub const TYPE_ID: u64 = 0xe5c1_9b1f_2a7d_4e83;
+
+        pub struct Owned;
+
+        impl<'a> capnp::traits::Owned<'a> for Owned {
+            type Reader = Reader<'a>;
+            type Builder = Builder<'a>;
+        }
+
+        pub struct Reader<'a> {
+            reader: layout::StructReader<'a>,
+        }
+
+        impl<'a> FromPointerReader<'a> for Reader<'a> {
+            fn get_from_pointer(reader: &layout::PointerReader<'a>) -> Result<Reader<'a>> {
+                Ok(Reader {
+                    reader: reader.get_struct(::std::ptr::null())?,
+                })
+            }
+        }
+
+        impl<'a, 'b> SetPointerBuilder<Builder<'b>> for Reader<'a> {
+            fn set_pointer_builder<'c>(
+                builder: layout::PointerBuilder<'c>,
+                value: Reader<'a>,
+                canonicalize: bool,
+            ) -> Result<()> {
+                builder.set_struct(&value.reader, canonicalize)
+            }
+        }
+
+        impl<'a> Reader<'a> {
+            pub fn get_text(&self) -> Result<&'a str> {
+                self.reader
+                    .get_pointer_field(0)
+                    .get_text(::std::ptr::null(), 0)
+            }
+        }
+
+        pub struct Builder<'a> {
+            builder: layout::StructBuilder<'a>,
+        }
+
+        impl<'a> HasTypeId for Builder<'a> {
+            fn type_id() -> u64 {
+                TYPE_ID
+            }
+        }
+
+        impl<'a> FromPointerBuilder<'a> for Builder<'a> {
+            fn init_pointer(builder: layout::PointerBuilder<'a>, _size: u32) -> Builder<'a> {
+                Builder {
+                    builder: builder.init_struct(STRUCT_SIZE),
+                }
+            }
+
+            fn get_from_pointer(builder: layout::PointerBuilder<'a>) -> Result<Builder<'a>> {
+                Ok(Builder {
+                    builder: builder.get_struct(STRUCT_SIZE, ::std::ptr::null())?,
+                })
+            }
+        }
+
+        impl<'a> Builder<'a> {
+            pub fn set_text(&mut self, value: &str) {
+                self.builder.get_pointer_field(0).set_text(value);
+            }
+        }
+
+        const STRUCT_SIZE: layout::StructSize = layout::StructSize {
+            data: 0,
+            pointers: 1,
+        };
+    }
+
+    /// A node that hands its core to the test that created it, and panics in run and stop.
+    struct Probe {
+        name: String,
+    }
+
+    thread_local! {
+        static PROBE_CORE: Cell<Option<sdk::Core>> = const { Cell::new(None) };
+        static PROBES_DROPPED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    impl sdk::Node for Probe {
+        type Error = String;
+
+        const TYPE: &'static str = "test/probe";
+
+        fn create(core: sdk::Core, name: &str) -> Result<Probe, String> {
+            if name == "panics_in_create" {
+                panic!("probe panicked in create");
+            }
+
+            PROBE_CORE.with(|c| c.set(Some(core)));
+
+            Ok(Probe {
+                name: name.to_string(),
+            })
+        }
+
+        fn run(&self) -> Result<(), String> {
+            panic!("{} panicked in run", self.name)
+        }
+
+        fn stop(&self) -> Result<(), String> {
+            panic!("{} panicked in stop", self.name)
+        }
+    }
+
+    impl Drop for Probe {
+        fn drop(&mut self) {
+            PROBES_DROPPED.with(|d| d.set(d.get() + 1));
+        }
+    }
+
+    srm::srm_export_node!(Probe);
+
+    /// Starts a Probe through its exported vtable, returning the core it was created with.
+    fn start_probe(core: &Arc<StaticCore>, name: &str) -> Result<sdk::Core, NodeError> {
+        let plugin = NodePlugin::from_get_vtbl(srm_Node_get_vtbl, PathBuf::from("probe")).unwrap();
+        start_node(
+            core,
+            name.to_string(),
+            Arc::new(plugin),
+            &NodeOptions::default(),
+        )?;
+
+        Ok(PROBE_CORE.with(Cell::take).unwrap())
+    }
+
+    fn wait_until<F: Fn() -> bool>(condition: F) {
+        let start = Instant::now();
+
+        while !condition() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn failures(core: &StaticCore, topic: &str) -> u64 {
+        core.topic_stats()
+            .into_iter()
+            .find(|(name, _)| name == topic)
+            .map_or(0, |(_, stats)| stats.channel.failures)
+    }
+
+    #[test]
+    fn exported_node_is_created_and_destroyed() {
+        let core = Arc::new(StaticCore::new(Vec::new()));
+        start_probe(&core, "probe").unwrap();
+
+        let interface = core.nodes.write().remove("probe").unwrap();
+        assert_eq!(interface.node().state(), NodeState::Created);
+
+        let dropped = PROBES_DROPPED.with(Cell::get);
+        mem::drop(interface);
+        assert_eq!(PROBES_DROPPED.with(Cell::get), dropped + 1);
+    }
+
+    #[test]
+    fn node_panics_are_reported_as_errors() {
+        let core = Arc::new(StaticCore::new(Vec::new()));
+
+        match start_probe(&core, "panics_in_create") {
+            Err(NodeError::Start(e)) => {
+                assert!(e.to_string().contains("probe panicked in create"), "{}", e)
+            }
+            r => panic!("expected Start, got {:?}", r),
+        }
+        assert!(core.nodes.read().get("panics_in_create").is_none());
+
+        start_probe(&core, "probe").unwrap();
+        let node = core.nodes.read()["probe"].node().clone();
+
+        match node.run() {
+            Err(e) => assert!(e.to_string().contains("probe panicked in run"), "{}", e),
+            Ok(()) => panic!("expected run to fail"),
+        }
+        assert_eq!(node.state(), NodeState::Failed);
+
+        match node.stop() {
+            Err(e) => assert!(e.to_string().contains("probe panicked in stop"), "{}", e),
+            Ok(()) => panic!("expected stop to fail"),
+        }
+    }
+
+    #[test]
+    fn build_panic_is_resumed_without_publishing() {
+        let core = Arc::new(StaticCore::new(Vec::new()));
+        let node = start_probe(&core, "probe").unwrap();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let _subscriber = {
+            let received = received.clone();
+
+            node.subscribe::<chat::Owned, _>("/chat", move |msg| {
+                received.lock().push(msg.get_text()?.to_string());
+
+                Ok(())
+            })
+            .unwrap()
+        };
+        let mut publisher = node.advertise::<chat::Owned>("/chat").unwrap();
+
+        let payload = panic::catch_unwind(AssertUnwindSafe(|| {
+            publisher.publish(|_| panic!("build panicked"))
+        }))
+        .unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"build panicked"));
+
+        publisher.publish(|mut msg| msg.set_text("hello")).unwrap();
+        wait_until(|| !received.lock().is_empty());
+
+        assert_eq!(*received.lock(), ["hello"]);
+    }
+
+    #[test]
+    fn subscriber_panic_is_counted_as_failure() {
+        let core = Arc::new(StaticCore::new(Vec::new()));
+        let node = start_probe(&core, "probe").unwrap();
+
+        let _subscriber = node
+            .subscribe::<chat::Owned, _>("/chat", |_| panic!("callback panicked"))
+            .unwrap();
+        let mut publisher = node.advertise::<chat::Owned>("/chat").unwrap();
+
+        publisher.publish(|mut msg| msg.set_text("hello")).unwrap();
+        wait_until(|| failures(&core, "/chat") == 1);
+
+        // the core outlives the panic and keeps delivering
+        publisher.publish(|mut msg| msg.set_text("hello")).unwrap();
+        wait_until(|| failures(&core, "/chat") == 2);
+    }
+}
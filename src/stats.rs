@@ -0,0 +1,225 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use super::ffi::NUM_LATENCY_BUCKETS;
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Upper bounds of the callback duration histogram buckets, in nanoseconds. The last bucket is
+/// unbounded.
+pub const LATENCY_BUCKET_BOUNDS: [u64; NUM_LATENCY_BUCKETS - 1] = [
+    1_000,
+    10_000,
+    100_000,
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+];
+
+/// Counters for messages published on a channel.
+///
+/// Bytes are counted as the length of each message, excluding unused space in its segments.
+pub struct ChannelStats {
+    created: Instant,
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    failures: AtomicU64,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChannelStatsSnapshot {
+    pub messages: u64,
+    pub bytes: u64,
+    pub failures: u64,
+    pub rate: f64, // messages per second since the channel was created
+}
+
+impl ChannelStats {
+    pub fn new() -> ChannelStats {
+        ChannelStats {
+            created: Instant::now(),
+            messages: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, num_bytes: usize, num_failures: usize) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(num_bytes as u64, Ordering::Relaxed);
+        self.failures
+            .fetch_add(num_failures as u64, Ordering::Relaxed);
+    }
+
+    /// Counts failures of callbacks that were invoked after their message was recorded.
+    pub fn record_failures(&self, num_failures: usize) {
+        self.failures
+            .fetch_add(num_failures as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ChannelStatsSnapshot {
+        let messages = self.messages.load(Ordering::Relaxed);
+        let elapsed = self.created.elapsed().as_secs_f64();
+
+        ChannelStatsSnapshot {
+            messages,
+            bytes: self.bytes.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            rate: if elapsed > 0.0 {
+                messages as f64 / elapsed
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Counters for the invocations of a subscriber callback.
+#[derive(Default)]
+pub struct CallbackStats {
+    calls: AtomicU64,
+    failures: AtomicU64,
+    total_duration: AtomicU64, // nanoseconds
+    buckets: [AtomicU64; NUM_LATENCY_BUCKETS],
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CallbackStatsSnapshot {
+    pub calls: u64,
+    pub failures: u64,
+    pub total_duration: u64,
+    pub buckets: [u64; NUM_LATENCY_BUCKETS], // not cumulative
+}
+
+impl CallbackStats {
+    pub fn record(&self, duration: Duration, failed: bool) {
+        let nanos = duration.as_nanos().min(u128::from(u64::MAX)) as u64;
+        let bucket = LATENCY_BUCKET_BOUNDS
+            .iter()
+            .position(|&b| nanos <= b)
+            .unwrap_or(NUM_LATENCY_BUCKETS - 1);
+
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.total_duration.fetch_add(nanos, Ordering::Relaxed);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+
+        if failed {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> CallbackStatsSnapshot {
+        let mut snapshot = CallbackStatsSnapshot {
+            calls: self.calls.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            total_duration: self.total_duration.load(Ordering::Relaxed),
+            buckets: [0; NUM_LATENCY_BUCKETS],
+        };
+
+        for (s, b) in snapshot.buckets.iter_mut().zip(self.buckets.iter()) {
+            *s = b.load(Ordering::Relaxed);
+        }
+
+        snapshot
+    }
+}
+
+impl CallbackStatsSnapshot {
+    /// Adds the counts of other to self.
+    pub fn merge(&mut self, other: &CallbackStatsSnapshot) {
+        self.calls += other.calls;
+        self.failures += other.failures;
+        self.total_duration += other.total_duration;
+
+        for (s, o) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *s += o;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callback_durations_are_bucketed_by_upper_bound() {
+        let stats = CallbackStats::default();
+
+        stats.record(Duration::from_nanos(0), false);
+        stats.record(Duration::from_nanos(1_000), false); // bounds are inclusive
+        stats.record(Duration::from_nanos(1_001), false);
+        stats.record(Duration::from_millis(5), true);
+        stats.record(Duration::from_secs(10), false); // beyond the last bound
+
+        let snapshot = stats.snapshot();
+
+        assert_eq!(snapshot.calls, 5);
+        assert_eq!(snapshot.failures, 1);
+        assert_eq!(
+            snapshot.total_duration,
+            1_000 + 1_001 + 5_000_000 + 10_000_000_000
+        );
+        assert_eq!(snapshot.buckets[0], 2);
+        assert_eq!(snapshot.buckets[1], 1);
+        assert_eq!(snapshot.buckets[4], 1);
+        assert_eq!(snapshot.buckets[NUM_LATENCY_BUCKETS - 1], 1);
+        assert_eq!(snapshot.buckets.iter().sum::<u64>(), snapshot.calls);
+    }
+
+    #[test]
+    fn callback_snapshots_merge() {
+        let lhs = CallbackStats::default();
+        lhs.record(Duration::from_nanos(10), false);
+
+        let rhs = CallbackStats::default();
+        rhs.record(Duration::from_nanos(20), true);
+        rhs.record(Duration::from_secs(1), false);
+
+        let mut merged = lhs.snapshot();
+        merged.merge(&rhs.snapshot());
+
+        assert_eq!(merged.calls, 3);
+        assert_eq!(merged.failures, 1);
+        assert_eq!(merged.total_duration, 1_000_000_030);
+        assert_eq!(merged.buckets[0], 2);
+        assert_eq!(merged.buckets[6], 1);
+    }
+
+    #[test]
+    fn channel_stats_count_messages_bytes_and_failures() {
+        let stats = ChannelStats::new();
+
+        stats.record(64, 0);
+        stats.record(128, 2);
+        stats.record_failures(1);
+
+        let snapshot = stats.snapshot();
+
+        assert_eq!(snapshot.messages, 2);
+        assert_eq!(snapshot.bytes, 192);
+        assert_eq!(snapshot.failures, 3);
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::{ffi, util};
+
+use std::{collections::VecDeque, slice, sync::Arc};
+
+use capnp::Word;
+use libc::{c_int, c_void};
+use log::warn;
+use parking_lot::Mutex;
+
+/// Matches messages received on several topics by their header stamps.
+///
+/// Once every topic has a message whose stamp is within slop of the others, the callback is
+/// invoked with one message from each topic, in the order the topics were given. Matched messages
+/// and all messages older than them are then discarded. A slop of zero requires exact matches.
+pub struct SyncState {
+    slop: i64,
+    queue_size: usize,
+    queues: Mutex<Vec<VecDeque<OwnedMsg>>>,
+    f: ffi::SyncCallback,
+    arg: *mut c_void,
+}
+
+impl SyncState {
+    pub fn new(
+        num_topics: usize,
+        slop: i64,
+        queue_size: usize,
+        f: ffi::SyncCallback,
+        arg: *mut c_void,
+    ) -> SyncState {
+        assert!(num_topics > 0 && slop >= 0 && queue_size > 0);
+
+        SyncState {
+            slop,
+            queue_size,
+            queues: Mutex::new((0..num_topics).map(|_| VecDeque::new()).collect()),
+            f,
+            arg,
+        }
+    }
+
+    /// Copies a message received on the index'th topic, then invokes the callback if the
+    /// message completes a match.
+    unsafe fn push(&self, index: usize, msg: &ffi::MsgView) {
+        let owned = OwnedMsg::from_ffi(msg);
+
+        let matched = {
+            let mut queues = self.queues.lock();
+
+            if queues[index].len() == self.queue_size {
+                queues[index].pop_front(); // drop the oldest
+            }
+
+            queues[index].push_back(owned);
+
+            self.take_match(&mut queues, index)
+        };
+
+        if let Some(msgs) = matched {
+            let segments: Vec<Vec<ffi::MsgSegmentView>> =
+                msgs.iter().map(OwnedMsg::segment_views).collect();
+            let views: Vec<ffi::MsgView> = msgs
+                .iter()
+                .zip(segments.iter())
+                .map(|(m, s)| m.as_view(s))
+                .collect();
+
+            match (self.f)(views.as_ptr(), views.len() as ffi::Index, self.arg) {
+                0 => (),
+                x => warn!("synchronizer callback {:p} failed with errc {}", self.f, x),
+            }
+        }
+    }
+
+    /// Looks for a match around the newest message on the index'th topic.
+    fn take_match(&self, queues: &mut [VecDeque<OwnedMsg>], index: usize) -> Option<Vec<OwnedMsg>> {
+        let pivot = queues[index].back().unwrap().stamp;
+
+        // the message closest to the pivot on each topic
+        let mut chosen = Vec::with_capacity(queues.len());
+
+        for queue in queues.iter() {
+            let (i, msg) = queue
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, m)| (m.stamp - pivot).abs())?;
+
+            chosen.push((i, msg.stamp));
+        }
+
+        let earliest = chosen.iter().map(|(_, s)| *s).min().unwrap();
+        let latest = chosen.iter().map(|(_, s)| *s).max().unwrap();
+
+        if latest - earliest > self.slop {
+            return None;
+        }
+
+        Some(
+            queues
+                .iter_mut()
+                .zip(chosen)
+                .map(|(queue, (i, _))| {
+                    queue.drain(..i);
+
+                    queue.pop_front().unwrap()
+                })
+                .collect(),
+        )
+    }
+}
+
+unsafe impl Send for SyncState {}
+
+unsafe impl Sync for SyncState {}
+
+/// Forwards messages received on one topic to a SyncState.
+pub struct SyncInput {
+    state: Arc<SyncState>,
+    index: usize,
+}
+
+impl SyncInput {
+    pub fn new(state: Arc<SyncState>, index: usize) -> SyncInput {
+        SyncInput { state, index }
+    }
+
+    /// A subscribe callback; arg must point to a SyncInput.
+    pub unsafe extern "C" fn callback(msg: ffi::MsgView, arg: *mut c_void) -> c_int {
+        let input = &*(arg as *const SyncInput);
+        input.state.push(input.index, &msg);
+
+        0
+    }
+}
+
+/// A message copied out of its publisher's segments.
+struct OwnedMsg {
+    segments: Vec<Vec<Word>>,
+    msg_type: u64,
+    stamp: i64,
+    sequence: u64,
+    publisher_id: u64,
+    publisher_name: String,
+}
+
+impl OwnedMsg {
+    unsafe fn from_ffi(msg: &ffi::MsgView) -> OwnedMsg {
+        let segments = slice::from_raw_parts(msg.segments, msg.num_segments as usize)
+            .iter()
+            .map(|s| slice::from_raw_parts(s.data, s.len as usize).to_vec())
+            .collect();
+
+        OwnedMsg {
+            segments,
+            msg_type: msg.msg_type,
+            stamp: msg.header.stamp,
+            sequence: msg.header.sequence,
+            publisher_id: msg.header.publisher_id,
+            publisher_name: util::ffi_to_str(msg.header.publisher_name)
+                .unwrap()
+                .to_string(),
+        }
+    }
+
+    fn segment_views(&self) -> Vec<ffi::MsgSegmentView> {
+        self.segments
+            .iter()
+            .map(|s| ffi::MsgSegmentView {
+                data: s.as_ptr(),
+                len: s.len() as ffi::Index,
+            })
+            .collect()
+    }
+
+    /// The returned view borrows from self and segments.
+    fn as_view(&self, segments: &[ffi::MsgSegmentView]) -> ffi::MsgView {
+        ffi::MsgView {
+            segments: segments.as_ptr(),
+            num_segments: segments.len() as ffi::Index,
+            msg_type: self.msg_type,
+            header: ffi::MsgHeader {
+                stamp: self.stamp,
+                sequence: self.sequence,
+                publisher_id: self.publisher_id,
+                publisher_name: util::str_to_ffi(&self.publisher_name),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The (topic index, stamp) of each message in each match.
+    #[derive(Default)]
+    struct Matches {
+        matches: Mutex<Vec<Vec<(u64, i64)>>>,
+    }
+
+    unsafe extern "C" fn record(
+        msgs: *const ffi::MsgView,
+        num: ffi::Index,
+        arg: *mut c_void,
+    ) -> c_int {
+        let matched = slice::from_raw_parts(msgs, num as usize)
+            .iter()
+            .map(|m| (m.header.publisher_id, m.header.stamp))
+            .collect();
+
+        (*(arg as *const Matches)).matches.lock().push(matched);
+
+        0
+    }
+
+    fn state(num_topics: usize, slop: i64, queue_size: usize, matches: &Matches) -> SyncState {
+        let arg = matches as *const Matches as *mut c_void;
+
+        SyncState::new(num_topics, slop, queue_size, record, arg)
+    }
+
+    fn push(state: &SyncState, index: usize, stamp: i64) {
+        let words = Word::allocate_zeroed_vec(1);
+        let segments = [ffi::MsgSegmentView {
+            data: words.as_ptr(),
+            len: words.len() as ffi::Index,
+        }];
+        let msg = ffi::MsgView {
+            segments: segments.as_ptr(),
+            num_segments: segments.len() as ffi::Index,
+            msg_type: 0,
+            header: ffi::MsgHeader {
+                stamp,
+                sequence: 0,
+                publisher_id: index as u64,
+                publisher_name: util::str_to_ffi("test"),
+            },
+        };
+
+        unsafe { state.push(index, &msg) };
+    }
+
+    #[test]
+    fn exact_match() {
+        let matches = Matches::default();
+        let state = state(2, 0, 10, &matches);
+
+        push(&state, 0, 10);
+        push(&state, 1, 20);
+        assert!(matches.matches.lock().is_empty());
+
+        push(&state, 1, 10);
+        assert_eq!(*matches.matches.lock(), vec![vec![(0, 10), (1, 10)]]);
+    }
+
+    #[test]
+    fn approximate_match_within_slop() {
+        let matches = Matches::default();
+        let state = state(2, 5, 10, &matches);
+
+        push(&state, 0, 100);
+        push(&state, 1, 104);
+        push(&state, 0, 200);
+        push(&state, 1, 210);
+
+        assert_eq!(*matches.matches.lock(), vec![vec![(0, 100), (1, 104)]]);
+    }
+
+    #[test]
+    fn match_is_in_topic_order() {
+        let matches = Matches::default();
+        let state = state(3, 0, 10, &matches);
+
+        push(&state, 2, 7);
+        push(&state, 0, 7);
+        push(&state, 1, 7);
+
+        assert_eq!(*matches.matches.lock(), vec![vec![(0, 7), (1, 7), (2, 7)]]);
+    }
+
+    #[test]
+    fn older_messages_are_discarded_by_a_match() {
+        let matches = Matches::default();
+        let state = state(2, 0, 10, &matches);
+
+        push(&state, 0, 1);
+        push(&state, 0, 2);
+        push(&state, 1, 2);
+        assert_eq!(matches.matches.lock().len(), 1);
+
+        // the message stamped 1 on topic 0 was older than the match
+        push(&state, 1, 1);
+        assert_eq!(matches.matches.lock().len(), 1);
+    }
+
+    #[test]
+    fn queues_are_bounded() {
+        let matches = Matches::default();
+        let state = state(2, 0, 2, &matches);
+
+        push(&state, 0, 1);
+        push(&state, 0, 2);
+        push(&state, 0, 3);
+
+        // the message stamped 1 was dropped to make room for the one stamped 3
+        push(&state, 1, 1);
+        assert!(matches.matches.lock().is_empty());
+
+        push(&state, 1, 2);
+        assert_eq!(*matches.matches.lock(), vec![vec![(0, 2), (1, 2)]]);
+    }
+}
@@ -0,0 +1,462 @@
+// Copyright 2019 Gregory Meyer
+//
+// Permission is hereby granted, free of charge, to any person
+// obtaining a copy of this software and associated documentation
+// files (the "Software"), to deal in the Software without
+// restriction, including without limitation the rights to use, copy,
+// modify, merge, publish, distribute, sublicense, and/or sell copies
+// of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be
+// included in all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND,
+// EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF
+// MERCHANTABILITY, FITNESS FOR A PARTICULAR PURPOSE AND
+// NONINFRINGEMENT. IN NO EVENT SHALL THE AUTHORS OR COPYRIGHT HOLDERS
+// BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER LIABILITY, WHETHER IN AN
+// ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM, OUT OF OR IN
+// CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::{clock::Clock, executor::Executor, ffi};
+
+use std::{
+    cell::RefCell,
+    cmp, mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use libc::c_void;
+use log::{trace, warn};
+use parking_lot::Mutex;
+
+/// Resolution of the timer wheel, in nanoseconds.
+const TICK: i64 = 1_000_000;
+
+/// Number of slots in the timer wheel. Timers that expire further than this many ticks in the
+/// future stay in their slot until the wheel has turned enough times.
+const NUM_SLOTS: i64 = 512;
+
+/// A hashed timer wheel.
+///
+/// Expired timers are invoked on their executor. Each timer is invoked at most once at a time; if a
+/// periodic timer is still running when it next expires, that expiry is skipped.
+pub struct TimerWheel {
+    clock: Arc<Clock>,
+    state: Mutex<WheelState>,
+    keep_running: AtomicBool,
+}
+
+struct WheelState {
+    slots: Vec<Vec<(i64, Arc<TimerEntry>)>>, // (deadline, timer)
+    tick: i64,                               // the next tick to be processed
+}
+
+impl TimerWheel {
+    pub fn new(clock: Arc<Clock>) -> TimerWheel {
+        let tick = clock.now() / TICK;
+
+        TimerWheel {
+            clock,
+            state: Mutex::new(WheelState {
+                slots: (0..NUM_SLOTS).map(|_| Vec::new()).collect(),
+                tick,
+            }),
+            keep_running: AtomicBool::new(true),
+        }
+    }
+
+    /// Schedules a timer to first expire one period from now.
+    pub fn insert(&self, timer: Arc<TimerEntry>) {
+        let deadline = self.clock.now() + timer.period;
+
+        self.state.lock().insert(deadline, timer);
+    }
+
+    /// Advances the wheel once per tick until stopped. In sim time, the wheel is instead advanced
+    /// by whoever sets the time.
+    pub fn run(&self) {
+        while self.keep_running.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_nanos(TICK as u64));
+
+            if !self.clock.is_sim_time() {
+                self.advance_to(self.clock.now());
+            }
+        }
+    }
+
+    pub fn stop(&self) {
+        self.keep_running.store(false, Ordering::Relaxed);
+    }
+
+    /// Invokes all timers that expire at or before now.
+    pub fn advance_to(&self, now: i64) {
+        let mut expired = Vec::new();
+
+        {
+            let mut state = self.state.lock();
+            let target = now / TICK;
+
+            // each slot need only be visited once, no matter how far the wheel turns
+            let num_ticks = cmp::min(target - state.tick + 1, NUM_SLOTS);
+
+            for tick in state.tick..state.tick + num_ticks {
+                let index = tick.rem_euclid(NUM_SLOTS) as usize;
+                let (ready, pending): (Vec<_>, Vec<_>) = mem::take(&mut state.slots[index])
+                    .into_iter()
+                    .filter(|(_, t)| !t.is_cancelled())
+                    .partition(|(d, _)| *d <= now);
+
+                state.slots[index] = pending;
+
+                for (deadline, timer) in ready.into_iter() {
+                    if !timer.oneshot {
+                        // skip any expiries that were missed entirely
+                        let num_periods = (now - deadline) / timer.period + 1;
+                        state.insert(deadline + num_periods * timer.period, timer.clone());
+                    }
+
+                    expired.push((deadline, timer));
+                }
+            }
+
+            state.tick = cmp::max(state.tick, target + 1);
+        }
+
+        for (deadline, timer) in expired.into_iter() {
+            let executor = timer.executor.clone();
+            let f = timer.f;
+
+            if !executor.spawn(move || timer.fire(deadline)) {
+                trace!("timer {:p} callback group is backed up, skipping expiry", f);
+            }
+        }
+    }
+
+    /// Moves the wheel to now without invoking any timers, then reschedules all timers to expire
+    /// one period from now. Used when sim time starts or jumps backwards.
+    pub fn reset_to(&self, now: i64) {
+        let mut state = self.state.lock();
+
+        let timers: Vec<_> = state
+            .slots
+            .iter_mut()
+            .flat_map(|s| s.drain(..))
+            .filter(|(_, t)| !t.is_cancelled())
+            .collect();
+
+        state.tick = now / TICK;
+
+        for (_, timer) in timers.into_iter() {
+            state.insert(now + timer.period, timer);
+        }
+    }
+}
+
+impl WheelState {
+    fn insert(&mut self, deadline: i64, timer: Arc<TimerEntry>) {
+        let tick = cmp::max(deadline / TICK, self.tick);
+        let index = tick.rem_euclid(NUM_SLOTS) as usize;
+
+        self.slots[index].push((deadline, timer));
+    }
+}
+
+/// A callback registered with a TimerWheel.
+pub struct TimerEntry {
+    period: i64,
+    oneshot: bool,
+    f: ffi::TimerCallback,
+    arg: *mut c_void,
+    executor: Arc<Executor>,
+    cancelled: AtomicBool,
+    running: Mutex<()>, // held while the callback is running
+}
+
+thread_local! {
+    // timers whose callbacks are running on this thread, innermost last. a callback that
+    // publishes may run other callbacks, including other timers, before it returns
+    static FIRING: RefCell<Vec<*const TimerEntry>> = const { RefCell::new(Vec::new()) };
+}
+
+impl TimerEntry {
+    /// period must be positive.
+    pub fn new(
+        period: i64,
+        oneshot: bool,
+        f: ffi::TimerCallback,
+        arg: *mut c_void,
+        executor: Arc<Executor>,
+    ) -> TimerEntry {
+        assert!(period > 0);
+
+        TimerEntry {
+            period,
+            oneshot,
+            f,
+            arg,
+            executor,
+            cancelled: AtomicBool::new(false),
+            running: Mutex::new(()),
+        }
+    }
+
+    pub fn period(&self) -> i64 {
+        self.period
+    }
+
+    /// Prevents this timer from being invoked again. Blocks while the callback is running,
+    /// unless called from within the callback itself.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+
+        if self.is_firing_on_this_thread() {
+            return;
+        }
+
+        // wait for a running callback to finish
+        mem::drop(self.running.lock());
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn is_firing_on_this_thread(&self) -> bool {
+        FIRING.with(|f| f.borrow().contains(&(self as *const TimerEntry)))
+    }
+
+    fn fire(&self, deadline: i64) {
+        let _running = match self.running.try_lock() {
+            Some(r) => r,
+            None => {
+                trace!("timer {:p} still running, skipping expiry", self.f);
+
+                return;
+            }
+        };
+
+        if self.is_cancelled() {
+            return;
+        }
+
+        FIRING.with(|f| f.borrow_mut().push(self as *const TimerEntry));
+        let result = unsafe { (self.f)(deadline, self.arg) };
+        FIRING.with(|f| f.borrow_mut().pop());
+
+        match result {
+            0 => (),
+            x => warn!("timer {:p} failed with errc {}", self.f, x),
+        }
+    }
+}
+
+unsafe impl Send for TimerEntry {}
+
+unsafe impl Sync for TimerEntry {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{sync::atomic::AtomicUsize, time::Instant};
+
+    use libc::c_int;
+
+    const MS: i64 = 1_000_000;
+
+    /// Deadlines that a timer was invoked with.
+    #[derive(Default)]
+    struct Fired {
+        deadlines: Mutex<Vec<i64>>,
+    }
+
+    impl Fired {
+        fn arg(&self) -> *mut c_void {
+            self as *const Fired as *mut c_void
+        }
+
+        /// Waits for at least n invocations, then returns all of them in order.
+        fn wait_for(&self, n: usize) -> Vec<i64> {
+            let start = Instant::now();
+
+            while self.deadlines.lock().len() < n {
+                assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            let mut deadlines = self.deadlines.lock().clone();
+            deadlines.sort();
+
+            deadlines
+        }
+    }
+
+    unsafe extern "C" fn record(deadline: i64, arg: *mut c_void) -> c_int {
+        (*(arg as *const Fired)).deadlines.lock().push(deadline);
+
+        0
+    }
+
+    fn sim_wheel() -> TimerWheel {
+        let clock = Arc::new(Clock::new());
+        clock.enable_sim_time();
+
+        TimerWheel::new(clock)
+    }
+
+    fn entry(
+        period: i64,
+        oneshot: bool,
+        f: ffi::TimerCallback,
+        arg: *mut c_void,
+    ) -> Arc<TimerEntry> {
+        Arc::new(TimerEntry::new(
+            period,
+            oneshot,
+            f,
+            arg,
+            Arc::new(Executor::default()),
+        ))
+    }
+
+    #[test]
+    fn periodic_timer_skips_missed_expiries() {
+        let wheel = sim_wheel();
+        let fired = Fired::default();
+
+        wheel.insert(entry(10 * MS, false, record, fired.arg()));
+
+        wheel.advance_to(5 * MS);
+        wheel.advance_to(10 * MS);
+        assert_eq!(fired.wait_for(1), vec![10 * MS]);
+
+        // the expiry at 30ms is missed entirely
+        wheel.advance_to(35 * MS);
+        wheel.advance_to(40 * MS);
+        assert_eq!(fired.wait_for(3), vec![10 * MS, 20 * MS, 40 * MS]);
+    }
+
+    #[test]
+    fn oneshot_timer_fires_once() {
+        let wheel = sim_wheel();
+        let fired = Fired::default();
+
+        wheel.insert(entry(3 * MS, true, record, fired.arg()));
+
+        wheel.advance_to(3 * MS);
+        assert_eq!(fired.wait_for(1), vec![3 * MS]);
+
+        wheel.advance_to(10 * MS);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(fired.wait_for(1), vec![3 * MS]);
+    }
+
+    #[test]
+    fn timer_beyond_one_turn_waits_for_its_deadline() {
+        let wheel = sim_wheel();
+        let fired = Fired::default();
+
+        let period = (NUM_SLOTS + 3) * TICK;
+        wheel.insert(entry(period, true, record, fired.arg()));
+
+        // the timer's slot is visited after 3 ticks, but its deadline is a full turn later
+        wheel.advance_to(3 * TICK);
+        thread::sleep(Duration::from_millis(10));
+        assert!(fired.deadlines.lock().is_empty());
+
+        wheel.advance_to(period);
+        assert_eq!(fired.wait_for(1), vec![period]);
+    }
+
+    #[test]
+    fn cancelled_timer_does_not_fire() {
+        let wheel = sim_wheel();
+        let fired = Fired::default();
+        let timer = entry(MS, false, record, fired.arg());
+
+        wheel.insert(timer.clone());
+        timer.cancel();
+
+        wheel.advance_to(10 * MS);
+        thread::sleep(Duration::from_millis(10));
+        assert!(fired.deadlines.lock().is_empty());
+    }
+
+    #[test]
+    fn reset_reschedules_from_now() {
+        let wheel = sim_wheel();
+        let fired = Fired::default();
+
+        wheel.insert(entry(10 * MS, false, record, fired.arg()));
+
+        wheel.reset_to(100 * MS);
+        wheel.advance_to(105 * MS);
+        wheel.advance_to(110 * MS);
+        assert_eq!(fired.wait_for(1), vec![110 * MS]);
+    }
+
+    /// A timer whose callback tries to fire or cancel itself.
+    struct Reentrant {
+        timer: Mutex<Option<Arc<TimerEntry>>>,
+        count: AtomicUsize,
+        cancel: bool,
+    }
+
+    unsafe extern "C" fn reenter(deadline: i64, arg: *mut c_void) -> c_int {
+        let this = &*(arg as *const Reentrant);
+        let timer = this.timer.lock().clone().unwrap();
+
+        this.count.fetch_add(1, Ordering::SeqCst);
+
+        if this.cancel {
+            timer.cancel();
+        } else {
+            timer.fire(deadline);
+        }
+
+        0
+    }
+
+    fn reentrant(cancel: bool) -> (Box<Reentrant>, Arc<TimerEntry>) {
+        let this = Box::new(Reentrant {
+            timer: Mutex::new(None),
+            count: AtomicUsize::new(0),
+            cancel,
+        });
+
+        let arg = &*this as *const Reentrant as *mut c_void;
+        let timer = entry(MS, false, reenter, arg);
+        *this.timer.lock() = Some(timer.clone());
+
+        (this, timer)
+    }
+
+    #[test]
+    fn timer_does_not_fire_within_itself() {
+        let (this, timer) = reentrant(false);
+
+        timer.fire(MS);
+
+        assert_eq!(this.count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn timer_can_cancel_itself() {
+        let (this, timer) = reentrant(true);
+
+        timer.fire(MS);
+        timer.fire(2 * MS);
+
+        assert_eq!(this.count.load(Ordering::SeqCst), 1);
+        assert!(timer.is_cancelled());
+        assert!(!timer.is_firing_on_this_thread());
+    }
+}
@@ -20,7 +20,7 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use super::*;
+use crate::ffi;
 
 use std::{slice, str};
 
@@ -33,9 +33,13 @@ use libc::{c_char, ptrdiff_t};
 /// Similar to `std::slice::from_raw_parts`, there is no guarantee that the provided slice
 /// is valid for as many bytes as it claims to be, nor is the inferred lifetime accurate.
 ///
+/// Returns `None` if `raw.data` is null, in which case `raw.len` must be zero. A non-null
+/// `raw` with a length of zero is a valid empty string.
+///
 /// # Panics
 ///
-/// Panics if `raw` does not point to a valid UTF-8 sequence.
+/// Panics if `raw.len` is negative, if `raw.data` is null but `raw.len` is nonzero, or if
+/// `raw` does not point to a valid UTF-8 sequence.
 pub unsafe fn ffi_to_str<'a>(raw: ffi::StrView) -> Option<&'a str> {
     if raw.data.is_null() {
         assert!(raw.len == 0);
@@ -43,7 +47,7 @@ pub unsafe fn ffi_to_str<'a>(raw: ffi::StrView) -> Option<&'a str> {
         return None;
     }
 
-    assert!(raw.len > 0);
+    assert!(raw.len >= 0);
 
     let as_slice: &'a [u8] = slice::from_raw_parts(raw.data as *const u8, raw.len as usize);
 